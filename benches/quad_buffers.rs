@@ -0,0 +1,187 @@
+// Uploads the same number of quads frame after frame and reports how much is
+// allocated per frame: GPU buffers, staging belt chunks and heap allocations.
+// Run with `cargo bench --bench quad_buffers`; it needs a GPU adapter but no
+// window.
+
+use dynamo_lib::geometry::quad::Quad;
+use dynamo_lib::geometry::{Geometry, QuadBuffers};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::executor::block_on;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+// Counts heap allocations, the ones wgpu makes included.
+struct CountingAllocator;
+
+static HEAP_ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        HEAP_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        HEAP_ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Counts the staging chunks the belt creates. wgpu doesn't expose that, but
+// it opens a span named after the message each time it makes one.
+struct ChunkCounter;
+
+const CHUNK_SPAN: &str = "Creating chunk of size {}";
+
+static CHUNKS: AtomicU64 = AtomicU64::new(0);
+static NEXT_SPAN: AtomicU64 = AtomicU64::new(1);
+
+impl Subscriber for ChunkCounter {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        if span.metadata().name() == CHUNK_SPAN {
+            CHUNKS.fetch_add(1, Ordering::Relaxed);
+        }
+        Id::from_u64(NEXT_SPAN.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _: &Id, _: &Record) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event) {}
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+const FRAMES: u32 = 120;
+
+struct Frame {
+    buffers: u64,
+    chunks: u64,
+    heap: u64,
+    time: Duration,
+}
+
+fn main() {
+    tracing::subscriber::set_global_default(ChunkCounter).expect("Install chunk counter");
+
+    let (device, queue) = match block_on(request_device()) {
+        Some(device) => device,
+        None => {
+            println!("no GPU adapter found, skipping");
+            return;
+        }
+    };
+
+    println!(
+        "{:>8} {:>14} {:>14} {:>13} {:>13} {:>16} {:>16} {:>12}",
+        "quads",
+        "first buffers",
+        "later buffers",
+        "first chunks",
+        "later chunks",
+        "first heap",
+        "later heap",
+        "later time"
+    );
+    for &count in &[10_000, 100_000] {
+        let frames = run(&device, &queue, count);
+        let (first, later) = frames.split_first().unwrap();
+        let later_frames = later.len() as u64;
+        let later_buffers: u64 = later.iter().map(|frame| frame.buffers).sum();
+        let later_chunks: u64 = later.iter().map(|frame| frame.chunks).sum();
+        let later_heap: u64 = later.iter().map(|frame| frame.heap).sum();
+        let later_time: Duration = later.iter().map(|frame| frame.time).sum();
+        println!(
+            "{:>8} {:>14} {:>14.2} {:>13} {:>13.2} {:>16} {:>16.2} {:>12.2?}",
+            count,
+            first.buffers,
+            later_buffers as f64 / later_frames as f64,
+            first.chunks,
+            later_chunks as f64 / later_frames as f64,
+            first.heap,
+            later_heap as f64 / later_frames as f64,
+            later_time / later_frames as u32,
+        );
+    }
+}
+
+async fn request_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::Default,
+            compatible_surface: None,
+        })
+        .await?;
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::default(),
+                shader_validation: true,
+            },
+            None,
+        )
+        .await
+        .ok()
+}
+
+// Pushes, uploads and submits `count` quads each frame the way the renderer
+// does, and counts what each frame allocates.
+fn run(device: &wgpu::Device, queue: &wgpu::Queue, count: u32) -> Vec<Frame> {
+    let mut geometry = Geometry::new();
+    let mut buffers = QuadBuffers::new(device);
+    let mut staging_belt = wgpu::util::StagingBelt::new(1024);
+
+    (0..FRAMES)
+        .map(|frame| {
+            let buffers_before = buffers.allocations();
+            let chunks_before = CHUNKS.load(Ordering::Relaxed);
+            let heap_before = HEAP_ALLOCATIONS.load(Ordering::Relaxed);
+            let start = Instant::now();
+
+            geometry.reset();
+            for i in 0..count {
+                let x = (i % 1000) as f32 / 500.0 - 1.0 + frame as f32 * 0.001;
+                let y = (i / 1000) as f32 / 500.0 - 1.0;
+                geometry.push_quad(&Quad::new((x, y).into(), (0.001, 0.001).into()));
+            }
+
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Benchmark Encoder"),
+            });
+            geometry.upload(device, &mut encoder, &mut staging_belt, &mut buffers);
+            staging_belt.finish();
+            queue.submit(std::iter::once(encoder.finish()));
+
+            let recall = staging_belt.recall();
+            device.poll(wgpu::Maintain::Wait);
+            block_on(recall);
+
+            Frame {
+                buffers: buffers.allocations() - buffers_before,
+                chunks: CHUNKS.load(Ordering::Relaxed) - chunks_before,
+                heap: HEAP_ALLOCATIONS.load(Ordering::Relaxed) - heap_before,
+                time: start.elapsed(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,11 @@
+#version 450
+
+layout(location=0) in vec2 aPosition;
+layout(location=1) in vec4 aColor;
+
+layout(location=0) out vec4 vColor;
+
+void main() {
+    gl_Position = vec4(aPosition, 0, 1);
+    vColor = aColor;
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location=0) in vec2 vTexCoord;
+layout(location=1) in vec4 vColor;
+layout(location=2) in vec4 vOutlineColor;
+layout(location=3) in float vOutlineWidth;
+
+layout(location=0) out vec4 fColor;
+
+layout(set=0, binding=0) uniform texture2D tDistance;
+layout(set=0, binding=1) uniform sampler sDistance;
+
+// Distances are stored with 0.5 on the glyph's edge, higher inside it. The
+// edge is smoothed over about a pixel whatever the scale.
+void main() {
+    float field = texture(sampler2D(tDistance, sDistance), vTexCoord).r;
+    float smoothing = max(fwidth(field) * 0.7, 0.0001);
+
+    float fill = smoothstep(0.5 - smoothing, 0.5 + smoothing, field);
+    float edge = 0.5 - vOutlineWidth;
+    float shape = smoothstep(edge - smoothing, edge + smoothing, field);
+
+    vec4 color = mix(vOutlineColor, vColor, fill);
+    fColor = vec4(color.rgb, color.a * shape);
+}
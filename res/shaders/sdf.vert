@@ -0,0 +1,20 @@
+#version 450
+
+layout(location=0) in vec2 aPosition;
+layout(location=1) in vec2 aTexCoord;
+layout(location=2) in vec4 aColor;
+layout(location=3) in vec4 aOutlineColor;
+layout(location=4) in float aOutlineWidth;
+
+layout(location=0) out vec2 vTexCoord;
+layout(location=1) out vec4 vColor;
+layout(location=2) out vec4 vOutlineColor;
+layout(location=3) out float vOutlineWidth;
+
+void main() {
+    gl_Position = vec4(aPosition, 0, 1);
+    vTexCoord = aTexCoord;
+    vColor = aColor;
+    vOutlineColor = aOutlineColor;
+    vOutlineWidth = aOutlineWidth;
+}
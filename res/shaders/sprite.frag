@@ -0,0 +1,13 @@
+#version 450
+
+layout(location=0) in vec2 vTexCoord;
+layout(location=1) in vec4 vColor;
+
+layout(location=0) out vec4 fColor;
+
+layout(set=0, binding=0) uniform texture2D tSprite;
+layout(set=0, binding=1) uniform sampler sSprite;
+
+void main() {
+    fColor = texture(sampler2D(tSprite, sSprite), vTexCoord) * vColor;
+}
@@ -1,9 +1,9 @@
 #version 450
 
-layout(location=0) in vec2 vTexCoord;
+layout(location=0) in vec4 vColor;
 
 layout(location=0) out vec4 fColor;
 
 void main() {
-    fColor = vec4(1);
-}
\ No newline at end of file
+    fColor = vColor;
+}
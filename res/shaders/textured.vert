@@ -1,11 +1,17 @@
 #version 450
 
 layout(location=0) in vec2 aPosition;
-layout(location=1) in vec2 aTexCoord;
+layout(location=1) in vec4 aColor;
 
-layout(location=0) out vec2 vTexCoord;
+layout(location=0) out vec4 vColor;
+
+// Quads are given in world space, see `Camera`.
+layout(set=0, binding=0) uniform Camera {
+    vec2 uCameraPosition;
+    float uCameraZoom;
+};
 
 void main() {
-    gl_Position = vec4(aPosition, 0, 1);
-    vTexCoord = aTexCoord;
-}
\ No newline at end of file
+    gl_Position = vec4((aPosition - uCameraPosition) * uCameraZoom, 0, 1);
+    vColor = aColor;
+}
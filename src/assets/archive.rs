@@ -0,0 +1,378 @@
+use crate::error::{Error, Result};
+
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+// Layout: magic, version, index length, the index, then each file's data.
+// The index lists every file's path, where its data starts relative to the
+// end of the index, its stored length, its original length and whether it's
+// compressed.
+const MAGIC: &[u8; 4] = b"DPAK";
+const VERSION: u32 = 1;
+const COMPRESSION_LEVEL: u8 = 8;
+// Deflate can't shrink data by more than about this much, so compressed
+// entries claiming a larger original length are corrupt.
+const MAX_COMPRESSION_RATIO: u64 = 1032;
+
+struct Entry {
+    offset: u64,
+    stored_length: u64,
+    length: u64,
+    compressed: bool,
+}
+
+// A packed asset tree that the asset manager can mount in place of a
+// directory. Files are read and decompressed when they're loaded.
+pub struct Archive {
+    file: Mutex<File>,
+    data_start: u64,
+    entries: HashMap<String, Entry>,
+}
+
+impl Archive {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = File::open(path)?;
+
+        let mut header = [0; 12];
+        file.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not an asset archive"));
+        }
+        if u32_at(&header, 4) != VERSION {
+            return Err(invalid("unsupported archive version"));
+        }
+
+        // Lengths are checked against the file before anything is allocated
+        // for them, so a corrupt archive can't ask for more than it holds.
+        let file_length = file.metadata()?.len();
+        let data_start = header.len() as u64 + u32_at(&header, 8) as u64;
+        if data_start > file_length {
+            return Err(invalid("archive index is truncated"));
+        }
+        let mut index = vec![0; (data_start - header.len() as u64) as usize];
+        file.read_exact(&mut index)?;
+        let entries = parse_index(&index)?;
+
+        let data_length = file_length - data_start;
+        let past_end = entries.iter().find(|(_, entry)| {
+            entry
+                .offset
+                .checked_add(entry.stored_length)
+                .filter(|&end| end <= data_length)
+                .is_none()
+        });
+        if let Some((name, _)) = past_end {
+            return Err(invalid(&format!("{} is past the end of the archive", name)));
+        }
+        // The original length is what reading an entry allocates.
+        let bad_length = entries.iter().find(|(_, entry)| {
+            if entry.compressed {
+                entry.length > entry.stored_length.saturating_mul(MAX_COMPRESSION_RATIO)
+            } else {
+                entry.length != entry.stored_length
+            }
+        });
+        if let Some((name, _)) = bad_length {
+            return Err(invalid(&format!("{} has an impossible length", name)));
+        }
+
+        Ok(Self {
+            file: Mutex::new(file),
+            data_start,
+            entries,
+        })
+    }
+
+    pub fn contains<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.entries.contains_key(&archive_path(path.as_ref()))
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(|path| path.as_str())
+    }
+
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Result<Vec<u8>> {
+        let name = archive_path(path.as_ref());
+        let entry = self.entries.get(&name).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} isn't in the archive", name),
+            )
+        })?;
+
+        let mut stored = vec![0; entry.stored_length as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(self.data_start + entry.offset))?;
+            file.read_exact(&mut stored)?;
+        }
+
+        if !entry.compressed {
+            return Ok(stored);
+        }
+        inflate(&stored, entry.length as usize)
+            .ok_or_else(|| invalid(&format!("{} is corrupt", name)))
+    }
+}
+
+// Packs every file under `directory` into an archive at `output`. Suits a
+// game's build.rs as well as the `dynamo_pack` tool.
+pub fn pack_directory<P: AsRef<Path>, Q: AsRef<Path>>(directory: P, output: Q) -> Result<()> {
+    let directory = directory.as_ref();
+    let mut files = Vec::new();
+    collect_files(directory, &mut files)?;
+    files.sort();
+
+    let mut index = Vec::new();
+    let mut data = Vec::new();
+    for file in files {
+        let relative = file.strip_prefix(directory).unwrap_or(&file);
+        let name = archive_path(relative);
+        let contents = std::fs::read(&file)?;
+        let length = contents.len();
+
+        // Formats like PNG and OGG are already compressed, store those as is.
+        let compressed = miniz_oxide::deflate::compress_to_vec(&contents, COMPRESSION_LEVEL);
+        let (stored, is_compressed) = if compressed.len() < length {
+            (compressed, true)
+        } else {
+            (contents, false)
+        };
+
+        let name_length = u16::try_from(name.len())
+            .map_err(|_| invalid(&format!("{} has too long a path", name)))?;
+        index.extend_from_slice(&name_length.to_le_bytes());
+        index.extend_from_slice(name.as_bytes());
+        index.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        index.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+        index.extend_from_slice(&(length as u64).to_le_bytes());
+        index.push(is_compressed as u8);
+        data.extend_from_slice(&stored);
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(index.len() as u32).to_le_bytes())?;
+    writer.write_all(&index)?;
+    writer.write_all(&data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Inflates `stored` into exactly `length` bytes, or None if it inflates to
+// anything else. The output is allocated up front, with a byte to spare to
+// catch data that's too long.
+fn inflate(stored: &[u8], length: usize) -> Option<Vec<u8>> {
+    use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide};
+    use miniz_oxide::inflate::TINFLStatus;
+
+    let mut data = vec![0; length + 1];
+    let mut decompressor = Box::<DecompressorOxide>::default();
+    let flags = inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    let (status, _, written) = decompress(&mut decompressor, stored, &mut data, 0, flags);
+    if status != TINFLStatus::Done || written != length {
+        return None;
+    }
+    data.truncate(length);
+    Some(data)
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Paths in the index always use forward slashes so archives packed on one
+// platform load on any other.
+fn archive_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn parse_index(mut index: &[u8]) -> Result<HashMap<String, Entry>> {
+    let mut entries = HashMap::new();
+    while !index.is_empty() {
+        let name_length = u16::from_le_bytes(take(&mut index, 2)?.try_into().unwrap()) as usize;
+        let name = String::from_utf8(take(&mut index, name_length)?.to_vec())
+            .map_err(|_| invalid("archive index has a path that isn't UTF-8"))?;
+        let fields = take(&mut index, 25)?;
+
+        entries.insert(
+            name,
+            Entry {
+                offset: u64_at(fields, 0),
+                stored_length: u64_at(fields, 8),
+                length: u64_at(fields, 16),
+                compressed: fields[24] != 0,
+            },
+        );
+    }
+    Ok(entries)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], length: usize) -> Result<&'a [u8]> {
+    if bytes.len() < length {
+        return Err(invalid("archive index is truncated"));
+    }
+    let (taken, rest) = bytes.split_at(length);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidArchive(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A scratch directory that's removed when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "dynamo-archive-{}-{}",
+                std::process::id(),
+                name
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Builds an archive by hand from (path, stored bytes, original length,
+    // compressed) entries laid out one after another.
+    fn archive_bytes(entries: &[(&str, &[u8], u64, bool)]) -> Vec<u8> {
+        let mut index = Vec::new();
+        let mut data = Vec::new();
+        for &(name, stored, length, compressed) in entries {
+            index.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            index.extend_from_slice(name.as_bytes());
+            index.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            index.extend_from_slice(&(stored.len() as u64).to_le_bytes());
+            index.extend_from_slice(&length.to_le_bytes());
+            index.push(compressed as u8);
+            data.extend_from_slice(stored);
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(index.len() as u32).to_le_bytes());
+        bytes.extend(index);
+        bytes.extend(data);
+        bytes
+    }
+
+    fn open_bytes(dir: &TempDir, bytes: &[u8]) -> Result<Archive> {
+        let path = dir.0.join("test.pak");
+        std::fs::write(&path, bytes).unwrap();
+        Archive::open(path)
+    }
+
+    #[test]
+    fn round_trips_a_directory() {
+        let dir = TempDir::new("round-trip");
+        let assets = dir.0.join("assets");
+        std::fs::create_dir_all(assets.join("fonts")).unwrap();
+        let text = "hello hello hello hello hello hello".repeat(20);
+        std::fs::write(assets.join("greeting.txt"), &text).unwrap();
+        std::fs::write(assets.join("fonts").join("tiny.bin"), [7, 3, 1]).unwrap();
+
+        let output = dir.0.join("assets.pak");
+        pack_directory(&assets, &output).unwrap();
+        let archive = Archive::open(&output).unwrap();
+
+        let mut paths: Vec<_> = archive.paths().collect();
+        paths.sort_unstable();
+        assert_eq!(paths, vec!["fonts/tiny.bin", "greeting.txt"]);
+        assert!(archive.contains(Path::new("fonts").join("tiny.bin")));
+        assert!(archive.entries["greeting.txt"].compressed);
+        assert!(!archive.entries["fonts/tiny.bin"].compressed);
+
+        assert_eq!(archive.read("greeting.txt").unwrap(), text.as_bytes());
+        assert_eq!(archive.read("fonts/tiny.bin").unwrap(), vec![7, 3, 1]);
+        assert!(archive.read("missing.txt").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_indexes() {
+        let dir = TempDir::new("truncated");
+        let bytes = archive_bytes(&[("a.txt", b"abc", 3, false)]);
+
+        // Cut inside the index, and an index length past the end of the file.
+        assert!(open_bytes(&dir, &bytes[..bytes.len() - 10]).is_err());
+        let mut long_index = bytes.clone();
+        long_index[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(open_bytes(&dir, &long_index).is_err());
+
+        assert!(open_bytes(&dir, &bytes).is_ok());
+    }
+
+    #[test]
+    fn rejects_entries_past_the_end() {
+        let dir = TempDir::new("past-end");
+        let bytes = archive_bytes(&[("a.txt", b"abc", 3, false)]);
+        assert!(open_bytes(&dir, &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_original_lengths() {
+        let dir = TempDir::new("lengths");
+        let original = vec![b'a'; 4096];
+        let compressed = miniz_oxide::deflate::compress_to_vec(&original, COMPRESSION_LEVEL);
+
+        // Lengths that can't be right are rejected when the archive opens.
+        let huge = compressed.len() as u64 * MAX_COMPRESSION_RATIO + 1;
+        let bytes = archive_bytes(&[("a.txt", &compressed, huge, true)]);
+        assert!(open_bytes(&dir, &bytes).is_err());
+        let bytes = archive_bytes(&[("a.txt", b"abc", 4, false)]);
+        assert!(open_bytes(&dir, &bytes).is_err());
+
+        // Ones that are only wrong are caught when the entry is read.
+        for &length in &[4095, 4097] {
+            let bytes = archive_bytes(&[("a.txt", &compressed, length, true)]);
+            let archive = open_bytes(&dir, &bytes).unwrap();
+            assert!(archive.read("a.txt").is_err(), "length {}", length);
+        }
+
+        let bytes = archive_bytes(&[("a.txt", &compressed, 4096, true)]);
+        assert_eq!(
+            open_bytes(&dir, &bytes).unwrap().read("a.txt").unwrap(),
+            original
+        );
+    }
+}
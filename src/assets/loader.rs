@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed,
+}
+
+// How far along the current batch of background loads is. A batch starts
+// with the first `load_async` after everything before it finished.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub requested: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl LoadProgress {
+    pub fn finished(&self) -> usize {
+        self.loaded + self.failed
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.finished() >= self.requested
+    }
+
+    // 0 to 1, for drawing a loading bar. 1 when nothing was requested.
+    pub fn fraction(&self) -> f32 {
+        if self.requested == 0 {
+            1.0
+        } else {
+            self.finished() as f32 / self.requested as f32
+        }
+    }
+}
+
+#[derive(Default)]
+pub(super) struct Progress {
+    requested: AtomicUsize,
+    loaded: AtomicUsize,
+    failed: AtomicUsize,
+}
+
+impl Progress {
+    pub fn snapshot(&self) -> LoadProgress {
+        // Read the finished counts first so they never run ahead of `requested`.
+        let loaded = self.loaded.load(Ordering::Acquire);
+        let failed = self.failed.load(Ordering::Acquire);
+        LoadProgress {
+            requested: self.requested.load(Ordering::Acquire),
+            loaded,
+            failed,
+        }
+    }
+
+    // Only called from the thread that requests loads, so nothing can be
+    // requested between the check and the reset.
+    pub fn request(&self) {
+        if self.snapshot().is_done() {
+            self.loaded.store(0, Ordering::Release);
+            self.failed.store(0, Ordering::Release);
+            self.requested.store(0, Ordering::Release);
+        }
+        self.requested.fetch_add(1, Ordering::AcqRel);
+    }
+
+    pub fn finish(&self, loaded: bool) {
+        let counter = if loaded { &self.loaded } else { &self.failed };
+        counter.fetch_add(1, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,484 @@
+mod archive;
+mod loader;
+mod shader;
+mod texture;
+
+pub use archive::{pack_directory, Archive};
+pub use loader::{LoadProgress, LoadState};
+pub use shader::Shader;
+pub use texture::Texture;
+
+use crate::error::{Error, Result};
+use crate::sound::clip::SoundClip;
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::executor::ThreadPool;
+use loader::Progress;
+use wgpu_glyph::ab_glyph::FontArc;
+
+// How often hot reloading checks files for changes.
+const HOT_RELOAD_INTERVAL: Duration = Duration::from_millis(250);
+
+// Anything the asset manager can build from the contents of a file.
+pub trait Asset: Send + Sync + Sized + 'static {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self>;
+
+    // For assets that need to know which file they came from.
+    fn from_file(bytes: Vec<u8>, _path: &Path) -> Result<Self> {
+        Self::from_bytes(bytes)
+    }
+}
+
+// Raw data files.
+impl Asset for Vec<u8> {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Ok(bytes)
+    }
+}
+
+impl Asset for String {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e).into())
+    }
+}
+
+impl Asset for SoundClip {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        SoundClip::from_reader(Cursor::new(bytes))
+    }
+}
+
+impl Asset for FontArc {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Ok(FontArc::try_from_vec(bytes)?)
+    }
+}
+
+struct Slot<T> {
+    path: PathBuf,
+    // `None` until a background load finishes.
+    value: RwLock<Option<Arc<T>>>,
+    failed: AtomicBool,
+    version: AtomicU64,
+}
+
+impl<T> Slot<T> {
+    fn new(path: PathBuf, value: Option<T>) -> Self {
+        Self {
+            path,
+            value: RwLock::new(value.map(Arc::new)),
+            failed: AtomicBool::new(false),
+            version: AtomicU64::new(0),
+        }
+    }
+}
+
+// Lets the cache hold slots of every asset type side by side.
+trait CachedSlot: Send + Sync {
+    fn is_alive(&self) -> bool;
+    fn reload(&self, source: &Source) -> Result<()>;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Asset> CachedSlot for Weak<Slot<T>> {
+    fn is_alive(&self) -> bool {
+        self.strong_count() > 0
+    }
+
+    fn reload(&self, source: &Source) -> Result<()> {
+        let slot = match self.upgrade() {
+            Some(slot) => slot,
+            None => return Ok(()),
+        };
+        let value = source.read::<T>(&slot.path)?;
+        *slot.value.write().unwrap() = Some(Arc::new(value));
+        slot.failed.store(false, Ordering::Release);
+        slot.version.fetch_add(1, Ordering::AcqRel);
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// A reference counted asset. Clones share the asset, which is unloaded once
+// the last handle is dropped.
+pub struct Handle<T> {
+    slot: Arc<Slot<T>>,
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            slot: self.slot.clone(),
+        }
+    }
+}
+
+impl<T> Handle<T> {
+    // Wraps an asset that wasn't loaded from a file, so it can be used
+    // wherever handles are. It's never reloaded and has no path.
+    pub fn from_value(value: T) -> Self {
+        Self {
+            slot: Arc::new(Slot::new(PathBuf::new(), Some(value))),
+        }
+    }
+
+    // Panics if the asset hasn't loaded, which can only happen to handles
+    // from `load_async`. Use `try_get` for those.
+    pub fn get(&self) -> Arc<T> {
+        match self.try_get() {
+            Some(value) => value,
+            None => panic!("{} hasn't loaded", self.path().display()),
+        }
+    }
+
+    pub fn try_get(&self) -> Option<Arc<T>> {
+        self.slot.value.read().unwrap().clone()
+    }
+
+    pub fn state(&self) -> LoadState {
+        if self.slot.failed.load(Ordering::Acquire) {
+            LoadState::Failed
+        } else if self.slot.value.read().unwrap().is_some() {
+            LoadState::Loaded
+        } else {
+            LoadState::Loading
+        }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.state() == LoadState::Loaded
+    }
+
+    // Goes up each time the asset is hot reloaded, so anything built from it,
+    // like a GPU texture, knows to rebuild.
+    pub fn version(&self) -> u64 {
+        self.slot.version.load(Ordering::Acquire)
+    }
+
+    // The path the asset was loaded from, relative to the asset root.
+    pub fn path(&self) -> &Path {
+        &self.slot.path
+    }
+
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.slot)
+    }
+
+    // The same for every clone of a handle, and not reused while any of them
+    // is alive.
+    pub(crate) fn id(&self) -> usize {
+        Arc::as_ptr(&self.slot) as usize
+    }
+}
+
+// Where assets are read from.
+#[derive(Clone)]
+enum Source {
+    Directory(PathBuf),
+    Archive(Arc<Archive>),
+}
+
+impl Source {
+    fn read<T: Asset>(&self, path: &Path) -> Result<T> {
+        let bytes = match self {
+            Source::Directory(root) => std::fs::read(root.join(path))?,
+            Source::Archive(archive) => archive.read(path)?,
+        };
+        T::from_file(bytes, path)
+    }
+
+    fn modified_time(&self, path: &Path) -> Option<SystemTime> {
+        match self {
+            Source::Directory(root) => std::fs::metadata(root.join(path))
+                .and_then(|meta| meta.modified())
+                .ok(),
+            Source::Archive(_) => None,
+        }
+    }
+}
+
+// Loads assets from files under a root directory, or from an archive mounted
+// in its place. Loading the same path as the same type again returns the
+// existing handle while any are still alive.
+pub struct AssetManager {
+    root: PathBuf,
+    archive: Option<Arc<Archive>>,
+    // Keyed by the asset's type and path. Holds weak references so the cache
+    // doesn't keep anything alive.
+    cache: HashMap<(TypeId, PathBuf), CacheEntry>,
+    hot_reload: bool,
+    last_reload_check: Option<Instant>,
+    // Started on the first background load.
+    pool: Option<ThreadPool>,
+    progress: Arc<Progress>,
+    errors: Arc<Mutex<Vec<Error>>>,
+}
+
+impl AssetManager {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: root.into(),
+            archive: None,
+            cache: HashMap::new(),
+            hot_reload: false,
+            last_reload_check: None,
+            pool: None,
+            progress: Arc::new(Progress::default()),
+            errors: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // Reads assets from `res.pak` when one ships next to the executable,
+    // otherwise from `default_root`.
+    pub fn from_default_location() -> Result<Self> {
+        let mut assets = Self::new(Self::default_root());
+        let archive = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join("res.pak")))
+            .filter(|path| path.is_file());
+        if let Some(archive) = archive {
+            assets.mount(Archive::open(archive)?);
+        }
+        Ok(assets)
+    }
+
+    // A `res` directory next to the executable when the game is shipped,
+    // then one in the working directory, then the copy build.rs makes.
+    pub fn default_root() -> PathBuf {
+        let beside_exe = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join("res")));
+        if let Some(dir) = beside_exe.filter(|dir| dir.is_dir()) {
+            return dir;
+        }
+
+        let working = PathBuf::from("res");
+        match option_env!("OUT_DIR") {
+            Some(out_dir) if !working.is_dir() => Path::new(out_dir).join("res"),
+            _ => working,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Only affects assets loaded afterwards.
+    pub fn set_root<P: Into<PathBuf>>(&mut self, root: P) {
+        self.root = root.into();
+    }
+
+    // Reads assets from the archive instead of the root directory until it's
+    // unmounted. Only affects assets loaded afterwards.
+    pub fn mount(&mut self, archive: Archive) {
+        self.archive = Some(Arc::new(archive));
+    }
+
+    pub fn unmount(&mut self) -> Option<Arc<Archive>> {
+        self.archive.take()
+    }
+
+    pub fn archive(&self) -> Option<&Archive> {
+        self.archive.as_deref()
+    }
+
+    fn source(&self) -> Source {
+        match &self.archive {
+            Some(archive) => Source::Archive(archive.clone()),
+            None => Source::Directory(self.root.clone()),
+        }
+    }
+
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.root.join(path)
+    }
+
+    // Loads on the calling thread. If `load_async` already requested the
+    // same asset the returned handle may still be loading.
+    pub fn load<T: Asset, P: AsRef<Path>>(&mut self, path: P) -> Result<Handle<T>> {
+        // A background load that failed is retried rather than returned.
+        let cached = self.get::<T, _>(path.as_ref());
+        if let Some(handle) = cached.filter(|handle| handle.state() != LoadState::Failed) {
+            return Ok(handle);
+        }
+
+        let path = path.as_ref().to_path_buf();
+        let value = self.source().read(&path).map_err(|e| Error::Asset {
+            path: path.clone(),
+            source: Box::new(e),
+        })?;
+        let slot = Arc::new(Slot::new(path, Some(value)));
+        self.insert(&slot);
+        Ok(Handle { slot })
+    }
+
+    // Returns straight away and loads the asset on a worker thread. The
+    // handle reports when it's ready, and failures are collected for
+    // `take_errors`.
+    pub fn load_async<T: Asset, P: AsRef<Path>>(&mut self, path: P) -> Result<Handle<T>> {
+        let cached = self.get::<T, _>(path.as_ref());
+        if let Some(handle) = cached.filter(|handle| handle.state() != LoadState::Failed) {
+            return Ok(handle);
+        }
+
+        if self.pool.is_none() {
+            self.pool = Some(
+                ThreadPool::builder()
+                    .name_prefix("asset-loader-")
+                    .create()?,
+            );
+        }
+
+        let path = path.as_ref().to_path_buf();
+        let source = self.source();
+        let slot = Arc::new(Slot::new(path.clone(), None));
+        self.insert(&slot);
+        self.progress.request();
+
+        let task_slot = slot.clone();
+        let progress = self.progress.clone();
+        let errors = self.errors.clone();
+        let task = async move {
+            match source.read::<T>(&task_slot.path) {
+                Ok(value) => {
+                    *task_slot.value.write().unwrap() = Some(Arc::new(value));
+                    progress.finish(true);
+                }
+                Err(e) => {
+                    task_slot.failed.store(true, Ordering::Release);
+                    errors.lock().unwrap().push(Error::Asset {
+                        path,
+                        source: Box::new(e),
+                    });
+                    progress.finish(false);
+                }
+            }
+        };
+        self.pool.as_ref().unwrap().spawn_ok(task);
+
+        Ok(Handle { slot })
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        self.progress.snapshot()
+    }
+
+    // Errors from background loads since the last call.
+    pub fn take_errors(&self) -> Vec<Error> {
+        std::mem::take(&mut *self.errors.lock().unwrap())
+    }
+
+    // The handle for an asset that's already loaded.
+    pub fn get<T: Asset, P: AsRef<Path>>(&self, path: P) -> Option<Handle<T>> {
+        let key = (TypeId::of::<T>(), path.as_ref().to_path_buf());
+        let slot = self
+            .cache
+            .get(&key)?
+            .slot
+            .as_any()
+            .downcast_ref::<Weak<Slot<T>>>()?;
+        slot.upgrade().map(|slot| Handle { slot })
+    }
+
+    // How many assets are cached, including any whose handles were dropped
+    // since the last `collect_garbage`.
+    pub fn loaded_count(&self) -> usize {
+        self.cache.len()
+    }
+
+    // Forgets assets whose handles have all been dropped.
+    pub fn collect_garbage(&mut self) {
+        self.cache.retain(|_, entry| entry.slot.is_alive());
+    }
+
+    // A development aid that reloads assets in place when their files change,
+    // keeping existing handles valid. Does nothing while an archive is mounted.
+    pub fn set_hot_reload(&mut self, enabled: bool) {
+        if enabled && !self.hot_reload {
+            let source = self.source();
+            for ((_, path), entry) in self.cache.iter_mut() {
+                entry.modified = source.modified_time(path);
+            }
+        }
+        self.hot_reload = enabled;
+    }
+
+    pub fn is_hot_reloading(&self) -> bool {
+        self.hot_reload
+    }
+
+    // Reloads assets whose files changed since they were loaded and returns
+    // their paths. Call it every frame, it only looks at the files a few times
+    // a second. A reload that fails keeps the previous version and the error
+    // goes to `take_errors`.
+    pub fn reload_changed(&mut self) -> Vec<PathBuf> {
+        let due = match self.last_reload_check {
+            Some(last) => last.elapsed() >= HOT_RELOAD_INTERVAL,
+            None => true,
+        };
+        if !self.hot_reload || !due {
+            return Vec::new();
+        }
+        self.last_reload_check = Some(Instant::now());
+        self.collect_garbage();
+
+        let source = self.source();
+        let mut reloaded = Vec::new();
+        for ((_, path), entry) in self.cache.iter_mut() {
+            let modified = source.modified_time(path);
+            if modified.is_none() || modified == entry.modified {
+                continue;
+            }
+            entry.modified = modified;
+
+            match entry.slot.reload(&source) {
+                Ok(()) => {
+                    log::info!("Reloaded {}", path.display());
+                    reloaded.push(path.clone());
+                }
+                Err(e) => self.errors.lock().unwrap().push(Error::Asset {
+                    path: path.clone(),
+                    source: Box::new(e),
+                }),
+            }
+        }
+        reloaded
+    }
+
+    fn insert<T: Asset>(&mut self, slot: &Arc<Slot<T>>) {
+        self.collect_garbage();
+        let key = (TypeId::of::<T>(), slot.path.clone());
+        let modified = if self.hot_reload {
+            self.source().modified_time(&slot.path)
+        } else {
+            None
+        };
+        let entry = CacheEntry {
+            slot: Box::new(Arc::downgrade(slot)),
+            modified,
+        };
+        self.cache.insert(key, entry);
+    }
+}
+
+struct CacheEntry {
+    slot: Box<dyn CachedSlot>,
+    // When the file was last read, only tracked while hot reloading.
+    modified: Option<SystemTime>,
+}
+
+impl Default for AssetManager {
+    fn default() -> Self {
+        Self::new(Self::default_root())
+    }
+}
@@ -0,0 +1,69 @@
+use super::Asset;
+use crate::error::{Error, Result};
+
+use std::borrow::Cow;
+use std::io;
+use std::path::Path;
+
+// SPIR-V ready to create a shader module from. GLSL sources are compiled
+// with shaderc when loaded, the same way build.rs compiles them.
+pub struct Shader {
+    stage: shaderc::ShaderKind,
+    spirv: Vec<u32>,
+}
+
+impl Shader {
+    pub fn compile(source: &str, stage: shaderc::ShaderKind, name: &str) -> Result<Self> {
+        let mut compiler = shaderc::Compiler::new().ok_or_else(|| {
+            shaderc::Error::InternalError("unable to create shader compiler".to_string())
+        })?;
+        let compiled = compiler.compile_into_spirv(source, stage, name, "main", None)?;
+
+        Ok(Self {
+            stage,
+            spirv: compiled.as_binary().to_vec(),
+        })
+    }
+
+    pub fn stage(&self) -> shaderc::ShaderKind {
+        self.stage
+    }
+
+    pub fn spirv(&self) -> &[u32] {
+        &self.spirv
+    }
+
+    pub fn module_source(&self) -> wgpu::ShaderModuleSource<'_> {
+        wgpu::ShaderModuleSource::SpirV(Cow::Borrowed(&self.spirv))
+    }
+}
+
+fn stage_for(extension: &str) -> Option<shaderc::ShaderKind> {
+    match extension {
+        "vert" => Some(shaderc::ShaderKind::Vertex),
+        "frag" => Some(shaderc::ShaderKind::Fragment),
+        "comp" => Some(shaderc::ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+impl Asset for Shader {
+    // The stage comes from the file extension, which `from_bytes` can't see.
+    fn from_bytes(_bytes: Vec<u8>) -> Result<Self> {
+        Err(unsupported("shaders need a .vert, .frag or .comp path"))
+    }
+
+    fn from_file(bytes: Vec<u8>, path: &Path) -> Result<Self> {
+        let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+        let stage = stage_for(extension)
+            .ok_or_else(|| unsupported("shaders need a .vert, .frag or .comp path"))?;
+        let source = String::from_utf8(bytes)
+            .map_err(|e| Error::from(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+
+        Self::compile(&source, stage, &path.to_string_lossy())
+    }
+}
+
+fn unsupported(message: &str) -> Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message).into()
+}
@@ -0,0 +1,41 @@
+use super::Asset;
+use crate::error::Result;
+
+// Decoded RGBA8 pixels, ready to be uploaded to the GPU.
+pub struct Texture {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Texture {
+    pub fn from_rgba(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        assert_eq!(pixels.len(), width as usize * height as usize * 4);
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+}
+
+impl Asset for Texture {
+    // Any format the image crate can decode, PNG being the usual one.
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        let image = image::load_from_memory(&bytes)?.into_rgba8();
+        let (width, height) = image.dimensions();
+        Ok(Self::from_rgba(width, height, image.into_raw()))
+    }
+}
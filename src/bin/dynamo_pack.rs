@@ -0,0 +1,35 @@
+// Packs an asset directory into an archive the asset manager can mount.
+//
+//     cargo run --bin dynamo_pack -- res target/release/res.pak
+
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (directory, output) = match args.as_slice() {
+        [directory] => (PathBuf::from(directory), PathBuf::from("res.pak")),
+        [directory, output] => (PathBuf::from(directory), PathBuf::from(output)),
+        _ => {
+            eprintln!("Usage: dynamo_pack <asset directory> [output archive]");
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = dynamo_lib::assets::pack_directory(&directory, &output) {
+        eprintln!("Unable to pack {}: {}", directory.display(), e);
+        process::exit(1);
+    }
+
+    match dynamo_lib::assets::Archive::open(&output) {
+        Ok(archive) => println!(
+            "Packed {} files into {}",
+            archive.paths().count(),
+            output.display()
+        ),
+        Err(e) => {
+            eprintln!("Unable to read back {}: {}", output.display(), e);
+            process::exit(1);
+        }
+    }
+}
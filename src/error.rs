@@ -0,0 +1,101 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use wgpu_glyph::ab_glyph::InvalidFont;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Decode(rodio::decoder::DecoderError),
+    Wav(hound::Error),
+    UnknownSound(String),
+    InvalidMusicOptions(String),
+    InvalidModule(String),
+    InvalidArchive(String),
+    InvalidBitmapFont(String),
+    InvalidStringTable(String),
+    Image(image::ImageError),
+    Font(InvalidFont),
+    Shader(shaderc::Error),
+    // Wraps the error from loading an asset with the path it was loaded from.
+    Asset { path: PathBuf, source: Box<Error> },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Decode(e) => write!(f, "unable to decode audio: {}", e),
+            Error::Wav(e) => write!(f, "unable to write wav: {}", e),
+            Error::UnknownSound(name) => write!(f, "no sound loaded named {:?}", name),
+            Error::InvalidMusicOptions(message) => write!(f, "invalid music options: {}", message),
+            Error::InvalidModule(message) => write!(f, "invalid module: {}", message),
+            Error::InvalidArchive(message) => write!(f, "invalid archive: {}", message),
+            Error::InvalidBitmapFont(message) => write!(f, "invalid bitmap font: {}", message),
+            Error::InvalidStringTable(message) => write!(f, "invalid string table: {}", message),
+            Error::Image(e) => write!(f, "unable to decode image: {}", e),
+            Error::Font(e) => write!(f, "unable to load font: {}", e),
+            Error::Shader(e) => write!(f, "unable to compile shader: {}", e),
+            // The cause comes from `source`, so reporters don't print it twice.
+            Error::Asset { path, .. } => write!(f, "unable to load {}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::Wav(e) => Some(e),
+            Error::Image(e) => Some(e),
+            Error::Font(e) => Some(e),
+            Error::Shader(e) => Some(e),
+            Error::Asset { source, .. } => Some(source.as_ref()),
+            Error::UnknownSound(_)
+            | Error::InvalidMusicOptions(_)
+            | Error::InvalidModule(_)
+            | Error::InvalidArchive(_)
+            | Error::InvalidBitmapFont(_)
+            | Error::InvalidStringTable(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<rodio::decoder::DecoderError> for Error {
+    fn from(e: rodio::decoder::DecoderError) -> Self {
+        Error::Decode(e)
+    }
+}
+
+impl From<hound::Error> for Error {
+    fn from(e: hound::Error) -> Self {
+        Error::Wav(e)
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(e: image::ImageError) -> Self {
+        Error::Image(e)
+    }
+}
+
+impl From<InvalidFont> for Error {
+    fn from(e: InvalidFont) -> Self {
+        Error::Font(e)
+    }
+}
+
+impl From<shaderc::Error> for Error {
+    fn from(e: shaderc::Error) -> Self {
+        Error::Shader(e)
+    }
+}
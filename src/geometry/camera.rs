@@ -0,0 +1,51 @@
+use cgmath::Vector2;
+
+// Where the scene is viewed from. Quads and world space text are given in
+// world coordinates and drawn relative to the camera. The default camera
+// shows -1 to 1 on both axes, so world coordinates match the normalized
+// device coordinates quads were always given in.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Camera {
+    // The point of the world at the center of the screen.
+    pub position: Vector2<f32>,
+    // Above 1 zooms in, below 1 zooms out.
+    pub zoom: f32,
+}
+
+impl Camera {
+    pub fn new(position: Vector2<f32>, zoom: f32) -> Self {
+        Self { position, zoom }
+    }
+
+    pub fn world_to_ndc(&self, point: Vector2<f32>) -> Vector2<f32> {
+        (point - self.position) * self.zoom
+    }
+
+    pub fn ndc_to_world(&self, point: Vector2<f32>) -> Vector2<f32> {
+        point / self.zoom + self.position
+    }
+
+    // Pixels from the top left of a screen of `screen_size` pixels, the
+    // space `RenderText::position` is given in for screen space text.
+    pub fn world_to_screen(&self, point: Vector2<f32>, screen_size: (f32, f32)) -> Vector2<f32> {
+        let ndc = self.world_to_ndc(point);
+        Vector2::new(
+            (ndc.x + 1.0) * 0.5 * screen_size.0,
+            (1.0 - ndc.y) * 0.5 * screen_size.1,
+        )
+    }
+
+    pub fn screen_to_world(&self, point: Vector2<f32>, screen_size: (f32, f32)) -> Vector2<f32> {
+        let ndc = Vector2::new(
+            point.x / screen_size.0 * 2.0 - 1.0,
+            1.0 - point.y / screen_size.1 * 2.0,
+        );
+        self.ndc_to_world(ndc)
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(Vector2::new(0.0, 0.0), 1.0)
+    }
+}
@@ -1,89 +1,224 @@
-use crate::util::size_of_slice;
+use crate::util::write_buffer;
 
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
+pub mod camera;
 pub mod quad;
+mod retained;
 pub mod vertex;
+use camera::Camera;
 use quad::Quad;
+pub use retained::QuadHandle;
+pub(crate) use retained::RetainedBuffers;
+use retained::RetainedQuads;
+use vertex::{quad_indices, Vertex, U32_SIZE};
 
 pub struct Geometry {
-    vertex_data: Vec<vertex::Vertex>,
-    index_data: Vec<u32>,
+    vertex_data: Vec<Vertex>,
     pub num_quads: u32,
+    // Applied when the quads are drawn, so it can move after they're pushed.
+    pub camera: Camera,
+    retained: RetainedQuads,
 }
 
 impl Geometry {
     pub fn new() -> Self {
         Self {
             vertex_data: Vec::new(),
-            index_data: Vec::new(),
             num_quads: 0,
+            camera: Camera::default(),
+            retained: RetainedQuads::new(),
         }
     }
 
+    // Clears the quads pushed this frame. Quads added with `add_quad` stay.
     pub fn reset(&mut self) {
         self.vertex_data.clear();
-        self.index_data.clear();
         self.num_quads = 0;
     }
 
+    // Draws a quad this frame only.
     pub fn push_quad(&mut self, quad: &Quad) {
-        let min_x = quad.position.x - quad.size.x * 0.5;
-        let min_y = quad.position.y - quad.size.y * 0.5;
-        let max_x = quad.position.x + quad.size.x * 0.5;
-        let max_y = quad.position.y + quad.size.y * 0.5;
-
-        self.vertex_data.extend(&[
-            vertex::Vertex {
-                position: (min_x, min_y).into(),
-            },
-            vertex::Vertex {
-                position: (max_x, min_y).into(),
-            },
-            vertex::Vertex {
-                position: (max_x, max_y).into(),
-            },
-            vertex::Vertex {
-                position: (min_x, max_y).into(),
-            },
-        ]);
-        self.index_data.extend(&[
-            self.num_quads * 4 + 0,
-            self.num_quads * 4 + 1,
-            self.num_quads * 4 + 2,
-            self.num_quads * 4 + 0,
-            self.num_quads * 4 + 2,
-            self.num_quads * 4 + 3,
-        ]);
+        self.vertex_data.extend(&Vertex::quad(quad));
         self.num_quads += 1;
     }
 
-    pub fn build(&self, device: &wgpu::Device) -> (StagingBuffer, StagingBuffer, u32) {
-        (
-            StagingBuffer::new(device, &self.vertex_data),
-            StagingBuffer::new(device, &self.index_data),
-            self.index_data.len() as u32,
-        )
+    // Adds a quad that's drawn every frame until it's removed, behind the
+    // quads pushed each frame. Suits things that rarely change, like a wall of
+    // bricks, since only changed quads are sent to the GPU again.
+    pub fn add_quad(&mut self, quad: &Quad) -> QuadHandle {
+        self.retained.add(quad)
+    }
+
+    // None once the quad's been removed.
+    pub fn quad(&self, handle: QuadHandle) -> Option<&Quad> {
+        self.retained.get(handle)
+    }
+
+    // Replaces a quad. Like the other changes, this does nothing once the
+    // quad's been removed.
+    pub fn set_quad(&mut self, handle: QuadHandle, quad: &Quad) {
+        self.retained.update(handle, |current, _| *current = *quad);
+    }
+
+    pub fn move_quad(&mut self, handle: QuadHandle, position: cgmath::Vector2<f32>) {
+        self.retained
+            .update(handle, |quad, _| quad.position = position);
+    }
+
+    pub fn resize_quad(&mut self, handle: QuadHandle, size: cgmath::Vector2<f32>) {
+        self.retained.update(handle, |quad, _| quad.size = size);
+    }
+
+    pub fn recolor_quad(&mut self, handle: QuadHandle, color: cgmath::Vector4<f32>) {
+        self.retained.update(handle, |quad, _| quad.color = color);
+    }
+
+    // Hidden quads keep their place and come back with `set_quad_visible`.
+    pub fn set_quad_visible(&mut self, handle: QuadHandle, visible: bool) {
+        self.retained
+            .update(handle, |_, current| *current = visible);
+    }
+
+    pub fn is_quad_visible(&self, handle: QuadHandle) -> bool {
+        self.retained.is_visible(handle)
+    }
+
+    pub fn remove_quad(&mut self, handle: QuadHandle) -> Option<Quad> {
+        self.retained.remove(handle)
+    }
+
+    // Removes every quad added with `add_quad`.
+    pub fn clear_quads(&mut self) {
+        self.retained.clear();
+    }
+
+    // How many quads added with `add_quad` haven't been removed.
+    pub fn quad_count(&self) -> usize {
+        self.retained.len()
+    }
+
+    pub(crate) fn sync_retained(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        buffers: &mut RetainedBuffers,
+    ) {
+        buffers.sync(device, encoder, staging_belt, &mut self.retained);
+    }
+
+    // Records copying this frame's quads into `buffers` through the staging
+    // belt. The belt has to be finished before `encoder` is submitted. Only
+    // public so the quad_buffers bench can drive it without a window.
+    #[doc(hidden)]
+    pub fn upload(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        buffers: &mut QuadBuffers,
+    ) {
+        buffers.upload(device, encoder, staging_belt, &self.vertex_data);
     }
 }
 
-pub struct StagingBuffer {
-    buffer: wgpu::Buffer,
-    size: wgpu::BufferAddress,
+// The GPU copy of the quads pushed each frame. The buffers are kept from frame
+// to frame and only replaced when a frame has more quads than they hold, so
+// drawing about as many quads each frame allocates nothing after the first.
+// Public for the quad_buffers bench only, so it's left out of the docs.
+#[doc(hidden)]
+pub struct QuadBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // In quads. Never shrinks.
+    capacity: u32,
+    // Quads uploaded last.
+    count: u32,
+    allocations: u64,
 }
 
-impl StagingBuffer {
-    pub fn new<T: bytemuck::Pod + Sized>(device: &wgpu::Device, data: &[T]) -> StagingBuffer {
-        StagingBuffer {
-            buffer: device.create_buffer_init(&BufferInitDescriptor {
-                contents: bytemuck::cast_slice(data),
-                usage: wgpu::BufferUsage::COPY_SRC,
-                label: Some("Staging Buffer"),
-            }),
-            size: size_of_slice(data) as wgpu::BufferAddress,
+impl QuadBuffers {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            vertex_buffer: Self::create_vertex_buffer(device, 0),
+            index_buffer: Self::create_index_buffer(device, 0),
+            capacity: 0,
+            count: 0,
+            allocations: 0,
         }
     }
 
-    pub fn copy_to_buffer(self, encoder: &mut wgpu::CommandEncoder, other: &wgpu::Buffer) {
-        encoder.copy_buffer_to_buffer(&self.buffer, 0, other, 0, self.size)
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Quad Vertex Buffer"),
+            size: Vertex::SIZE * 4 * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_index_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Quad Index Buffer"),
+            size: U32_SIZE * 6 * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // How many quads fit before the buffers have to be replaced.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    // How many buffers have been created to grow, not counting the empty ones
+    // `new` starts with.
+    pub fn allocations(&self) -> u64 {
+        self.allocations
+    }
+
+    // The indices never change for a given capacity, so they're only
+    // uploaded when the buffers grow.
+    fn upload(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        vertices: &[Vertex],
+    ) {
+        self.count = (vertices.len() / 4) as u32;
+        if self.count > self.capacity {
+            self.capacity = self.count.next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+            self.index_buffer = Self::create_index_buffer(device, self.capacity);
+            self.allocations += 2;
+
+            let indices = quad_indices(0..self.capacity);
+            write_buffer(
+                staging_belt,
+                encoder,
+                device,
+                &self.index_buffer,
+                0,
+                bytemuck::cast_slice(&indices),
+            );
+        }
+
+        write_buffer(
+            staging_belt,
+            encoder,
+            device,
+            &self.vertex_buffer,
+            0,
+            bytemuck::cast_slice(vertices),
+        );
+    }
+
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.count == 0 {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..));
+        render_pass.draw_indexed(0..self.count * 6, 0, 0..1);
     }
 }
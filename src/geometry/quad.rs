@@ -2,13 +2,21 @@
 pub struct Quad {
     pub position: cgmath::Vector2<f32>,
     pub size: cgmath::Vector2<f32>,
+    pub color: cgmath::Vector4<f32>,
 }
 
 impl Quad {
+    // A white quad.
     pub fn new(position: cgmath::Vector2<f32>, size: cgmath::Vector2<f32>) -> Quad {
         Quad {
             position: position,
             size: size,
+            color: (1.0, 1.0, 1.0, 1.0).into(),
         }
     }
+
+    pub fn with_color(mut self, color: cgmath::Vector4<f32>) -> Quad {
+        self.color = color;
+        self
+    }
 }
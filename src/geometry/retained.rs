@@ -0,0 +1,324 @@
+use super::quad::Quad;
+use super::vertex::{quad_indices, Vertex, U32_SIZE};
+use crate::util::write_buffer;
+
+use std::ops::Range;
+
+// Refers to a quad added with `Geometry::add_quad`. Handles of removed quads
+// stay invalid even once their slot is reused.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct QuadHandle {
+    index: u32,
+    generation: u32,
+}
+
+struct Slot {
+    quad: Quad,
+    visible: bool,
+    alive: bool,
+    generation: u32,
+}
+
+// Quads that stay put between frames. Each has four vertices at a fixed
+// place in the vertex buffer, so a change only re-uploads its own vertices.
+// Hidden and removed quads collapse to a point rather than shifting the
+// quads after them.
+pub(crate) struct RetainedQuads {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    vertices: Vec<Vertex>,
+    // Slots changed since the last upload, unsorted and possibly repeated.
+    dirty: Vec<u32>,
+}
+
+impl RetainedQuads {
+    pub fn new() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            vertices: Vec::new(),
+            dirty: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, quad: &Quad) -> QuadHandle {
+        let index = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.quad = *quad;
+                slot.visible = true;
+                slot.alive = true;
+                index
+            }
+            None => {
+                self.slots.push(Slot {
+                    quad: *quad,
+                    visible: true,
+                    alive: true,
+                    generation: 0,
+                });
+                self.vertices.extend_from_slice(&[Vertex::HIDDEN; 4]);
+                self.slots.len() as u32 - 1
+            }
+        };
+        self.dirty.push(index);
+        QuadHandle {
+            index,
+            generation: self.slots[index as usize].generation,
+        }
+    }
+
+    fn slot(&self, handle: QuadHandle) -> Option<&Slot> {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.alive && slot.generation == handle.generation)
+    }
+
+    pub fn get(&self, handle: QuadHandle) -> Option<&Quad> {
+        self.slot(handle).map(|slot| &slot.quad)
+    }
+
+    pub fn is_visible(&self, handle: QuadHandle) -> bool {
+        self.slot(handle).is_some_and(|slot| slot.visible)
+    }
+
+    // Changes a quad through `update`, doing nothing for stale handles.
+    pub fn update<F: FnOnce(&mut Quad, &mut bool)>(&mut self, handle: QuadHandle, update: F) {
+        if self.slot(handle).is_some() {
+            let slot = &mut self.slots[handle.index as usize];
+            update(&mut slot.quad, &mut slot.visible);
+            self.dirty.push(handle.index);
+        }
+    }
+
+    pub fn remove(&mut self, handle: QuadHandle) -> Option<Quad> {
+        let quad = *self.get(handle)?;
+        let slot = &mut self.slots[handle.index as usize];
+        slot.alive = false;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(handle.index);
+        self.dirty.push(handle.index);
+        Some(quad)
+    }
+
+    pub fn clear(&mut self) {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if slot.alive {
+                slot.alive = false;
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free.push(index as u32);
+                self.dirty.push(index as u32);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    // Rewrites the vertices of the slots changed since the last call and
+    // returns them as ranges of slots.
+    fn take_dirty(&mut self) -> Vec<Range<u32>> {
+        let mut dirty = std::mem::take(&mut self.dirty);
+        dirty.sort_unstable();
+        dirty.dedup();
+
+        let mut ranges: Vec<Range<u32>> = Vec::new();
+        for index in dirty {
+            let slot = &self.slots[index as usize];
+            let vertices = if slot.alive && slot.visible {
+                Vertex::quad(&slot.quad)
+            } else {
+                [Vertex::HIDDEN; 4]
+            };
+            let start = index as usize * 4;
+            self.vertices[start..start + 4].copy_from_slice(&vertices);
+
+            match ranges.last_mut() {
+                Some(range) if range.end == index => range.end += 1,
+                _ => ranges.push(index..index + 1),
+            }
+        }
+        ranges
+    }
+}
+
+// The GPU copy of the retained quads, grown as quads are added.
+pub(crate) struct RetainedBuffers {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // In quads.
+    capacity: u32,
+    // Slots in use, hidden and removed ones included.
+    count: u32,
+}
+
+impl RetainedBuffers {
+    pub fn new(device: &wgpu::Device) -> Self {
+        Self {
+            vertex_buffer: Self::create_vertex_buffer(device, 0),
+            index_buffer: Self::create_index_buffer(device, 0),
+            capacity: 0,
+            count: 0,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Retained Vertex Buffer"),
+            size: Vertex::SIZE * 4 * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_index_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Retained Index Buffer"),
+            size: U32_SIZE * 6 * capacity as wgpu::BufferAddress,
+            usage: wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    // Uploads what changed since the last sync through the staging belt.
+    // Growing the buffers uploads everything once, since the old contents are
+    // left behind.
+    pub fn sync(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        quads: &mut RetainedQuads,
+    ) {
+        let dirty = quads.take_dirty();
+        self.count = quads.slots.len() as u32;
+
+        if self.count > self.capacity {
+            self.capacity = self.count.next_power_of_two();
+            self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+            self.index_buffer = Self::create_index_buffer(device, self.capacity);
+
+            let indices = quad_indices(0..self.capacity);
+            write_buffer(
+                staging_belt,
+                encoder,
+                device,
+                &self.index_buffer,
+                0,
+                bytemuck::cast_slice(&indices),
+            );
+            write_buffer(
+                staging_belt,
+                encoder,
+                device,
+                &self.vertex_buffer,
+                0,
+                bytemuck::cast_slice(&quads.vertices),
+            );
+            return;
+        }
+
+        for range in dirty {
+            let vertices = &quads.vertices[range.start as usize * 4..range.end as usize * 4];
+            let offset = Vertex::SIZE * 4 * range.start as wgpu::BufferAddress;
+            write_buffer(
+                staging_belt,
+                encoder,
+                device,
+                &self.vertex_buffer,
+                offset,
+                bytemuck::cast_slice(vertices),
+            );
+        }
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if self.count == 0 {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..));
+        render_pass.draw_indexed(0..self.count * 6, 0, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad(x: f32) -> Quad {
+        Quad::new((x, 0.0).into(), (1.0, 1.0).into())
+    }
+
+    #[test]
+    fn stale_handles_stay_invalid_after_reuse() {
+        let mut quads = RetainedQuads::new();
+        let first = quads.add(&quad(1.0));
+        assert_eq!(quads.remove(first).map(|quad| quad.position.x), Some(1.0));
+
+        // The new quad reuses the slot but not the handle.
+        let second = quads.add(&quad(2.0));
+        assert_eq!(second.index, first.index);
+        assert_ne!(second, first);
+        assert!(quads.get(first).is_none());
+        assert!(quads.remove(first).is_none());
+        assert_eq!(quads.get(second).map(|quad| quad.position.x), Some(2.0));
+    }
+
+    #[test]
+    fn update_ignores_stale_handles() {
+        let mut quads = RetainedQuads::new();
+        let stale = quads.add(&quad(1.0));
+        quads.remove(stale);
+        let current = quads.add(&quad(2.0));
+        quads.take_dirty();
+
+        quads.update(stale, |quad, visible| {
+            quad.position.x = 3.0;
+            *visible = false;
+        });
+        assert_eq!(quads.get(current).map(|quad| quad.position.x), Some(2.0));
+        assert!(quads.is_visible(current));
+        assert!(quads.take_dirty().is_empty());
+
+        quads.update(current, |_, visible| *visible = false);
+        assert!(!quads.is_visible(current));
+        assert_eq!(quads.take_dirty(), vec![current.index..current.index + 1]);
+    }
+
+    #[test]
+    fn clear_removes_every_quad() {
+        let mut quads = RetainedQuads::new();
+        let handles: Vec<_> = (0..3).map(|i| quads.add(&quad(i as f32))).collect();
+        quads.remove(handles[1]);
+        assert_eq!(quads.len(), 2);
+
+        quads.clear();
+        assert_eq!(quads.len(), 0);
+        assert!(handles.iter().all(|&handle| quads.get(handle).is_none()));
+
+        // Cleared slots are reused before new ones are made.
+        quads.add(&quad(0.0));
+        assert_eq!(quads.len(), 1);
+        assert_eq!(quads.slots.len(), 3);
+    }
+
+    #[test]
+    fn dirty_slots_coalesce_into_ranges() {
+        let mut quads = RetainedQuads::new();
+        let handles: Vec<_> = (0..6).map(|i| quads.add(&quad(i as f32))).collect();
+        assert_eq!(quads.take_dirty(), vec![0..6]);
+        assert!(quads.take_dirty().is_empty());
+
+        // Out of order and repeated changes still merge.
+        for &index in &[4, 1, 0, 4, 5] {
+            quads.update(handles[index], |quad, _| quad.position.y = 1.0);
+        }
+        assert_eq!(quads.take_dirty(), vec![0..2, 4..6]);
+
+        quads.remove(handles[3]);
+        assert_eq!(quads.take_dirty(), vec![3..4]);
+        assert_eq!(quads.vertices.len(), 6 * 4);
+    }
+}
@@ -1,10 +1,14 @@
+use super::quad::Quad;
+
 use std::mem;
+use std::ops::Range;
 
 pub const U32_SIZE: wgpu::BufferAddress = std::mem::size_of::<u32>() as wgpu::BufferAddress;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Vertex {
     pub position: cgmath::Vector2<f32>,
+    pub color: cgmath::Vector4<f32>,
 }
 
 unsafe impl bytemuck::Pod for Vertex {}
@@ -15,10 +19,6 @@ impl Vertex {
     pub const DESC: wgpu::VertexBufferDescriptor<'static> = wgpu::VertexBufferDescriptor {
         stride: Self::SIZE,
         step_mode: wgpu::InputStepMode::Vertex,
-        // What?
-        // attributes: &wgpu::vertex_attr_array![
-        //     0 => Float2
-        // ],
         attributes: &[
             wgpu::VertexAttributeDescriptor {
                 offset: 0,
@@ -26,15 +26,56 @@ impl Vertex {
                 format: wgpu::VertexFormat::Float2,
             },
             wgpu::VertexAttributeDescriptor {
-                offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
                 shader_location: 1,
-                format: wgpu::VertexFormat::Float2,
-            },
-            wgpu::VertexAttributeDescriptor {
-                offset: mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
-                shader_location: 2,
-                format: wgpu::VertexFormat::Float2,
+                format: wgpu::VertexFormat::Float4,
             },
         ],
     };
+
+    // Stands in for the vertices of a quad that isn't drawn. Four of them
+    // make two triangles with no area.
+    pub(crate) const HIDDEN: Vertex = Vertex {
+        position: cgmath::Vector2::new(0.0, 0.0),
+        color: cgmath::Vector4::new(0.0, 0.0, 0.0, 0.0),
+    };
+
+    // The corners of a quad, counter clockwise from the bottom left.
+    pub(crate) fn quad(quad: &Quad) -> [Vertex; 4] {
+        let min_x = quad.position.x - quad.size.x * 0.5;
+        let min_y = quad.position.y - quad.size.y * 0.5;
+        let max_x = quad.position.x + quad.size.x * 0.5;
+        let max_y = quad.position.y + quad.size.y * 0.5;
+        let color = quad.color;
+
+        [
+            Vertex {
+                position: (min_x, min_y).into(),
+                color,
+            },
+            Vertex {
+                position: (max_x, min_y).into(),
+                color,
+            },
+            Vertex {
+                position: (max_x, max_y).into(),
+                color,
+            },
+            Vertex {
+                position: (min_x, max_y).into(),
+                color,
+            },
+        ]
+    }
+}
+
+// Two triangles for each quad in `quads`, indexing the four vertices
+// `Vertex::quad` gives each one.
+pub(crate) fn quad_indices(quads: Range<u32>) -> Vec<u32> {
+    let mut indices = Vec::with_capacity(quads.len() * 6);
+    for quad in quads {
+        let first = quad * 4;
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    indices
 }
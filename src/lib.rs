@@ -1,5 +1,8 @@
+pub mod assets;
+pub mod error;
 pub mod geometry;
 pub mod keyboard;
+pub mod localization;
 pub mod renderer;
 pub mod sound;
 mod util;
@@ -7,6 +10,7 @@ mod util;
 use geometry::Geometry;
 use renderer::render_text::*;
 use renderer::*;
+use sound::timeline::BeatEvent;
 use sound::SoundSystem;
 
 use winit::{
@@ -15,23 +19,29 @@ use winit::{
   window::WindowBuilder,
 };
 
+// Games get the sound system mutably, to play music and load sounds. It used
+// to be `&SoundSystem`, so games written against that need `&mut` in their
+// `initialize` and `update`.
 pub trait Game {
   fn initialize(
     &mut self,
     geometry: &mut Geometry,
     text_renderer: &mut TextRenderer,
-    sound_system: &SoundSystem,
+    sound_system: &mut SoundSystem,
     window_size: (f32, f32),
   );
   fn update(
     &mut self,
     geometry: &mut Geometry,
     text_renderer: &mut TextRenderer,
-    sound_system: &SoundSystem,
+    sound_system: &mut SoundSystem,
   );
   fn process_keyboard(&mut self, input: keyboard::KeyboardInput);
   fn is_quitting(&self) -> bool;
   fn focus_changed(&mut self, focus: bool);
+  // Called before `update` for each beat of the music timeline that passed
+  // since the last frame.
+  fn beat(&mut self, _beat: BeatEvent) {}
 }
 
 pub fn start(title: &str, mut game: Box<dyn Game>) {
@@ -47,12 +57,32 @@ pub fn start(title: &str, mut game: Box<dyn Game>) {
   let mut renderer = block_on(Renderer::new(&window));
   let mut geometry = Geometry::new();
   let mut text_renderer = TextRenderer::new();
-  let sound_system = sound::SoundSystem::new();
+  let mut sound_system = sound::SoundSystem::new();
+  let start_time = std::time::Instant::now();
 
+  // Setting DYNAMO_HOT_RELOAD picks up edits to the shaders in the asset
+  // root while the game runs.
+  let mut shader_reloader = if std::env::var_os("DYNAMO_HOT_RELOAD").is_some() {
+    ShaderReloader::new(assets::AssetManager::default_root())
+      .map_err(|e| log::error!("Unable to watch shaders: {}", e))
+      .ok()
+  } else {
+    None
+  };
+
+  // The default font comes from the asset root like other assets, and hot
+  // reloads with them.
+  let mut assets = assets::AssetManager::new(assets::AssetManager::default_root());
+  assets.set_hot_reload(std::env::var_os("DYNAMO_HOT_RELOAD").is_some());
+  if let Err(e) = text_renderer.load_default_font(&mut assets) {
+    log::warn!("Using the built in font: {}", e);
+  }
+
+  text_renderer.set_screen_size((renderer.width(), renderer.height()));
   game.initialize(
     &mut geometry,
     &mut text_renderer,
-    &sound_system,
+    &mut sound_system,
     (renderer.width(), renderer.height()),
   );
 
@@ -67,8 +97,21 @@ pub fn start(title: &str, mut game: Box<dyn Game>) {
 
     match event {
       Event::RedrawRequested(_) => {
-        game.update(&mut geometry, &mut text_renderer, &sound_system);
-        renderer.render(&geometry, &text_renderer);
+        if let Some(reloader) = shader_reloader.as_mut() {
+          reloader.update(&mut renderer);
+        }
+        assets.reload_changed();
+        for beat in sound_system.music().take_beats() {
+          game.beat(beat);
+        }
+        text_renderer.set_screen_size((renderer.width(), renderer.height()));
+        text_renderer.set_time(start_time.elapsed());
+        game.update(&mut geometry, &mut text_renderer, &mut sound_system);
+        text_renderer.update_localized();
+        text_renderer.update_fonts();
+        text_renderer.set_camera(geometry.camera);
+        sound_system.update_listener(&geometry.camera);
+        renderer.render(&mut geometry, &text_renderer);
       }
       Event::MainEventsCleared => {
         window.request_redraw();
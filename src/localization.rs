@@ -0,0 +1,526 @@
+use crate::assets::{Asset, AssetManager, Handle};
+use crate::error::{Error, Result};
+
+use std::collections::HashMap;
+use std::fmt;
+
+// Where string tables live under the asset root, one file per language named
+// after its language tag, like `locales/en.strings` or `locales/pt-BR.strings`.
+const TABLE_DIRECTORY: &str = "locales";
+const TABLE_EXTENSION: &str = "strings";
+
+// The plural categories of the Unicode CLDR. Which ones a language uses, and
+// for which numbers, comes from `plural_category`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PluralCategory {
+    Zero,
+    One,
+    Two,
+    Few,
+    Many,
+    Other,
+}
+
+impl PluralCategory {
+    // The suffix that picks a plural form in a string table.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            PluralCategory::Zero => "zero",
+            PluralCategory::One => "one",
+            PluralCategory::Two => "two",
+            PluralCategory::Few => "few",
+            PluralCategory::Many => "many",
+            PluralCategory::Other => "other",
+        }
+    }
+}
+
+// The cardinal plural category of `n` for a language tag. Covers the common
+// plural rules; languages without rules of their own get English's.
+pub fn plural_category(language: &str, n: f64) -> PluralCategory {
+    let primary = language
+        .split(['-', '_'])
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let n = n.abs();
+    let i = n.trunc() as u64;
+    // Numbers with a fractional part fall in "other" for most rules.
+    let whole = n.fract() == 0.0;
+    let (i10, i100) = (i % 10, i % 100);
+
+    match primary.as_str() {
+        "ja" | "zh" | "ko" | "th" | "vi" | "id" | "ms" | "lo" | "my" => PluralCategory::Other,
+        "fr" | "pt" | "hi" | "bn" | "fa" if i <= 1 => PluralCategory::One,
+        "fr" | "pt" | "hi" | "bn" | "fa" => PluralCategory::Other,
+        "ru" | "uk" | "be" if !whole => PluralCategory::Other,
+        "ru" | "uk" | "be" if i10 == 1 && i100 != 11 => PluralCategory::One,
+        "ru" | "uk" | "be" if (2..=4).contains(&i10) && !(12..=14).contains(&i100) => {
+            PluralCategory::Few
+        }
+        "ru" | "uk" | "be" => PluralCategory::Many,
+        "pl" if !whole => PluralCategory::Other,
+        "pl" if i == 1 => PluralCategory::One,
+        "pl" if (2..=4).contains(&i10) && !(12..=14).contains(&i100) => PluralCategory::Few,
+        "pl" => PluralCategory::Many,
+        "cs" | "sk" if !whole => PluralCategory::Many,
+        "cs" | "sk" if i == 1 => PluralCategory::One,
+        "cs" | "sk" if (2..=4).contains(&i) => PluralCategory::Few,
+        "ar" if !whole => PluralCategory::Other,
+        "ar" if i == 0 => PluralCategory::Zero,
+        "ar" if i == 1 => PluralCategory::One,
+        "ar" if i == 2 => PluralCategory::Two,
+        "ar" if (3..=10).contains(&i100) => PluralCategory::Few,
+        "ar" if (11..=99).contains(&i100) => PluralCategory::Many,
+        "he" if whole && i == 1 => PluralCategory::One,
+        "he" if whole && i == 2 => PluralCategory::Two,
+        _ if whole && i == 1 => PluralCategory::One,
+        _ => PluralCategory::Other,
+    }
+}
+
+// A value substituted into a localized string.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arg {
+    Text(String),
+    Number(f64),
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Text(text) => write!(f, "{}", text),
+            // Whole numbers print without a trailing `.0`.
+            Arg::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Arg::Number(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl From<&str> for Arg {
+    fn from(text: &str) -> Self {
+        Arg::Text(text.to_string())
+    }
+}
+
+impl From<String> for Arg {
+    fn from(text: String) -> Self {
+        Arg::Text(text)
+    }
+}
+
+macro_rules! number_arg {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Arg {
+                fn from(n: $t) -> Self {
+                    Arg::Number(n as f64)
+                }
+            }
+        )*
+    };
+}
+
+number_arg!(i32, i64, u32, u64, usize, f32, f64);
+
+// A string looked up by key in the current language, with the arguments
+// substituted for its `{name}` placeholders. A numeric argument named `count`
+// also picks the plural form.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalizedText {
+    pub key: String,
+    pub args: Vec<(String, Arg)>,
+}
+
+impl LocalizedText {
+    pub fn new<S: Into<String>>(key: S) -> Self {
+        Self {
+            key: key.into(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg<S: Into<String>, A: Into<Arg>>(mut self, name: S, value: A) -> Self {
+        self.args.push((name.into(), value.into()));
+        self
+    }
+
+    fn count(&self) -> Option<f64> {
+        self.args.iter().find_map(|(name, value)| match value {
+            Arg::Number(n) if name == "count" => Some(*n),
+            _ => None,
+        })
+    }
+}
+
+// The strings of one language, loaded from a file of `key = value` lines.
+// Lines starting with `#` are comments. Plural forms are separate keys with
+// the category as a suffix:
+//
+//     greeting = Hello, {name}!
+//     coins.one = {count} coin
+//     coins.other = {count} coins
+//
+// Values can use `\n` for line breaks and `{{` and `}}` for literal braces.
+#[derive(Debug, Clone, Default)]
+pub struct StringTable {
+    strings: HashMap<String, String>,
+}
+
+impl StringTable {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut strings = HashMap::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let equals = line.find('=').ok_or_else(|| {
+                Error::InvalidStringTable(format!("line {} has no `=`", number + 1))
+            })?;
+            let key = line[..equals].trim();
+            if key.is_empty() {
+                return Err(Error::InvalidStringTable(format!(
+                    "line {} has no key",
+                    number + 1
+                )));
+            }
+            let value = line[equals + 1..].trim().replace("\\n", "\n");
+            strings.insert(key.to_string(), value);
+        }
+        Ok(Self { strings })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.strings.get(key).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+impl Asset for StringTable {
+    fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::parse(&String::from_bytes(bytes)?)
+    }
+}
+
+// The current language's strings, falling back to another language's for
+// keys it's missing. Keys missing from both come out as the key itself, so
+// they're easy to spot.
+pub struct Localization {
+    language: String,
+    fallback: String,
+    tables: HashMap<String, Handle<StringTable>>,
+    language_changes: u64,
+}
+
+impl Localization {
+    pub fn new() -> Self {
+        Self {
+            language: "en".to_string(),
+            fallback: "en".to_string(),
+            tables: HashMap::new(),
+            language_changes: 0,
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    // Loads the language's string table if it isn't loaded yet and switches
+    // to it. The language is left alone when the table can't be loaded.
+    pub fn set_language(&mut self, assets: &mut AssetManager, language: &str) -> Result<()> {
+        self.load_table(assets, language)?;
+        if self.language != language {
+            self.language = language.to_string();
+            self.language_changes += 1;
+        }
+        Ok(())
+    }
+
+    pub fn fallback_language(&self) -> &str {
+        &self.fallback
+    }
+
+    // The language used for keys the current language is missing, English
+    // unless set.
+    pub fn set_fallback_language(
+        &mut self,
+        assets: &mut AssetManager,
+        language: &str,
+    ) -> Result<()> {
+        self.load_table(assets, language)?;
+        if self.fallback != language {
+            self.fallback = language.to_string();
+            self.language_changes += 1;
+        }
+        Ok(())
+    }
+
+    fn load_table(&mut self, assets: &mut AssetManager, language: &str) -> Result<()> {
+        if !self.tables.contains_key(language) {
+            let path = format!("{}/{}.{}", TABLE_DIRECTORY, language, TABLE_EXTENSION);
+            let table = assets.load::<StringTable, _>(path)?;
+            self.tables.insert(language.to_string(), table);
+        }
+        Ok(())
+    }
+
+    // Changes whenever the language changes or a loaded table is hot
+    // reloaded, so text can be looked up again only when it might differ.
+    pub fn version(&self) -> u64 {
+        self.tables
+            .values()
+            .map(Handle::version)
+            .fold(self.language_changes, u64::wrapping_add)
+    }
+
+    pub fn get(&self, key: &str) -> String {
+        self.text(&LocalizedText::new(key))
+    }
+
+    pub fn text(&self, text: &LocalizedText) -> String {
+        let template = [&self.language, &self.fallback]
+            .iter()
+            .find_map(|language| self.lookup(language, text));
+        match template {
+            Some(template) => substitute(&template, &text.args),
+            None => text.key.clone(),
+        }
+    }
+
+    // The plural form for the language's own rules if there's a count,
+    // else the plain key.
+    fn lookup(&self, language: &str, text: &LocalizedText) -> Option<String> {
+        let table = self.tables.get(language)?.try_get()?;
+        if let Some(count) = text.count() {
+            let category = plural_category(language, count);
+            for suffix in &[category.suffix(), PluralCategory::Other.suffix()] {
+                let key = format!("{}.{}", text.key, suffix);
+                if let Some(value) = table.get(&key) {
+                    return Some(value.to_string());
+                }
+            }
+        }
+        table.get(&text.key).map(str::to_string)
+    }
+}
+
+impl Default for Localization {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Replaces `{name}` placeholders with their arguments. Placeholders without
+// an argument are left in.
+fn substitute(template: &str, args: &[(String, Arg)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+
+        let close = match rest.find('}') {
+            Some(close) if rest.starts_with('{') => close,
+            _ => {
+                out.push_str(&rest[..1]);
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let name = rest[1..close].trim();
+        match args.iter().find(|(arg, _)| arg == name) {
+            Some((_, value)) => out.push_str(&value.to_string()),
+            None => out.push_str(&rest[..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PluralCategory::*;
+
+    fn categories(language: &str, numbers: &[f64]) -> Vec<PluralCategory> {
+        numbers
+            .iter()
+            .map(|&n| plural_category(language, n))
+            .collect()
+    }
+
+    fn args(args: &[(&str, Arg)]) -> Vec<(String, Arg)> {
+        args.iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    fn localization(tables: &[(&str, &str)]) -> Localization {
+        let mut localization = Localization::new();
+        for (language, source) in tables {
+            let table = Handle::from_value(StringTable::parse(source).unwrap());
+            localization.tables.insert(language.to_string(), table);
+        }
+        localization
+    }
+
+    #[test]
+    fn russian_plurals() {
+        assert_eq!(
+            categories(
+                "ru",
+                &[0.0, 1.0, 2.0, 4.0, 5.0, 11.0, 12.0, 14.0, 21.0, 22.0, 25.0, 111.0]
+            ),
+            vec![Many, One, Few, Few, Many, Many, Many, Many, One, Few, Many, Many]
+        );
+        assert_eq!(plural_category("ru", 1.5), Other);
+        assert_eq!(plural_category("ru-RU", -21.0), One);
+        assert_eq!(plural_category("uk_UA", 3.0), Few);
+    }
+
+    #[test]
+    fn polish_plurals() {
+        // Unlike Russian, only 1 itself is singular.
+        assert_eq!(
+            categories(
+                "pl",
+                &[0.0, 1.0, 2.0, 4.0, 5.0, 12.0, 14.0, 21.0, 22.0, 24.0, 25.0, 112.0]
+            ),
+            vec![Many, One, Few, Few, Many, Many, Many, Many, Few, Few, Many, Many]
+        );
+        assert_eq!(plural_category("pl", 2.5), Other);
+    }
+
+    #[test]
+    fn arabic_plurals() {
+        assert_eq!(
+            categories(
+                "ar",
+                &[0.0, 1.0, 2.0, 3.0, 10.0, 11.0, 99.0, 100.0, 102.0, 103.0, 111.0]
+            ),
+            vec![Zero, One, Two, Few, Few, Many, Many, Other, Other, Few, Many]
+        );
+        assert_eq!(plural_category("ar", 0.5), Other);
+    }
+
+    #[test]
+    fn other_plurals() {
+        assert_eq!(
+            categories("en", &[0.0, 1.0, 1.5, 2.0]),
+            vec![Other, One, Other, Other]
+        );
+        assert_eq!(
+            categories("fr", &[0.0, 1.0, 1.5, 2.0]),
+            vec![One, One, One, Other]
+        );
+        assert_eq!(
+            categories("cs", &[1.0, 4.0, 5.0, 0.5]),
+            vec![One, Few, Other, Many]
+        );
+        assert_eq!(categories("he", &[1.0, 2.0, 3.0]), vec![One, Two, Other]);
+        assert_eq!(categories("JA", &[1.0, 2.0]), vec![Other, Other]);
+        // Languages without rules of their own count like English.
+        assert_eq!(categories("xx", &[1.0, 2.0]), vec![One, Other]);
+    }
+
+    #[test]
+    fn substitutes_arguments() {
+        let args = args(&[
+            ("name", "Ada".into()),
+            ("count", 3.into()),
+            ("ratio", 2.5.into()),
+        ]);
+        assert_eq!(
+            substitute("{name} has {count} ({ ratio })", &args),
+            "Ada has 3 (2.5)"
+        );
+        assert_eq!(substitute("no placeholders", &args), "no placeholders");
+        assert_eq!(substitute("", &args), "");
+    }
+
+    #[test]
+    fn escapes_braces() {
+        let args = args(&[("name", "Ada".into())]);
+        assert_eq!(substitute("{{name}}", &args), "{name}");
+        assert_eq!(substitute("{{{name}}}", &args), "{Ada}");
+        assert_eq!(substitute("}} and {{", &args), "} and {");
+    }
+
+    #[test]
+    fn leaves_unknown_and_unbalanced_placeholders() {
+        let args = args(&[("name", "Ada".into())]);
+        assert_eq!(substitute("{missing} {name}", &args), "{missing} Ada");
+        assert_eq!(substitute("a } b", &args), "a } b");
+        assert_eq!(substitute("{name", &args), "{name");
+        assert_eq!(substitute("{a{name}", &args), "{a{name}");
+    }
+
+    #[test]
+    fn parses_string_tables() {
+        let table = StringTable::parse(
+            "# A comment\n\n  greeting = Hello, {name}!  \nmath = 1 + 1 = 2\nlines = one\\ntwo\nempty =\n",
+        )
+        .unwrap();
+        assert_eq!(table.len(), 4);
+        assert_eq!(table.get("greeting"), Some("Hello, {name}!"));
+        assert_eq!(table.get("math"), Some("1 + 1 = 2"));
+        assert_eq!(table.get("lines"), Some("one\ntwo"));
+        assert_eq!(table.get("empty"), Some(""));
+        assert_eq!(table.get("# A comment"), None);
+        assert!(StringTable::parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_string_table_lines() {
+        match StringTable::parse("ok = fine\nnot a pair") {
+            Err(Error::InvalidStringTable(message)) => assert!(message.contains("line 2")),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert!(matches!(
+            StringTable::parse(" = no key"),
+            Err(Error::InvalidStringTable(_))
+        ));
+    }
+
+    #[test]
+    fn looks_up_plural_forms_and_falls_back() {
+        let mut localization = localization(&[
+            (
+                "en",
+                "coins.one = {count} coin\ncoins.other = {count} coins\ntitle = Title",
+            ),
+            (
+                "ru",
+                "coins.one = {count} монета\ncoins.few = {count} монеты\ncoins.other = монет",
+            ),
+        ]);
+        let coins = |n: i32| LocalizedText::new("coins").arg("count", n);
+        assert_eq!(localization.text(&coins(1)), "1 coin");
+        assert_eq!(localization.text(&coins(2)), "2 coins");
+
+        localization.language = "ru".to_string();
+        assert_eq!(localization.text(&coins(21)), "21 монета");
+        assert_eq!(localization.text(&coins(3)), "3 монеты");
+        // Without a `many` form the `other` form is used.
+        assert_eq!(localization.text(&coins(5)), "монет");
+        // Keys missing from Russian come from English, and from neither as
+        // the key itself.
+        assert_eq!(localization.get("title"), "Title");
+        assert_eq!(localization.get("missing"), "missing");
+    }
+}
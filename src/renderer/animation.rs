@@ -0,0 +1,99 @@
+use super::render_text::{GlyphEffect, RenderText};
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use wgpu_glyph::{Extra, SectionGlyph};
+
+// How many times a second shaking characters jump to a new offset.
+const SHAKE_RATE: f32 = 20.0;
+
+// Drops the glyphs a typewriter reveal hasn't reached yet.
+pub(crate) fn reveal(text: &RenderText, time: Duration, glyphs: &mut Vec<SectionGlyph>) {
+    let revealed = match text.animation.revealed(time) {
+        Some(revealed) => revealed,
+        None => return,
+    };
+
+    let texts = text.texts();
+    // The number of characters before each span.
+    let starts: Vec<usize> = texts
+        .iter()
+        .scan(0, |count, text| {
+            let start = *count;
+            *count += text.chars().count();
+            Some(start)
+        })
+        .collect();
+
+    glyphs.retain(|glyph| {
+        let before = texts[glyph.section_index][..glyph.byte_index]
+            .chars()
+            .count();
+        starts[glyph.section_index] + before < revealed
+    });
+}
+
+// Moves and recolors glyphs for the text's effects. With any effects each
+// glyph gets an `Extra` of its own, so rainbows can color them one by one.
+pub(crate) fn apply_effects(
+    text: &RenderText,
+    time: Duration,
+    glyphs: &mut [SectionGlyph],
+    extras: Vec<Extra>,
+) -> Vec<Extra> {
+    if text.animation.effects.is_empty() {
+        return extras;
+    }
+
+    let seconds = text.animation.elapsed(time).as_secs_f32();
+    glyphs
+        .iter_mut()
+        .enumerate()
+        .map(|(index, glyph)| {
+            let mut extra = extras[glyph.section_index];
+            let phase = index as f32;
+            for effect in text.animation.effects.iter() {
+                match *effect {
+                    GlyphEffect::Wave { amplitude, speed } => {
+                        let angle = 2.0 * PI * speed * seconds - phase * 0.5;
+                        glyph.glyph.position.y += amplitude * angle.sin();
+                    }
+                    GlyphEffect::Shake { intensity } => {
+                        let step = (seconds * SHAKE_RATE) as u32;
+                        glyph.glyph.position.x += intensity * noise(index as u32, step, 0);
+                        glyph.glyph.position.y += intensity * noise(index as u32, step, 1);
+                    }
+                    GlyphEffect::Rainbow { speed } => {
+                        let hue = (speed * seconds + phase / 12.0).fract();
+                        let [r, g, b] = hue_to_rgb(hue);
+                        extra.color = [r, g, b, extra.color[3]];
+                    }
+                }
+            }
+            glyph.section_index = index;
+            extra
+        })
+        .collect()
+}
+
+// A repeatable pseudo random number between -1 and 1.
+fn noise(index: u32, step: u32, axis: u32) -> f32 {
+    let mut hash = index
+        .wrapping_mul(0x9E37_79B1)
+        .wrapping_add(step.wrapping_mul(0x85EB_CA77))
+        .wrapping_add(axis.wrapping_mul(0xC2B2_AE3D));
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2C1B_3C6D);
+    hash ^= hash >> 12;
+    (hash & 0xFFFF) as f32 / 32767.5 - 1.0
+}
+
+// Fully saturated colors around the color wheel, `hue` going from 0 to 1.
+fn hue_to_rgb(hue: f32) -> [f32; 3] {
+    let channel = |offset: f32| {
+        let position = (hue * 6.0 + offset) % 6.0;
+        (2.0 - (position - 3.0).abs()).clamp(0.0, 1.0)
+    };
+    [channel(3.0), channel(1.0), channel(5.0)]
+}
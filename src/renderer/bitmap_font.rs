@@ -0,0 +1,594 @@
+use super::layout::TextLayout;
+use super::render_text::{FontId, WrapMode};
+use crate::assets::{Asset, AssetManager, Handle, Texture};
+use crate::error::{Error, Result};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use wgpu_glyph::ab_glyph::{point, Glyph, GlyphId};
+use wgpu_glyph::{HorizontalAlign, Section, SectionGlyph, VerticalAlign};
+
+// Page ids are checked against this before anything is allocated for them,
+// as well as against the page count the description gives.
+const MAX_PAGES: usize = 256;
+
+// Where a character is on its page and how it sits on the line, in pixels
+// at the font's own size.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BitmapGlyph {
+    pub page: usize,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub advance: f32,
+}
+
+// A hand drawn font in AngelCode's BMFont format, a text description of
+// where each character is on one or more page images.
+pub struct BitmapFont {
+    size: f32,
+    line_height: f32,
+    base: f32,
+    pages: Vec<Handle<Texture>>,
+    glyphs: Vec<BitmapGlyph>,
+    ids: HashMap<char, GlyphId>,
+    kernings: HashMap<(char, char), f32>,
+}
+
+impl BitmapFont {
+    // Loads a text format `.fnt` file along with the page images it names,
+    // which are looked for next to it.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let description = std::fs::read_to_string(path)?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::from_parts(&description, |file| {
+            Texture::from_bytes(std::fs::read(directory.join(file))?)
+        })
+    }
+
+    // Loads a `.fnt` file and its pages through `assets`. Pages are kept as
+    // handles, so edits to them show up while hot reloading.
+    pub fn load_asset<P: AsRef<Path>>(assets: &mut AssetManager, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let description = assets.load::<String, _>(path)?.get();
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::from_pages(&description, |file| assets.load(directory.join(file)))
+    }
+
+    // Builds a font from the contents of a `.fnt` file, calling `load_page`
+    // with the file name of each page image.
+    pub fn from_parts<F>(description: &str, mut load_page: F) -> Result<Self>
+    where
+        F: FnMut(&str) -> Result<Texture>,
+    {
+        Self::from_pages(description, |file| Ok(Handle::from_value(load_page(file)?)))
+    }
+
+    fn from_pages<F>(description: &str, mut load_page: F) -> Result<Self>
+    where
+        F: FnMut(&str) -> Result<Handle<Texture>>,
+    {
+        let mut font = Self {
+            size: 0.0,
+            line_height: 0.0,
+            base: 0.0,
+            pages: Vec::new(),
+            glyphs: Vec::new(),
+            ids: HashMap::new(),
+            kernings: HashMap::new(),
+        };
+        let mut page_files = Vec::new();
+        let mut page_count = MAX_PAGES;
+
+        for line in description.lines() {
+            let (tag, fields) = parse_line(line);
+            let number = |name: &str| -> Result<f32> {
+                fields
+                    .get(name)
+                    .and_then(|value| value.parse().ok())
+                    .ok_or_else(|| invalid(&format!("{} line is missing {}", tag, name)))
+            };
+            let character = |name: &str| -> Result<char> {
+                std::char::from_u32(number(name)? as u32)
+                    .ok_or_else(|| invalid(&format!("{} isn't a character", name)))
+            };
+
+            match tag {
+                // Negative sizes mean the size was given as a character
+                // height rather than a cell height, either works here.
+                "info" => font.size = number("size")?.abs(),
+                "common" => {
+                    font.line_height = number("lineHeight")?;
+                    font.base = number("base")?;
+                    if fields.contains_key("pages") {
+                        page_count = number("pages")? as usize;
+                        if page_count > MAX_PAGES {
+                            return Err(invalid("too many pages"));
+                        }
+                    }
+                }
+                "page" => {
+                    let id = number("id")? as usize;
+                    if id >= page_count {
+                        return Err(invalid(&format!("page {} is past the last page", id)));
+                    }
+                    let file = fields
+                        .get("file")
+                        .ok_or_else(|| invalid("page line is missing file"))?;
+                    if page_files.len() <= id {
+                        page_files.resize(id + 1, String::new());
+                    }
+                    page_files[id] = file.to_string();
+                }
+                "char" => {
+                    let id = GlyphId(font.glyphs.len() as u16);
+                    font.ids.insert(character("id")?, id);
+                    font.glyphs.push(BitmapGlyph {
+                        page: number("page")? as usize,
+                        x: number("x")?,
+                        y: number("y")?,
+                        width: number("width")?,
+                        height: number("height")?,
+                        offset_x: number("xoffset")?,
+                        offset_y: number("yoffset")?,
+                        advance: number("xadvance")?,
+                    });
+                }
+                "kerning" => {
+                    let pair = (character("first")?, character("second")?);
+                    font.kernings.insert(pair, number("amount")?);
+                }
+                _ => {}
+            }
+        }
+
+        if font.size <= 0.0 || font.line_height <= 0.0 {
+            return Err(invalid("missing info or common line"));
+        }
+        if font.glyphs.len() > u16::MAX as usize {
+            return Err(invalid("too many characters"));
+        }
+        for file in page_files {
+            font.pages.push(load_page(&file)?);
+        }
+        if let Some(glyph) = font
+            .glyphs
+            .iter()
+            .find(|glyph| glyph.page >= font.pages.len())
+        {
+            return Err(invalid(&format!("no page {}", glyph.page)));
+        }
+        Ok(font)
+    }
+
+    // The size the font was drawn at. Text at multiples of it stays crisp.
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn line_height(&self) -> f32 {
+        self.line_height
+    }
+
+    pub(crate) fn glyph(&self, id: GlyphId) -> &BitmapGlyph {
+        &self.glyphs[id.0 as usize]
+    }
+
+    // How much a glyph drawn `scale` pixels high is scaled from the pages.
+    pub(crate) fn scale(&self, scale: f32) -> f32 {
+        scale / self.size
+    }
+
+    // The advance, ascent and descent of a glyph at `scale`, the same as an
+    // outline font would give.
+    pub(crate) fn metrics(&self, id: GlyphId, scale: f32) -> (f32, f32, f32) {
+        let scale = self.scale(scale);
+        (
+            self.glyph(id).advance * scale,
+            self.base * scale,
+            (self.base - self.line_height) * scale,
+        )
+    }
+
+    // Where a laid out glyph is drawn on screen and which part of which page
+    // it's drawn from. None for glyphs with nothing to draw, like spaces.
+    pub(crate) fn quad(&self, glyph: &Glyph) -> Option<BitmapQuad<'_>> {
+        let bitmap_glyph = self.glyph(glyph.id);
+        if bitmap_glyph.width <= 0.0 || bitmap_glyph.height <= 0.0 {
+            return None;
+        }
+        let scale = self.scale(glyph.scale.y);
+        let left = glyph.position.x + bitmap_glyph.offset_x * scale;
+        let top = glyph.position.y + (bitmap_glyph.offset_y - self.base) * scale;
+        Some(BitmapQuad {
+            page: &self.pages[bitmap_glyph.page],
+            min: (left, top),
+            max: (
+                left + bitmap_glyph.width * scale,
+                top + bitmap_glyph.height * scale,
+            ),
+            tex_min: (bitmap_glyph.x, bitmap_glyph.y),
+            tex_max: (
+                bitmap_glyph.x + bitmap_glyph.width,
+                bitmap_glyph.y + bitmap_glyph.height,
+            ),
+        })
+    }
+}
+
+pub(crate) struct BitmapQuad<'a> {
+    pub page: &'a Handle<Texture>,
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+    pub tex_min: (f32, f32),
+    pub tex_max: (f32, f32),
+}
+
+fn parse_line(line: &str) -> (&str, HashMap<&str, &str>) {
+    let line = line.trim();
+    let (tag, mut rest) = match line.find(' ') {
+        Some(space) => (&line[..space], &line[space..]),
+        None => (line, ""),
+    };
+
+    let mut fields = HashMap::new();
+    loop {
+        rest = rest.trim_start();
+        let equals = match rest.find('=') {
+            Some(equals) => equals,
+            None => break,
+        };
+        let name = &rest[..equals];
+        rest = &rest[equals + 1..];
+
+        // Quoted values like file names can hold spaces.
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            rest = quoted.get(end + 1..).unwrap_or("");
+            &quoted[..end]
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            let value = &rest[..end];
+            rest = &rest[end..];
+            value
+        };
+        fields.insert(name, value);
+    }
+    (tag, fields)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidBitmapFont(message.to_string())
+}
+
+// A glyph placed on a line, left aligned from the start of the line.
+struct Placed {
+    glyph: SectionGlyph,
+    right: f32,
+    space: bool,
+}
+
+// Lays out text drawn entirely in bitmap fonts the way `TextLayout` lays out
+// outline fonts, since glyph brush can only lay out outline fonts.
+pub(crate) fn layout<'a, F>(section: &Section, layout: &TextLayout, font: F) -> Vec<SectionGlyph>
+where
+    F: Fn(FontId) -> Option<&'a BitmapFont>,
+{
+    let (x, y) = section.screen_position;
+    let (bound_w, bound_h) = section.bounds;
+    let wrap_width = match layout.wrap {
+        WrapMode::Truncate => f32::INFINITY,
+        _ => bound_w,
+    };
+
+    let mut lines: Vec<Vec<Placed>> = vec![Vec::new()];
+    let mut caret = 0.0;
+    let mut previous: Option<(FontId, char)> = None;
+    for (section_index, text) in section.text.iter().enumerate() {
+        let bitmap_font = match font(text.font_id) {
+            Some(bitmap_font) => bitmap_font,
+            None => continue,
+        };
+        let scale = bitmap_font.scale(text.scale.y);
+
+        for (byte_index, character) in text.text.char_indices() {
+            if character == '\n' {
+                lines.push(Vec::new());
+                caret = 0.0;
+                previous = None;
+                continue;
+            }
+            let id = match bitmap_font.ids.get(&character) {
+                Some(&id) => id,
+                None => continue,
+            };
+
+            let kerning = match previous {
+                Some((font_id, before)) if font_id == text.font_id => bitmap_font
+                    .kernings
+                    .get(&(before, character))
+                    .copied()
+                    .unwrap_or(0.0),
+                _ => 0.0,
+            };
+            let mut left = caret + kerning * scale;
+            let advance = bitmap_font.glyph(id).advance * scale;
+            let space = character.is_whitespace();
+
+            let line = lines.last_mut().unwrap();
+            if !space && left + advance > wrap_width && !line.is_empty() {
+                // Words move to the next line whole unless they're too long
+                // for a line of their own.
+                let mut carried = match layout.wrap {
+                    WrapMode::Word => match line.iter().rposition(|placed| placed.space) {
+                        Some(space) => line.split_off(space + 1),
+                        None => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                };
+                let shift = carried
+                    .first()
+                    .map_or(0.0, |placed| placed.glyph.glyph.position.x);
+                for placed in carried.iter_mut() {
+                    placed.glyph.glyph.position.x -= shift;
+                    placed.right -= shift;
+                }
+                left = carried.last().map_or(0.0, |placed| placed.right);
+                lines.push(carried);
+            }
+
+            lines.last_mut().unwrap().push(Placed {
+                glyph: SectionGlyph {
+                    section_index,
+                    byte_index,
+                    font_id: text.font_id,
+                    glyph: Glyph {
+                        id,
+                        scale: text.scale,
+                        position: point(left, 0.0),
+                    },
+                },
+                right: left + advance,
+                space,
+            });
+            caret = left + advance;
+            previous = Some((text.font_id, character));
+        }
+    }
+
+    // Lines without glyphs take their height from the first font.
+    let first_font = section
+        .text
+        .iter()
+        .find_map(|text| font(text.font_id).map(|bitmap_font| (bitmap_font, text.scale.y)));
+    let (empty_height, empty_base) = first_font.map_or((0.0, 0.0), |(bitmap_font, scale)| {
+        let scale = bitmap_font.scale(scale);
+        (bitmap_font.line_height * scale, bitmap_font.base * scale)
+    });
+
+    let mut out = Vec::new();
+    let mut top = 0.0;
+    let mut bottom: f32 = 0.0;
+    for mut line in lines {
+        if top >= bound_h {
+            break;
+        }
+
+        let (height, base) =
+            line.iter()
+                .fold((empty_height, empty_base), |(height, base), placed| {
+                    let bitmap_font = font(placed.glyph.font_id).unwrap();
+                    let scale = bitmap_font.scale(placed.glyph.glyph.scale.y);
+                    (
+                        height.max(bitmap_font.line_height * scale),
+                        base.max(bitmap_font.base * scale),
+                    )
+                });
+
+        if layout.wrap == WrapMode::Truncate && line_width(&line) > bound_w {
+            truncate(&mut line, bound_w, &font);
+        }
+        let width = line_width(&line);
+        let left = match layout.h_align {
+            HorizontalAlign::Left => x,
+            HorizontalAlign::Center => x - width / 2.0,
+            HorizontalAlign::Right => x - width,
+        };
+
+        for placed in line {
+            let mut glyph = placed.glyph;
+            glyph.glyph.position.x += left;
+            glyph.glyph.position.y = top + base;
+            out.push(glyph);
+        }
+        bottom = top + height;
+        top += height * layout.line_spacing;
+    }
+
+    let shift = match layout.v_align {
+        VerticalAlign::Top => y,
+        VerticalAlign::Center => y - bottom / 2.0,
+        VerticalAlign::Bottom => y - bottom,
+    };
+    for glyph in out.iter_mut() {
+        glyph.glyph.position.y += shift;
+    }
+    out
+}
+
+// Trailing spaces don't count, so wrapped lines still line up on the right.
+fn line_width(line: &[Placed]) -> f32 {
+    line.iter()
+        .rev()
+        .find(|placed| !placed.space)
+        .map_or(0.0, |placed| placed.right)
+}
+
+// Cuts the line to fit in `width` with a trailing ellipsis, or three periods
+// for fonts without one.
+fn truncate<'a, F>(line: &mut Vec<Placed>, width: f32, font: &F)
+where
+    F: Fn(FontId) -> Option<&'a BitmapFont>,
+{
+    let first = &line[0].glyph;
+    let bitmap_font = font(first.font_id).unwrap();
+    let (ellipsis, count) = match bitmap_font.ids.get(&'…') {
+        Some(&id) => (id, 1),
+        None => match bitmap_font.ids.get(&'.') {
+            Some(&id) => (id, 3),
+            None => return line.retain(|placed| placed.right <= width),
+        },
+    };
+    let advance = bitmap_font.glyph(ellipsis).advance * bitmap_font.scale(first.glyph.scale.y);
+
+    let fits = line
+        .iter()
+        .take_while(|placed| placed.right + advance * count as f32 <= width)
+        .count();
+    let cut = line.get(fits).unwrap_or(&line[0]).glyph.clone();
+    line.truncate(fits);
+
+    let mut caret = line.last().map_or(0.0, |placed| placed.right);
+    for _ in 0..count {
+        let mut glyph = cut.clone();
+        glyph.glyph.id = ellipsis;
+        glyph.glyph.position.x = caret;
+        caret += advance;
+        line.push(Placed {
+            glyph,
+            right: caret,
+            space: false,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str = r#"info face="Pixel Sans" size=-16 bold=0
+common lineHeight=18 base=14 scaleW=64 scaleH=64 pages=2
+page id=0 file="pixel sans_0.png"
+page id=1 file=pixel_1.png
+chars count=2
+char id=65 x=1 y=2 width=8 height=10 xoffset=1 yoffset=4 xadvance=9 page=0
+char id=32 x=0 y=0 width=0 height=0 xoffset=0 yoffset=0 xadvance=4 page=1
+kernings count=1
+kerning first=65 second=32 amount=-1
+"#;
+
+    fn font(description: &str) -> Result<(BitmapFont, Vec<String>)> {
+        let mut files = Vec::new();
+        let font = BitmapFont::from_parts(description, |file| {
+            files.push(file.to_string());
+            Ok(Texture::from_rgba(1, 1, vec![0; 4]))
+        })?;
+        Ok((font, files))
+    }
+
+    fn glyph(id: GlyphId, x: f32, scale: f32) -> Glyph {
+        Glyph {
+            id,
+            scale: scale.into(),
+            position: point(x, 20.0),
+        }
+    }
+
+    #[test]
+    fn parses_plain_and_quoted_fields() {
+        let (tag, fields) = parse_line(r#"  page id=0 file="my font 0.png"  "#);
+        assert_eq!(tag, "page");
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields["id"], "0");
+        assert_eq!(fields["file"], "my font 0.png");
+
+        let (tag, fields) = parse_line(r#"info face="" size=12"#);
+        assert_eq!(tag, "info");
+        assert_eq!(fields["face"], "");
+        assert_eq!(fields["size"], "12");
+    }
+
+    #[test]
+    fn parses_lines_without_fields() {
+        let (tag, fields) = parse_line("chars");
+        assert_eq!(tag, "chars");
+        assert!(fields.is_empty());
+
+        let (tag, fields) = parse_line("");
+        assert_eq!(tag, "");
+        assert!(fields.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_rest_of_an_unterminated_quote() {
+        let (_, fields) = parse_line(r#"page id=1 file="open ended.png"#);
+        assert_eq!(fields["id"], "1");
+        assert_eq!(fields["file"], "open ended.png");
+    }
+
+    #[test]
+    fn builds_a_font_from_its_parts() {
+        let (font, files) = font(DESCRIPTION).unwrap();
+        assert_eq!(files, vec!["pixel sans_0.png", "pixel_1.png"]);
+        // Negative sizes are character heights.
+        assert_eq!(font.size(), 16.0);
+        assert_eq!(font.line_height(), 18.0);
+        assert_eq!(font.pages.len(), 2);
+        assert_eq!(font.kernings[&('A', ' ')], -1.0);
+
+        let a = font.ids[&'A'];
+        assert_eq!(font.glyph(a).page, 0);
+        // Twice the font's size doubles everything.
+        assert_eq!(font.metrics(a, 32.0), (18.0, 28.0, -8.0));
+
+        let quad = font.quad(&glyph(a, 10.0, 32.0)).unwrap();
+        assert_eq!(quad.min, (12.0, 0.0));
+        assert_eq!(quad.max, (28.0, 20.0));
+        assert_eq!((quad.tex_min, quad.tex_max), ((1.0, 2.0), (9.0, 12.0)));
+        // Spaces have nothing to draw.
+        assert!(font.quad(&glyph(font.ids[&' '], 0.0, 16.0)).is_none());
+    }
+
+    #[test]
+    fn rejects_incomplete_descriptions() {
+        let without_common = DESCRIPTION.replace("common", "uncommon");
+        assert!(font(&without_common).is_err());
+
+        let missing_field = DESCRIPTION.replace(" xadvance=9", "");
+        assert!(font(&missing_field).is_err());
+
+        let missing_page = DESCRIPTION.replace("page=1", "page=2");
+        assert!(font(&missing_page).is_err());
+
+        let bad_character = DESCRIPTION.replace("char id=65", "char id=55296");
+        assert!(font(&bad_character).is_err());
+    }
+
+    #[test]
+    fn rejects_page_ids_past_the_page_count() {
+        let past_count = DESCRIPTION.replace("page id=1", "page id=2");
+        assert!(font(&past_count).is_err());
+
+        let huge_id = DESCRIPTION.replace("page id=1", "page id=1000000000");
+        assert!(font(&huge_id).is_err());
+        let too_many = DESCRIPTION.replace("pages=2", "pages=1000000000");
+        assert!(font(&too_many).is_err());
+
+        // Without a page count, ids are only capped.
+        let uncounted = DESCRIPTION.replace(" pages=2", "");
+        assert!(font(&uncounted).is_ok());
+        let huge_id = uncounted.replace("page id=1", "page id=1000000000");
+        assert!(font(&huge_id).is_err());
+    }
+
+    #[test]
+    fn passes_on_page_errors() {
+        let result = BitmapFont::from_parts(DESCRIPTION, |_| Err(invalid("no pages here")));
+        assert!(matches!(result, Err(Error::InvalidBitmapFont(_))));
+    }
+}
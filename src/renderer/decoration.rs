@@ -0,0 +1,191 @@
+use super::create_render_pipeline;
+use crate::assets::Shader;
+use crate::util::GrowableBuffer;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct DecorationVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for DecorationVertex {}
+unsafe impl bytemuck::Zeroable for DecorationVertex {}
+
+impl DecorationVertex {
+    const SIZE: wgpu::BufferAddress = std::mem::size_of::<Self>() as wgpu::BufferAddress;
+    const DESC: wgpu::VertexBufferDescriptor<'static> = wgpu::VertexBufferDescriptor {
+        stride: Self::SIZE,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float2,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float4,
+            },
+        ],
+    };
+}
+
+// Solid colored rectangles drawn over text, like underlines. Rectangles are
+// given in pixels and converted to clip space as they're pushed.
+pub(crate) struct Decorations {
+    vertices: Vec<DecorationVertex>,
+    indices: Vec<u32>,
+    screen_size: (f32, f32),
+}
+
+impl Decorations {
+    pub fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            screen_size: (1.0, 1.0),
+        }
+    }
+
+    pub fn reset(&mut self, screen_size: (f32, f32)) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.screen_size = screen_size;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn push_rect(&mut self, min: (f32, f32), max: (f32, f32), color: [f32; 4]) {
+        let (width, height) = self.screen_size;
+        let to_clip = |x: f32, y: f32| [x / width * 2.0 - 1.0, 1.0 - y / height * 2.0];
+
+        let first = self.vertices.len() as u32;
+        self.vertices.extend(
+            [
+                to_clip(min.0, min.1),
+                to_clip(max.0, min.1),
+                to_clip(max.0, max.1),
+                to_clip(min.0, max.1),
+            ]
+            .iter()
+            .map(|&position| DecorationVertex { position, color }),
+        );
+        self.indices
+            .extend([0, 1, 2, 0, 2, 3].iter().map(|index| first + index));
+    }
+}
+
+pub(crate) struct DecorationPipeline {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    vertex_buffer: GrowableBuffer,
+    index_buffer: GrowableBuffer,
+}
+
+impl DecorationPipeline {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+            label: Some("Decoration Pipeline Layout"),
+        });
+        let pipeline = create_pipeline(
+            device,
+            &layout,
+            color_format,
+            wgpu::include_spirv!("../../res/shaders/decoration.vert.spv"),
+            wgpu::include_spirv!("../../res/shaders/decoration.frag.spv"),
+        );
+
+        Self {
+            pipeline,
+            layout,
+            color_format,
+            vertex_buffer: GrowableBuffer::new(
+                device,
+                "Decoration Vertex Buffer",
+                wgpu::BufferUsage::VERTEX,
+            ),
+            index_buffer: GrowableBuffer::new(
+                device,
+                "Decoration Index Buffer",
+                wgpu::BufferUsage::INDEX,
+            ),
+        }
+    }
+
+    pub fn set_shaders(&mut self, device: &wgpu::Device, vertex: &Shader, fragment: &Shader) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.layout,
+            self.color_format,
+            vertex.module_source(),
+            fragment.module_source(),
+        );
+    }
+
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        target: &wgpu::TextureView,
+        decorations: &Decorations,
+    ) {
+        if decorations.is_empty() {
+            return;
+        }
+
+        self.vertex_buffer.write(
+            device,
+            encoder,
+            staging_belt,
+            bytemuck::cast_slice(&decorations.vertices),
+        );
+        self.index_buffer.write(
+            device,
+            encoder,
+            staging_belt,
+            bytemuck::cast_slice(&decorations.indices),
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.buffer().slice(..));
+        render_pass.set_index_buffer(self.index_buffer.buffer().slice(..));
+        render_pass.draw_indexed(0..decorations.indices.len() as u32, 0, 0..1);
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    vs_src: wgpu::ShaderModuleSource,
+    fs_src: wgpu::ShaderModuleSource,
+) -> wgpu::RenderPipeline {
+    create_render_pipeline(
+        device,
+        "Decoration Pipeline",
+        layout,
+        color_format,
+        &[DecorationVertex::DESC],
+        vs_src,
+        fs_src,
+    )
+}
@@ -0,0 +1,88 @@
+use super::render_text::RenderText;
+
+use wgpu_glyph::Extra;
+
+// Offsets around a square rather than a circle, so outlines of pixel fonts
+// stay crisp at the corners.
+const RING: [(f32, f32); 8] = [
+    (-1.0, -1.0),
+    (0.0, -1.0),
+    (1.0, -1.0),
+    (-1.0, 0.0),
+    (1.0, 0.0),
+    (-1.0, 1.0),
+    (0.0, 1.0),
+    (1.0, 1.0),
+];
+
+// Each glow ring draws the text eight more times, so wide glows spread a few
+// rings out rather than drawing one per pixel.
+const MAX_GLOW_RINGS: f32 = 4.0;
+
+// A copy of a text's glyphs drawn behind them, moved by `offset` pixels.
+pub(crate) struct EffectLayer {
+    pub offset: (f32, f32),
+    pub color: [f32; 4],
+}
+
+impl EffectLayer {
+    // Colors every glyph with the layer's color, keeping the text's own
+    // alpha so fading text fades its effects too.
+    pub fn extras(&self, extras: &[Extra]) -> Vec<Extra> {
+        let color = self.color;
+        extras
+            .iter()
+            .map(|extra| Extra {
+                color: [color[0], color[1], color[2], color[3] * extra.color[3]],
+                z: extra.z,
+            })
+            .collect()
+    }
+}
+
+// The layers for the text's glow, shadow and outline, in the order they're
+// drawn. Distance field text outlines in its shader and leaves the outline
+// out.
+pub(crate) fn layers(text: &RenderText, include_outline: bool) -> Vec<EffectLayer> {
+    let mut layers = Vec::new();
+
+    if let Some(glow) = text.glow {
+        // Rings nearer the text overlap the ones further out, so the glow is
+        // strongest against the glyphs and fades towards its edge.
+        let steps = glow.radius.ceil().clamp(1.0, MAX_GLOW_RINGS) as usize;
+        let color = [
+            glow.color.x,
+            glow.color.y,
+            glow.color.z,
+            glow.color.w / steps as f32,
+        ];
+        for step in (1..=steps).rev() {
+            push_ring(&mut layers, glow.radius * step as f32 / steps as f32, color);
+        }
+    }
+
+    if let Some(shadow) = text.shadow {
+        layers.push(EffectLayer {
+            offset: (shadow.offset.x, shadow.offset.y),
+            color: shadow.color.into(),
+        });
+    }
+
+    if let Some(outline) = text.outline.filter(|_| include_outline) {
+        let steps = outline.thickness.ceil().max(1.0) as usize;
+        for step in 1..=steps {
+            let radius = outline.thickness * step as f32 / steps as f32;
+            push_ring(&mut layers, radius, outline.color.into());
+        }
+    }
+    layers
+}
+
+fn push_ring(layers: &mut Vec<EffectLayer>, radius: f32, color: [f32; 4]) {
+    for (x, y) in RING.iter() {
+        layers.push(EffectLayer {
+            offset: (x * radius, y * radius),
+            color,
+        });
+    }
+}
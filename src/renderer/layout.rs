@@ -0,0 +1,182 @@
+use super::render_text::WrapMode;
+use super::shaping::{self, Faces};
+
+use std::hash::{Hash, Hasher};
+
+use glyph_brush::ToSectionText;
+use wgpu_glyph::ab_glyph::{point, Font, Glyph, Rect, ScaleFont};
+use wgpu_glyph::{
+    BuiltInLineBreaker, FontId, GlyphPositioner, HorizontalAlign, Layout, SectionGeometry,
+    SectionGlyph, VerticalAlign,
+};
+
+// Positions text like glyph brush's built in `Layout`, adding shaping, font
+// fallback, line spacing and lines that are cut short with an ellipsis
+// instead of wrapping.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TextLayout {
+    pub h_align: HorizontalAlign,
+    pub v_align: VerticalAlign,
+    pub wrap: WrapMode,
+    pub line_spacing: f32,
+    pub fallbacks: Vec<FontId>,
+    // Left out of the hash, the glyph brush is rebuilt when fonts change.
+    pub faces: Faces,
+}
+
+impl Hash for TextLayout {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.h_align.hash(state);
+        self.v_align.hash(state);
+        self.wrap.hash(state);
+        self.line_spacing.to_bits().hash(state);
+        self.fallbacks.hash(state);
+    }
+}
+
+impl GlyphPositioner for TextLayout {
+    fn calculate_glyphs<F, S>(
+        &self,
+        fonts: &[F],
+        geometry: &SectionGeometry,
+        sections: &[S],
+    ) -> Vec<SectionGlyph>
+    where
+        F: Font,
+        S: ToSectionText,
+    {
+        let (x, y) = geometry.screen_position;
+        let (bound_w, bound_h) = geometry.bounds;
+
+        // Break lines from the origin, then align each line on its own.
+        let (line_breaker, wrap_width) = match self.wrap {
+            WrapMode::Word => (BuiltInLineBreaker::UnicodeLineBreaker, bound_w),
+            WrapMode::Character => (BuiltInLineBreaker::AnyCharLineBreaker, bound_w),
+            WrapMode::Truncate => (BuiltInLineBreaker::UnicodeLineBreaker, f32::INFINITY),
+        };
+        let lines = shaping::shape(
+            fonts,
+            &self.faces,
+            sections,
+            &self.fallbacks,
+            line_breaker,
+            wrap_width,
+        );
+
+        let texts: Vec<_> = sections
+            .iter()
+            .map(|section| section.to_section_text().text)
+            .collect();
+        let is_space = |glyph: &SectionGlyph| {
+            texts[glyph.section_index][glyph.byte_index..]
+                .chars()
+                .next()
+                .map(char::is_whitespace)
+                == Some(true)
+        };
+
+        let mut out = Vec::new();
+        let mut previous_baseline = None;
+        let mut spacing = 0.0;
+        let mut bottom: f32 = 0.0;
+        for line in lines {
+            let (baseline, mut line) = (line.baseline, line.glyphs);
+            if let Some(previous) = previous_baseline {
+                spacing += (self.line_spacing - 1.0) * (baseline - previous);
+            }
+            previous_baseline = Some(baseline);
+
+            let (ascent, descent) = line.iter().fold((0.0, 0.0), |(ascent, descent), glyph| {
+                let font = fonts[glyph.font_id.0].as_scaled(glyph.glyph.scale);
+                (font.ascent().max(ascent), font.descent().min(descent))
+            });
+            let baseline = baseline + spacing;
+            if baseline - ascent >= bound_h {
+                break;
+            }
+
+            if self.wrap == WrapMode::Truncate && line_width(fonts, &line, is_space) > bound_w {
+                truncate(fonts, &mut line, bound_w, baseline - spacing);
+            }
+            let width = line_width(fonts, &line, is_space);
+            let left = match self.h_align {
+                HorizontalAlign::Left => x,
+                HorizontalAlign::Center => x - width / 2.0,
+                HorizontalAlign::Right => x - width,
+            };
+
+            for glyph in &mut line {
+                glyph.glyph.position.x += left;
+                glyph.glyph.position.y += spacing;
+            }
+            bottom = baseline - descent;
+            out.extend(line);
+        }
+
+        let top = match self.v_align {
+            VerticalAlign::Top => y,
+            VerticalAlign::Center => y - bottom / 2.0,
+            VerticalAlign::Bottom => y - bottom,
+        };
+        for glyph in &mut out {
+            glyph.glyph.position.y += top;
+        }
+        out
+    }
+
+    fn bounds_rect(&self, geometry: &SectionGeometry) -> Rect {
+        Layout::default_wrap()
+            .h_align(self.h_align)
+            .v_align(self.v_align)
+            .bounds_rect(geometry)
+    }
+}
+
+fn right_edge<F: Font>(fonts: &[F], glyph: &SectionGlyph) -> f32 {
+    let font = fonts[glyph.font_id.0].as_scaled(glyph.glyph.scale);
+    glyph.glyph.position.x + font.h_advance(glyph.glyph.id)
+}
+
+// Trailing spaces don't count, so wrapped lines still line up on the right.
+fn line_width<F, W>(fonts: &[F], line: &[SectionGlyph], is_space: W) -> f32
+where
+    F: Font,
+    W: Fn(&SectionGlyph) -> bool,
+{
+    line.iter()
+        .rev()
+        .find(|glyph| !is_space(glyph))
+        .map_or(0.0, |glyph| right_edge(fonts, glyph))
+}
+
+// Drops the glyphs that don't fit in `width` along with an ellipsis, then adds
+// the ellipsis. Fonts without a `…` get three periods.
+fn truncate<F: Font>(fonts: &[F], line: &mut Vec<SectionGlyph>, width: f32, baseline: f32) {
+    let first = line[0].clone();
+    let font = fonts[first.font_id.0].as_scaled(first.glyph.scale);
+    let (ellipsis, count) = match font.glyph_id('…') {
+        id if id.0 != 0 => (id, 1),
+        _ => (font.glyph_id('.'), 3),
+    };
+    let advance = font.h_advance(ellipsis);
+
+    let fits = line
+        .iter()
+        .take_while(|glyph| right_edge(fonts, glyph) + advance * count as f32 <= width)
+        .count();
+    let cut = line.get(fits).cloned().unwrap_or(first);
+    line.truncate(fits);
+
+    let mut caret = line.last().map_or(0.0, |glyph| right_edge(fonts, glyph));
+    for _ in 0..count {
+        line.push(SectionGlyph {
+            glyph: Glyph {
+                id: ellipsis,
+                scale: cut.glyph.scale,
+                position: point(caret, baseline),
+            },
+            ..cut.clone()
+        });
+        caret += advance;
+    }
+}
@@ -0,0 +1,226 @@
+use super::render_text::{FontId, TextSpan};
+
+// Splits inline markup into spans. Supported tags, which nest:
+//
+//     [color=red]...[/color]      a color name, #rrggbb or #rrggbbaa
+//     [size=24]...[/size]
+//     [font=body]...[/font]       a name registered with the text renderer
+//     [u]...[/u]                  underline
+//
+// `[[` writes a literal bracket. Tags that can't be parsed are kept as text.
+pub(crate) fn parse<F>(markup: &str, base: &TextSpan, font: F) -> Vec<TextSpan>
+where
+    F: Fn(&str) -> Option<FontId>,
+{
+    let mut spans = Vec::new();
+    // Each open tag with the style it applies, innermost last.
+    let mut stack: Vec<(&str, TextSpan)> = Vec::new();
+    let mut text = String::new();
+    let mut rest = markup;
+
+    while let Some(open) = rest.find('[') {
+        text.push_str(&rest[..open]);
+        rest = &rest[open..];
+
+        if rest.starts_with("[[") {
+            text.push('[');
+            rest = &rest[2..];
+            continue;
+        }
+
+        let close = match rest.find(']') {
+            Some(close) => close,
+            None => break,
+        };
+        let tag = &rest[1..close];
+        let style = stack.last().map_or(base, |(_, style)| style);
+
+        let change = if let Some(name) = tag.strip_prefix('/') {
+            stack
+                .iter()
+                .rposition(|(open, _)| *open == name)
+                .map(Change::Close)
+        } else {
+            apply_tag(tag, style, &font).map(Change::Open)
+        };
+
+        match change {
+            Some(change) => {
+                flush(&mut spans, &mut text, style);
+                match change {
+                    Change::Open((name, style)) => stack.push((name, style)),
+                    Change::Close(index) => stack.truncate(index),
+                }
+            }
+            None => text.push_str(&rest[..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+
+    text.push_str(rest);
+    let style = stack.last().map_or(base, |(_, style)| style);
+    flush(&mut spans, &mut text, style);
+    spans
+}
+
+enum Change<'a> {
+    Open((&'a str, TextSpan)),
+    Close(usize),
+}
+
+fn flush(spans: &mut Vec<TextSpan>, text: &mut String, style: &TextSpan) {
+    if !text.is_empty() {
+        spans.push(TextSpan {
+            text: std::mem::take(text),
+            ..style.clone()
+        });
+    }
+}
+
+fn apply_tag<'a, F>(tag: &'a str, style: &TextSpan, font: &F) -> Option<(&'a str, TextSpan)>
+where
+    F: Fn(&str) -> Option<FontId>,
+{
+    let (name, value) = match tag.find('=') {
+        Some(equals) => (&tag[..equals], Some(tag[equals + 1..].trim())),
+        None => (tag, None),
+    };
+
+    let mut style = TextSpan {
+        text: String::new(),
+        ..style.clone()
+    };
+    match (name, value) {
+        ("color", Some(value)) => style.color = parse_color(value)?.into(),
+        ("size", Some(value)) => {
+            style.size = value.parse().ok().filter(|&size: &f32| size > 0.0)?
+        }
+        ("font", Some(value)) => style.font = font(value)?,
+        ("u", None) => style.underline = true,
+        _ => return None,
+    }
+    Some((name, style))
+}
+
+pub(crate) fn parse_color(value: &str) -> Option<[f32; 4]> {
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |index: usize| {
+            let digits = hex.get(index * 2..index * 2 + 2)?;
+            u8::from_str_radix(digits, 16)
+                .ok()
+                .map(|value| value as f32 / 255.0)
+        };
+        return match hex.len() {
+            6 => Some([channel(0)?, channel(1)?, channel(2)?, 1.0]),
+            8 => Some([channel(0)?, channel(1)?, channel(2)?, channel(3)?]),
+            _ => None,
+        };
+    }
+
+    let color = match value.to_ascii_lowercase().as_str() {
+        "white" => [1.0, 1.0, 1.0, 1.0],
+        "black" => [0.0, 0.0, 0.0, 1.0],
+        "red" => [1.0, 0.0, 0.0, 1.0],
+        "green" => [0.0, 1.0, 0.0, 1.0],
+        "blue" => [0.0, 0.0, 1.0, 1.0],
+        "yellow" => [1.0, 1.0, 0.0, 1.0],
+        "cyan" => [0.0, 1.0, 1.0, 1.0],
+        "magenta" => [1.0, 0.0, 1.0, 1.0],
+        "orange" => [1.0, 0.5, 0.0, 1.0],
+        "gray" | "grey" => [0.5, 0.5, 0.5, 1.0],
+        _ => return None,
+    };
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(markup: &str) -> Vec<TextSpan> {
+        parse(markup, &TextSpan::default(), |name| match name {
+            "body" => Some(FontId(2)),
+            _ => None,
+        })
+    }
+
+    fn texts(spans: &[TextSpan]) -> Vec<&str> {
+        spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    #[test]
+    fn plain_text_is_one_span() {
+        let spans = spans("hello world");
+        assert_eq!(spans, vec![TextSpan::new("hello world")]);
+        assert!(self::spans("").is_empty());
+    }
+
+    #[test]
+    fn tags_style_their_text() {
+        let spans = spans("a [color=red]b[/color] [size=24]c[/size] [font=body]d[/font] [u]e[/u]");
+        assert_eq!(texts(&spans), vec!["a ", "b", " ", "c", " ", "d", " ", "e"]);
+        assert_eq!(spans[1].color, (1.0, 0.0, 0.0, 1.0).into());
+        assert_eq!(spans[3].size, 24.0);
+        assert_eq!(spans[5].font, FontId(2));
+        assert!(spans[7].underline);
+        assert_eq!(spans[2], TextSpan::new(" "));
+    }
+
+    #[test]
+    fn tags_nest() {
+        let spans = spans("[color=#00ff00]a[u]b[/u]c[/color]");
+        assert_eq!(texts(&spans), vec!["a", "b", "c"]);
+        assert!(spans
+            .iter()
+            .all(|span| span.color == (0.0, 1.0, 0.0, 1.0).into()));
+        assert_eq!(
+            spans.iter().map(|span| span.underline).collect::<Vec<_>>(),
+            vec![false, true, false]
+        );
+    }
+
+    #[test]
+    fn closing_an_outer_tag_closes_the_inner_ones() {
+        let spans = spans("[size=20][u]a[/size]b");
+        assert_eq!(texts(&spans), vec!["a", "b"]);
+        assert_eq!(spans[1], TextSpan::new("b"));
+    }
+
+    #[test]
+    fn double_brackets_are_literal() {
+        let spans = spans("[[u]] [[[u]a[/u]");
+        assert_eq!(texts(&spans), vec!["[u]] [", "a"]);
+        assert!(!spans[0].underline);
+        assert!(spans[1].underline);
+    }
+
+    #[test]
+    fn bad_tags_are_kept_as_text() {
+        for markup in &[
+            "[color=nope]a",
+            "[size=-1]a",
+            "[size=big]a",
+            "[font=missing]a",
+            "[u=1]a",
+            "[b]a",
+            "[/u]a",
+            "[u a",
+        ] {
+            assert_eq!(spans(markup), vec![TextSpan::new(*markup)], "{}", markup);
+        }
+    }
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(parse_color("#ff8000"), Some([1.0, 128.0 / 255.0, 0.0, 1.0]));
+        assert_eq!(
+            parse_color("#00000080"),
+            Some([0.0, 0.0, 0.0, 128.0 / 255.0])
+        );
+        assert_eq!(parse_color("Grey"), Some([0.5, 0.5, 0.5, 1.0]));
+        assert_eq!(parse_color("#fff"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+        assert_eq!(parse_color("#ff000é"), None);
+        assert_eq!(parse_color("purple"), None);
+    }
+}
@@ -0,0 +1,155 @@
+use std::ops::Range;
+
+use cgmath::Vector2;
+use wgpu_glyph::{Section, SectionGlyph};
+
+// Where a character ends up once laid out, all in pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphMetrics {
+    // Index of the span the character came from, 0 for plain text.
+    pub span: usize,
+    // Byte index of the character within the span's text.
+    pub byte_index: usize,
+    pub line: usize,
+    // The left end of the glyph's baseline.
+    pub position: Vector2<f32>,
+    pub advance: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMetrics {
+    pub baseline: f32,
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+    // The line's glyphs in `TextMetrics::glyphs`.
+    pub glyphs: Range<usize>,
+}
+
+impl LineMetrics {
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f32 {
+        self.bottom - self.top
+    }
+}
+
+// The layout `draw_text` would use for a piece of text, without drawing it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextMetrics {
+    pub min: Vector2<f32>,
+    pub max: Vector2<f32>,
+    pub lines: Vec<LineMetrics>,
+    pub glyphs: Vec<GlyphMetrics>,
+}
+
+impl TextMetrics {
+    pub fn size(&self) -> Vector2<f32> {
+        self.max - self.min
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max.x - self.min.x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max.y - self.min.y
+    }
+
+    // The top of a text cursor placed before the character at `byte_index`
+    // of `span`, and the cursor's height. Indexes past the end of the span
+    // put the cursor after its last character.
+    pub fn caret(&self, span: usize, byte_index: usize) -> Option<(Vector2<f32>, f32)> {
+        let at_or_after = self
+            .glyphs
+            .iter()
+            .find(|glyph| glyph.span == span && glyph.byte_index >= byte_index);
+        let (line, x) = match at_or_after {
+            Some(glyph) => (glyph.line, glyph.position.x),
+            None => {
+                let last = self.glyphs.iter().rev().find(|glyph| glyph.span <= span)?;
+                (last.line, last.position.x + last.advance)
+            }
+        };
+
+        let line = &self.lines[line];
+        Some((Vector2::new(x, line.top), line.height()))
+    }
+}
+
+// Groups laid out glyphs into lines, with `metrics` giving each glyph's
+// advance, ascent and descent.
+pub(crate) fn measure<F>(
+    section: &Section,
+    positioned: Vec<SectionGlyph>,
+    metrics: F,
+) -> TextMetrics
+where
+    F: Fn(&SectionGlyph) -> (f32, f32, f32),
+{
+    let mut lines: Vec<LineMetrics> = Vec::new();
+    let mut glyphs = Vec::with_capacity(positioned.len());
+    for positioned in positioned {
+        let glyph = &positioned.glyph;
+        let (advance, ascent, descent) = metrics(&positioned);
+        let left = glyph.position.x;
+        let right = left + advance;
+        let mut baseline = glyph.position.y;
+
+        match lines.last_mut() {
+            // Marks shaped above or below the baseline stay on their line.
+            Some(line) if (baseline - line.baseline).abs() < line.height() / 2.0 => {
+                baseline = line.baseline;
+                let (top, bottom) = (baseline - ascent, baseline - descent);
+                line.top = line.top.min(top);
+                line.bottom = line.bottom.max(bottom);
+                line.left = line.left.min(left);
+                line.right = line.right.max(right);
+                line.glyphs.end += 1;
+            }
+            _ => {
+                let index = glyphs.len();
+                lines.push(LineMetrics {
+                    baseline,
+                    top: baseline - ascent,
+                    bottom: baseline - descent,
+                    left,
+                    right,
+                    glyphs: index..index + 1,
+                });
+            }
+        }
+
+        glyphs.push(GlyphMetrics {
+            span: positioned.section_index,
+            byte_index: positioned.byte_index,
+            line: lines.len() - 1,
+            position: Vector2::new(left, baseline),
+            advance: right - left,
+        });
+    }
+
+    let origin = Vector2::from(section.screen_position);
+    let (min, max) = lines.iter().fold((None, None), |(min, max), line| {
+        let line_min = Vector2::new(line.left, line.top);
+        let line_max = Vector2::new(line.right, line.bottom);
+        (
+            Some(min.map_or(line_min, |min: Vector2<f32>| {
+                Vector2::new(min.x.min(line_min.x), min.y.min(line_min.y))
+            })),
+            Some(max.map_or(line_max, |max: Vector2<f32>| {
+                Vector2::new(max.x.max(line_max.x), max.y.max(line_max.y))
+            })),
+        )
+    });
+
+    TextMetrics {
+        min: min.unwrap_or(origin),
+        max: max.unwrap_or(origin),
+        lines,
+        glyphs,
+    }
+}
@@ -1,16 +1,38 @@
+mod animation;
+mod bitmap_font;
+mod decoration;
+mod effects;
+mod layout;
+mod markup;
+mod measure;
 pub mod render_text;
+mod sdf;
+mod shaping;
+mod sprite;
 
+use crate::assets::{AssetManager, Handle, Shader};
+use crate::error::Result;
+use crate::geometry::camera::Camera;
 use crate::geometry::vertex::*;
-use crate::geometry::Geometry;
+use crate::geometry::{Geometry, QuadBuffers, RetainedBuffers};
+use crate::util::write_buffer;
+pub use bitmap_font::BitmapFont;
+use decoration::{DecorationPipeline, Decorations};
+pub use measure::{GlyphMetrics, LineMetrics, TextMetrics};
 use render_text::*;
+use sdf::SdfPipeline;
+use sprite::SpritePipeline;
 
 use std::iter;
+use std::path::PathBuf;
 
-use wgpu_glyph::{ab_glyph, Section, Text};
+use futures::executor::{LocalPool, LocalSpawner};
+use futures::task::SpawnExt;
+use wgpu::util::DeviceExt;
+use wgpu_glyph::ab_glyph;
+use wgpu_glyph::{GlyphCruncher, GlyphPositioner, Section, SectionGeometry, SectionGlyph};
 use winit::window::Window;
 
-const FONT_BYTES: &[u8] = include_bytes!("../../res/fonts/PressStart2P-Regular.ttf");
-
 pub struct Renderer {
     surface: wgpu::Surface,
     device: wgpu::Device,
@@ -19,10 +41,25 @@ pub struct Renderer {
     swap_chain: wgpu::SwapChain,
     size: winit::dpi::PhysicalSize<u32>,
     pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
+    pipeline_layout: wgpu::PipelineLayout,
+    quad_buffers: QuadBuffers,
+    retained_buffers: RetainedBuffers,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
     glyph_brush: wgpu_glyph::GlyphBrush<()>,
+    // How many of the text renderer's fonts the glyph brush has been given,
+    // and the text renderer's fonts version when it was given them.
+    fonts_added: usize,
+    fonts_version: u64,
+    decoration_pipeline: DecorationPipeline,
+    decorations: Decorations,
+    sdf_pipeline: SdfPipeline,
+    sprite_pipeline: SpritePipeline,
     staging_belt: wgpu::util::StagingBelt,
+    // Runs the staging belt's recalls, which finish once the GPU is done
+    // with last frame's chunks.
+    local_pool: LocalPool,
+    local_spawner: LocalSpawner,
 }
 
 impl Renderer {
@@ -71,38 +108,60 @@ impl Renderer {
         };
         let swap_chain = device.create_swap_chain(&surface, &sc_desc);
 
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Camera Bind Group Layout"),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&camera_uniform(&Camera::default())),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(camera_buffer.slice(..)),
+            }],
+        });
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            bind_group_layouts: &[],
+            bind_group_layouts: &[&camera_bind_group_layout],
             push_constant_ranges: &[],
             label: Some("Pipeline Layout"),
         });
-        let pipeline = create_render_pipeline(
+        let pipeline = create_quad_pipeline(
             &device,
             &pipeline_layout,
             sc_desc.format,
-            &[Vertex::DESC],
             wgpu::include_spirv!("../../res/shaders/textured.vert.spv"),
             wgpu::include_spirv!("../../res/shaders/textured.frag.spv"),
         );
 
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: 0,
-            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: 0,
-            usage: wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let quad_buffers = QuadBuffers::new(&device);
+        let retained_buffers = RetainedBuffers::new(&device);
 
+        // Only until the first frame, which rebuilds the brush with the text
+        // renderer's fonts if they've changed, the default font included.
         let font = ab_glyph::FontArc::try_from_slice(FONT_BYTES).unwrap();
         let glyph_brush =
             wgpu_glyph::GlyphBrushBuilder::using_font(font).build(&device, sc_desc.format);
         let staging_belt = wgpu::util::StagingBelt::new(1024);
+        let local_pool = LocalPool::new();
+        let local_spawner = local_pool.spawner();
+        let decoration_pipeline = DecorationPipeline::new(&device, sc_desc.format);
+        let sdf_pipeline = SdfPipeline::new(&device, sc_desc.format);
+        let sprite_pipeline = SpritePipeline::new(&device, sc_desc.format);
 
         Self {
             surface,
@@ -112,10 +171,22 @@ impl Renderer {
             swap_chain,
             size,
             pipeline,
-            vertex_buffer,
-            index_buffer,
+            pipeline_layout,
+            quad_buffers,
+            retained_buffers,
+            camera_buffer,
+            camera_bind_group,
             glyph_brush,
+            // The built in font.
+            fonts_added: 1,
+            fonts_version: 0,
+            decoration_pipeline,
+            decorations: Decorations::new(),
+            sdf_pipeline,
+            sprite_pipeline,
             staging_belt,
+            local_pool,
+            local_spawner,
         }
     }
 
@@ -126,34 +197,57 @@ impl Renderer {
         self.swap_chain = self.device.create_swap_chain(&self.surface, &self.sc_desc);
     }
 
-    pub fn render(&mut self, geometry: &Geometry, text_renderer: &TextRenderer) {
+    // Swaps the shaders one of the pipelines draws with.
+    pub(crate) fn set_shaders(&mut self, program: Program, vertex: &Shader, fragment: &Shader) {
+        let device = &self.device;
+        match program {
+            Program::Quad => {
+                self.pipeline = create_quad_pipeline(
+                    device,
+                    &self.pipeline_layout,
+                    self.sc_desc.format,
+                    vertex.module_source(),
+                    fragment.module_source(),
+                )
+            }
+            Program::Sdf => self.sdf_pipeline.set_shaders(device, vertex, fragment),
+            Program::Sprite => self.sprite_pipeline.set_shaders(device, vertex, fragment),
+            Program::Decoration => self
+                .decoration_pipeline
+                .set_shaders(device, vertex, fragment),
+        }
+    }
+
+    pub fn render(&mut self, geometry: &mut Geometry, text_renderer: &TextRenderer) {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Renderer Encoder"),
             });
 
-        self.vertex_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: Vertex::SIZE * 4 * (geometry.num_quads as u64),
-            usage: wgpu::BufferUsage::VERTEX | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        self.index_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: U32_SIZE * 6 * (geometry.num_quads as u64),
-            usage: wgpu::BufferUsage::INDEX | wgpu::BufferUsage::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let (stg_vertex, stg_index, num_indices) = geometry.build(&self.device);
-
-        stg_vertex.copy_to_buffer(&mut encoder, &self.vertex_buffer);
-        stg_index.copy_to_buffer(&mut encoder, &self.index_buffer);
-
         match self.swap_chain.get_current_frame() {
             Ok(frame) => {
+                geometry.upload(
+                    &self.device,
+                    &mut encoder,
+                    &mut self.staging_belt,
+                    &mut self.quad_buffers,
+                );
+                geometry.sync_retained(
+                    &self.device,
+                    &mut encoder,
+                    &mut self.staging_belt,
+                    &mut self.retained_buffers,
+                );
+                write_buffer(
+                    &mut self.staging_belt,
+                    &mut encoder,
+                    &self.device,
+                    &self.camera_buffer,
+                    0,
+                    bytemuck::cast_slice(&camera_uniform(&geometry.camera)),
+                );
+
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
                         attachment: &frame.output.view,
@@ -163,17 +257,44 @@ impl Renderer {
                     depth_stencil_attachment: None,
                 });
 
-                if num_indices != 0 {
-                    render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-                    render_pass.set_index_buffer(self.index_buffer.slice(..));
-                    render_pass.set_pipeline(&self.pipeline);
-                    render_pass.draw_indexed(0..num_indices, 0, 0..1);
-                }
+                render_pass.set_pipeline(&self.pipeline);
+                render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+                self.retained_buffers.draw(&mut render_pass);
+                self.quad_buffers.draw(&mut render_pass);
 
                 drop(render_pass);
 
+                // Glyph brush ids count up in the order fonts are added,
+                // matching the ids the text renderer handed out. Fonts can't
+                // be swapped in a glyph brush, so a reloaded font means
+                // building a new one, and dropping SDF glyphs from the old.
+                let fonts = text_renderer.fonts();
+                if text_renderer.fonts_version() != self.fonts_version {
+                    self.glyph_brush = wgpu_glyph::GlyphBrushBuilder::using_fonts(fonts.to_vec())
+                        .build(&self.device, self.sc_desc.format);
+                    self.sdf_pipeline.clear_glyphs();
+                    self.fonts_version = text_renderer.fonts_version();
+                } else {
+                    for font in &fonts[self.fonts_added..] {
+                        self.glyph_brush.add_font(font.clone());
+                    }
+                }
+                self.fonts_added = fonts.len();
+
+                let screen_size = (self.width(), self.height());
+                self.decorations.reset(screen_size);
+                self.sdf_pipeline.reset(screen_size);
+                self.sprite_pipeline.reset(screen_size);
                 for render_text in text_renderer.render_texts.iter() {
-                    draw_text(render_text, &mut self.glyph_brush);
+                    draw_text(
+                        render_text,
+                        text_renderer,
+                        screen_size,
+                        &mut self.glyph_brush,
+                        &mut self.sdf_pipeline,
+                        &mut self.sprite_pipeline,
+                        &mut self.decorations,
+                    );
                 }
 
                 self.glyph_brush
@@ -186,6 +307,27 @@ impl Renderer {
                         self.sc_desc.height,
                     )
                     .unwrap();
+                self.sdf_pipeline.draw(
+                    &self.device,
+                    &self.queue,
+                    &mut encoder,
+                    &mut self.staging_belt,
+                    &frame.output.view,
+                );
+                self.sprite_pipeline.draw(
+                    &self.device,
+                    &self.queue,
+                    &mut encoder,
+                    &mut self.staging_belt,
+                    &frame.output.view,
+                );
+                self.decoration_pipeline.draw(
+                    &self.device,
+                    &mut encoder,
+                    &mut self.staging_belt,
+                    &frame.output.view,
+                    &self.decorations,
+                );
 
                 self.staging_belt.finish();
 
@@ -198,11 +340,51 @@ impl Renderer {
                 eprintln!("Error: {}", e);
             }
         }
+
+        // Chunks come back to the belt once the GPU is done with them, so
+        // the same staging memory is used frame after frame.
+        self.local_spawner
+            .spawn(self.staging_belt.recall())
+            .expect("Recall staging belt");
+        self.local_pool.run_until_stalled();
     }
 }
 
+// The camera as the quad vertex shader's uniform block lays it out.
+fn camera_uniform(camera: &Camera) -> [f32; 4] {
+    [camera.position.x, camera.position.y, camera.zoom, 0.0]
+}
+
+fn create_quad_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    vs_src: wgpu::ShaderModuleSource,
+    fs_src: wgpu::ShaderModuleSource,
+) -> wgpu::RenderPipeline {
+    create_render_pipeline(
+        device,
+        "Render Pipeline",
+        layout,
+        color_format,
+        &[Vertex::DESC],
+        vs_src,
+        fs_src,
+    )
+}
+
+const ALPHA_BLEND: wgpu::BlendDescriptor = wgpu::BlendDescriptor {
+    src_factor: wgpu::BlendFactor::SrcAlpha,
+    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+    operation: wgpu::BlendOperation::Add,
+};
+
+// Builds a pipeline drawing indexed triangle lists straight to the swap
+// chain, blended over what's already there. Shared by all of the renderer's
+// pipelines, so quads and text blend the same way.
 fn create_render_pipeline(
     device: &wgpu::Device,
+    label: &str,
     layout: &wgpu::PipelineLayout,
     color_format: wgpu::TextureFormat,
     vertex_descs: &[wgpu::VertexBufferDescriptor],
@@ -213,8 +395,8 @@ fn create_render_pipeline(
     let fs_module = device.create_shader_module(fs_src);
 
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&layout),
+        label: Some(label),
+        layout: Some(layout),
         vertex_stage: wgpu::ProgrammableStageDescriptor {
             module: &vs_module,
             entry_point: "main",
@@ -227,8 +409,8 @@ fn create_render_pipeline(
         primitive_topology: wgpu::PrimitiveTopology::TriangleList,
         color_states: &[wgpu::ColorStateDescriptor {
             format: color_format,
-            color_blend: wgpu::BlendDescriptor::REPLACE,
-            alpha_blend: wgpu::BlendDescriptor::REPLACE,
+            color_blend: ALPHA_BLEND,
+            alpha_blend: ALPHA_BLEND,
             write_mask: wgpu::ColorWrite::ALL,
         }],
         depth_stencil_state: None,
@@ -242,27 +424,261 @@ fn create_render_pipeline(
     })
 }
 
-fn draw_text(text: &RenderText, glyph_brush: &mut wgpu_glyph::GlyphBrush<()>) {
-    let layout = wgpu_glyph::Layout::default().h_align(if text.centered {
-        wgpu_glyph::HorizontalAlign::Center
+fn draw_text(
+    text: &RenderText,
+    text_renderer: &TextRenderer,
+    screen_size: (f32, f32),
+    glyph_brush: &mut wgpu_glyph::GlyphBrush<()>,
+    sdf_pipeline: &mut SdfPipeline,
+    sprite_pipeline: &mut SpritePipeline,
+    decorations: &mut Decorations,
+) {
+    let time = text_renderer.time();
+    let section = text.section(screen_size, text_renderer.camera(), time);
+    let layout = text_renderer.text_layout(text);
+    let geometry = SectionGeometry {
+        screen_position: section.screen_position,
+        bounds: section.bounds,
+    };
+    let bounds = layout.bounds_rect(&geometry);
+
+    let bitmap = text_renderer.is_bitmap_text(&section);
+    let mut glyphs: Vec<_> = if bitmap {
+        text_renderer.bitmap_glyphs(&section, &layout)
     } else {
-        wgpu_glyph::HorizontalAlign::Left
-    });
-
-    let section =
-        Section {
-            screen_position: text.position.into(),
-            bounds: text.bounds.into(),
-            layout,
-            ..Section::default()
+        glyph_brush
+            .glyphs_custom_layout(&section, &layout)
+            .cloned()
+            .collect()
+    };
+    animation::reveal(text, time, &mut glyphs);
+    if text.spans.iter().any(|span| span.underline) {
+        let advance = |glyph: &SectionGlyph| text_renderer.glyph_metrics(glyph, bitmap).0;
+        underline_spans(&section, &glyphs, &text.spans, advance, decorations);
+    }
+
+    let extras = section.text.iter().map(|text| text.extra).collect();
+    let extras = animation::apply_effects(text, time, &mut glyphs, extras);
+
+    // Bitmap fonts draw from their pages, outlines included.
+    if bitmap {
+        for layer in effects::layers(text, true) {
+            let copy = offset_glyphs(&glyphs, layer.offset);
+            queue_bitmap_glyphs(
+                text_renderer,
+                &copy,
+                &layer.extras(&extras),
+                sprite_pipeline,
+            );
+        }
+        queue_bitmap_glyphs(text_renderer, &glyphs, &extras, sprite_pipeline);
+        return;
+    }
+
+    // Glyphs of distance field fonts go to their own pipeline, which draws
+    // after the glyph brush.
+    let (sdf_glyphs, glyphs): (Vec<_>, Vec<_>) = glyphs
+        .into_iter()
+        .partition(|glyph| text_renderer.font_rendering(glyph.font_id) == FontRendering::Sdf);
+
+    if !sdf_glyphs.is_empty() {
+        let fonts = text_renderer.fonts();
+        for layer in effects::layers(text, false) {
+            let copy = offset_glyphs(&sdf_glyphs, layer.offset);
+            sdf_pipeline.queue(fonts, &copy, &layer.extras(&extras), None);
+        }
+        sdf_pipeline.queue(fonts, &sdf_glyphs, &extras, text.outline);
+    }
+
+    if !glyphs.is_empty() {
+        for layer in effects::layers(text, true) {
+            let copy = offset_glyphs(&glyphs, layer.offset);
+            glyph_brush.queue_pre_positioned(copy, layer.extras(&extras), bounds);
         }
-        .add_text(Text::new(&text.text).with_color(text.color).with_scale(
-            if text.focused {
-                text.size + 8.0
-            } else {
-                text.size
-            },
-        ));
-
-    glyph_brush.queue(section);
+        glyph_brush.queue_pre_positioned(glyphs, extras, bounds);
+    }
+}
+
+fn offset_glyphs(glyphs: &[SectionGlyph], offset: (f32, f32)) -> Vec<SectionGlyph> {
+    glyphs
+        .iter()
+        .map(|glyph| {
+            let mut glyph = glyph.clone();
+            glyph.glyph.position.x += offset.0;
+            glyph.glyph.position.y += offset.1;
+            glyph
+        })
+        .collect()
+}
+
+fn queue_bitmap_glyphs(
+    text_renderer: &TextRenderer,
+    glyphs: &[SectionGlyph],
+    extras: &[wgpu_glyph::Extra],
+    sprite_pipeline: &mut SpritePipeline,
+) {
+    for glyph in glyphs {
+        let quad = text_renderer
+            .bitmap_font(glyph.font_id)
+            .and_then(|font| font.quad(&glyph.glyph));
+        if let Some(quad) = quad {
+            let color = extras[glyph.section_index].color;
+            sprite_pipeline.push_quad(
+                quad.page,
+                quad.min,
+                quad.max,
+                quad.tex_min,
+                quad.tex_max,
+                color,
+            );
+        }
+    }
+}
+
+// Draws a line under each underlined span, one per line the span wraps onto.
+fn underline_spans<F>(
+    section: &Section,
+    glyphs: &[SectionGlyph],
+    spans: &[TextSpan],
+    advance: F,
+    decorations: &mut Decorations,
+) where
+    F: Fn(&SectionGlyph) -> f32,
+{
+    // The span, baseline, thickness and horizontal extent of the current line.
+    let mut line: Option<(usize, f32, f32, f32, f32)> = None;
+    let mut finish = |line: Option<(usize, f32, f32, f32, f32)>| {
+        if let Some((span, baseline, thickness, left, right)) = line {
+            let top = baseline + thickness;
+            let color = section.text[span].extra.color;
+            decorations.push_rect((left, top), (right, top + thickness), color);
+        }
+    };
+
+    for glyph in glyphs {
+        if !spans[glyph.section_index].underline {
+            continue;
+        }
+        let left = glyph.glyph.position.x;
+        let right = left + advance(glyph);
+        let baseline = glyph.glyph.position.y;
+
+        match line.as_mut() {
+            Some((span, line_baseline, _, _, line_right))
+                if *span == glyph.section_index
+                    && (*line_baseline - baseline).abs() < glyph.glyph.scale.y / 2.0 =>
+            {
+                *line_right = right;
+            }
+            _ => {
+                finish(line.take());
+                let thickness = (glyph.glyph.scale.y / 16.0).max(1.0);
+                line = Some((glyph.section_index, baseline, thickness, left, right));
+            }
+        }
+    }
+    finish(line);
+}
+
+// The shader programs the renderer draws with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Program {
+    Quad,
+    Sdf,
+    Sprite,
+    Decoration,
+}
+
+impl Program {
+    const ALL: [Program; 4] = [
+        Program::Quad,
+        Program::Sdf,
+        Program::Sprite,
+        Program::Decoration,
+    ];
+
+    // The name of the program's `.vert` and `.frag` files in `shaders`.
+    fn name(self) -> &'static str {
+        match self {
+            Program::Quad => "textured",
+            Program::Sdf => "sdf",
+            Program::Sprite => "sprite",
+            Program::Decoration => "decoration",
+        }
+    }
+}
+
+// A program's shaders and the versions its pipeline was last built from.
+struct WatchedProgram {
+    program: Program,
+    vertex: Handle<Shader>,
+    fragment: Handle<Shader>,
+    versions: (u64, u64),
+}
+
+// Recompiles the renderer's shaders from the GLSL in the `shaders`
+// directory of an asset root whenever they're saved, so they can be tweaked
+// without a rebuild. Programs missing from the root keep their built in
+// shaders.
+pub(crate) struct ShaderReloader {
+    assets: AssetManager,
+    programs: Vec<WatchedProgram>,
+}
+
+impl ShaderReloader {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Result<Self> {
+        let mut assets = AssetManager::new(root);
+        assets.set_hot_reload(true);
+
+        let mut programs = Vec::new();
+        let mut first_error = None;
+        for &program in Program::ALL.iter() {
+            let name = program.name();
+            let shaders = assets
+                .load::<Shader, _>(format!("shaders/{}.vert", name))
+                .and_then(|vertex| {
+                    let fragment = assets.load::<Shader, _>(format!("shaders/{}.frag", name))?;
+                    Ok((vertex, fragment))
+                });
+            match shaders {
+                Ok((vertex, fragment)) => programs.push(WatchedProgram {
+                    program,
+                    vertex,
+                    fragment,
+                    versions: (0, 0),
+                }),
+                Err(e) => {
+                    log::warn!("Not watching the {} shaders: {}", name, e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) if programs.is_empty() => Err(e),
+            _ => Ok(Self { assets, programs }),
+        }
+    }
+
+    pub fn update(&mut self, renderer: &mut Renderer) {
+        self.assets.reload_changed();
+        for error in self.assets.take_errors() {
+            match std::error::Error::source(&error) {
+                Some(cause) => log::error!("{}: {}", error, cause),
+                None => log::error!("{}", error),
+            }
+        }
+
+        for watched in self.programs.iter_mut() {
+            let versions = (watched.vertex.version(), watched.fragment.version());
+            if versions != watched.versions {
+                watched.versions = versions;
+                renderer.set_shaders(
+                    watched.program,
+                    &watched.vertex.get(),
+                    &watched.fragment.get(),
+                );
+            }
+        }
+    }
 }
@@ -1,4 +1,204 @@
+use super::bitmap_font::{self, BitmapFont};
+use super::layout::TextLayout;
+use super::markup;
+use super::shaping::Faces;
+use super::measure::{self, TextMetrics};
+use crate::assets::{AssetManager, Handle};
+use crate::error::Result;
+use crate::geometry::camera::Camera;
+use crate::localization::{LocalizedText, Localization};
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use wgpu_glyph::ab_glyph::{Font, FontArc, ScaleFont};
+pub use wgpu_glyph::{FontId, HorizontalAlign, VerticalAlign};
+use wgpu_glyph::{GlyphPositioner, Section, SectionGeometry, SectionGlyph, Text};
+
+// The default font, under the asset root.
+pub const DEFAULT_FONT_PATH: &str = "fonts/PressStart2P-Regular.ttf";
+// A copy of the default font built in, for when the asset root doesn't have
+// it.
+pub(crate) const FONT_BYTES: &[u8] = include_bytes!("../../res/fonts/PressStart2P-Regular.ttf");
+
 pub const UNBOUNDED_F32: f32 = std::f32::INFINITY;
+// How much focused text grows, and how many times a second it pulses when
+// `TextAnimation::pulse` is set.
+const FOCUS_GROWTH: f32 = 8.0;
+const PULSE_RATE: f32 = 1.5;
+// The font text is drawn in unless it picks another, PressStart2P.
+pub const DEFAULT_FONT: FontId = FontId(0);
+
+// How a font's glyphs are drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontRendering {
+  // Rasterized at each size text is drawn at. Sharpest for text that keeps
+  // its size, and the only choice that keeps pixel fonts blocky.
+  Bitmap,
+  // Rasterized once into a signed distance field and scaled by a shader,
+  // for large or growing text. Outlines are drawn by the shader too.
+  Sdf,
+}
+
+// How lines longer than `RenderText::bounds` are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WrapMode {
+  // Wraps between words, or inside a word too long for a line of its own.
+  Word,
+  // Wraps after whichever character reaches the edge.
+  Character,
+  // Doesn't wrap, lines that don't fit end in an ellipsis instead.
+  Truncate,
+}
+
+// What `RenderText::position` is given in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextSpace {
+  // Pixels from the text's anchor.
+  Screen,
+  // A point in the world, which moves with the camera the same as quads do.
+  // The text keeps its size in pixels unless it scales with the camera's
+  // zoom. Anchors don't apply.
+  World { scale_with_zoom: bool },
+}
+
+// The point of the screen `RenderText::position` is measured from. Pair it
+// with matching alignments to pin text to an edge or corner, e.g.
+// `BottomRight` with right and bottom alignment and a position of (-8, -8).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Anchor {
+  TopLeft,
+  Top,
+  TopRight,
+  Left,
+  Center,
+  Right,
+  BottomLeft,
+  Bottom,
+  BottomRight,
+}
+
+impl Anchor {
+  pub fn point(self, screen_size: (f32, f32)) -> cgmath::Vector2<f32> {
+    let (width, height) = screen_size;
+    let x = match self {
+      Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => 0.0,
+      Anchor::Top | Anchor::Center | Anchor::Bottom => width / 2.0,
+      Anchor::TopRight | Anchor::Right | Anchor::BottomRight => width,
+    };
+    let y = match self {
+      Anchor::TopLeft | Anchor::Top | Anchor::TopRight => 0.0,
+      Anchor::Left | Anchor::Center | Anchor::Right => height / 2.0,
+      Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => height,
+    };
+    (x, y).into()
+  }
+}
+
+// A line around each glyph, `thickness` pixels wide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outline {
+  pub color: cgmath::Vector4<f32>,
+  pub thickness: f32,
+}
+
+// A copy of the text drawn behind it, moved by `offset` pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shadow {
+  pub color: cgmath::Vector4<f32>,
+  pub offset: cgmath::Vector2<f32>,
+}
+
+// A soft halo that fades out over `radius` pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Glow {
+  pub color: cgmath::Vector4<f32>,
+  pub radius: f32,
+}
+
+// Per-character motion or color, see `TextAnimation::effects`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlyphEffect {
+  // Characters bob up and down by `amplitude` pixels, in a wave that rolls
+  // along the text `speed` times a second.
+  Wave { amplitude: f32, speed: f32 },
+  // Characters jitter by up to `intensity` pixels.
+  Shake { intensity: f32 },
+  // Characters cycle through the colors of the rainbow `speed` times a
+  // second, each a little behind the one before it.
+  Rainbow { speed: f32 },
+}
+
+// Animates text with the engine time from `TextRenderer::time`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextAnimation {
+  // Reveals this many characters a second, like a typewriter.
+  pub typewriter: Option<f32>,
+  // The engine time the reveal and effects count from. Setting it to
+  // `TextRenderer::time` when a dialog box opens starts its reveal over.
+  pub started: Duration,
+  pub effects: Vec<GlyphEffect>,
+  // Focused text pulses between its own size and the grown size.
+  pub pulse: bool,
+}
+
+impl TextAnimation {
+  pub fn elapsed(&self, time: Duration) -> Duration {
+    time.checked_sub(self.started).unwrap_or_default()
+  }
+
+  // How many characters a typewriter reveal shows at `time`, or `None`
+  // without one.
+  pub fn revealed(&self, time: Duration) -> Option<usize> {
+    let speed = self.typewriter?;
+    Some((self.elapsed(time).as_secs_f32() * speed.max(0.0)) as usize)
+  }
+}
+
+impl Default for TextAnimation {
+  fn default() -> Self {
+    Self {
+      typewriter: None,
+      started: Duration::from_secs(0),
+      effects: Vec::new(),
+      pulse: false,
+    }
+  }
+}
+
+// A run of text with its own style, for mixing styles within one `RenderText`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSpan {
+  pub text: String,
+  pub color: cgmath::Vector4<f32>,
+  pub size: f32,
+  pub font: FontId,
+  pub underline: bool,
+}
+
+impl TextSpan {
+  pub fn new<S: Into<String>>(text: S) -> Self {
+    Self {
+      text: text.into(),
+      ..Self::default()
+    }
+  }
+}
+
+impl Default for TextSpan {
+  fn default() -> Self {
+    Self {
+      text: String::new(),
+      color: (1.0, 1.0, 1.0, 1.0).into(),
+      size: 16.0,
+      font: DEFAULT_FONT,
+      underline: false,
+    }
+  }
+}
 
 #[derive(Debug, Clone)]
 pub struct RenderText {
@@ -6,33 +206,456 @@ pub struct RenderText {
   pub bounds: cgmath::Vector2<f32>,
   pub color: cgmath::Vector4<f32>,
   pub text: String,
+  // Replaces `text` with the string for the key in the current language,
+  // looked up again whenever the language changes.
+  pub localized: Option<LocalizedText>,
   pub size: f32,
   pub focused: bool,
+  // Which side of `position` the text extends to. Right aligned text ends
+  // at `position`, centered text is centered on it, and so on.
+  pub h_align: HorizontalAlign,
+  pub v_align: VerticalAlign,
+  // Kept so older games still build, setting it is the same as setting
+  // `h_align` to `HorizontalAlign::Center`.
+  #[deprecated(note = "set `h_align` to `HorizontalAlign::Center` instead")]
   pub centered: bool,
+  pub anchor: Anchor,
+  pub space: TextSpace,
+  pub wrap: WrapMode,
+  // Multiplies the distance between lines.
+  pub line_spacing: f32,
+  pub font: FontId,
+  // Tried in order for characters missing from the text's or a span's own
+  // font, say a CJK font behind a Latin one.
+  pub fallback_fonts: Vec<FontId>,
+  // When not empty these are drawn in place of `text`, and `color`, `size`
+  // and `font` are ignored.
+  pub spans: Vec<TextSpan>,
+  // Drawn behind the text, glow first and outline last. Span colors only
+  // carry their alpha over to these.
+  pub outline: Option<Outline>,
+  pub shadow: Option<Shadow>,
+  pub glow: Option<Glow>,
+  pub animation: TextAnimation,
 }
 
 impl Default for RenderText {
+  #[allow(deprecated)]
   fn default() -> Self {
     Self {
       position: (0.0, 0.0).into(),
       bounds: (UNBOUNDED_F32, UNBOUNDED_F32).into(),
       color: (1.0, 1.0, 1.0, 1.0).into(),
       text: String::new(),
+      localized: None,
       size: 16.0,
       focused: false,
+      h_align: HorizontalAlign::Left,
+      v_align: VerticalAlign::Top,
       centered: false,
+      anchor: Anchor::TopLeft,
+      space: TextSpace::Screen,
+      wrap: WrapMode::Word,
+      line_spacing: 1.0,
+      font: DEFAULT_FONT,
+      fallback_fonts: Vec::new(),
+      spans: Vec::new(),
+      outline: None,
+      shadow: None,
+      glow: None,
+      animation: TextAnimation::default(),
+    }
+  }
+}
+
+impl RenderText {
+  // The glyph brush section this text is drawn and measured with, laid out
+  // with `layout` rather than the section's own layout.
+  pub(crate) fn section(
+    &self,
+    screen_size: (f32, f32),
+    camera: &Camera,
+    time: Duration,
+  ) -> Section<'_> {
+    let (position, zoom) = match self.space {
+      TextSpace::Screen => (self.anchor.point(screen_size) + self.position, 1.0),
+      TextSpace::World { scale_with_zoom } => (
+        camera.world_to_screen(self.position, screen_size),
+        if scale_with_zoom { camera.zoom } else { 1.0 },
+      ),
+    };
+
+    let grow = match (self.focused, self.animation.pulse) {
+      (false, _) => 0.0,
+      (true, false) => FOCUS_GROWTH,
+      (true, true) => {
+        let seconds = self.animation.elapsed(time).as_secs_f32();
+        FOCUS_GROWTH * (0.5 - 0.5 * (2.0 * PI * PULSE_RATE * seconds).cos())
+      }
+    };
+
+    let text = if self.spans.is_empty() {
+      vec![Text::new(&self.text)
+        .with_color(self.color)
+        .with_font_id(self.font)
+        .with_scale((self.size + grow) * zoom)]
+    } else {
+      self
+        .spans
+        .iter()
+        .map(|span| {
+          Text::new(&span.text)
+            .with_color(span.color)
+            .with_font_id(span.font)
+            .with_scale((span.size + grow) * zoom)
+        })
+        .collect()
+    };
+
+    Section {
+      screen_position: position.into(),
+      bounds: (self.bounds * zoom).into(),
+      layout: wgpu_glyph::Layout::default(),
+      text,
+    }
+  }
+
+  // Whether a typewriter reveal has shown every character by `time`. Text
+  // without one always has.
+  pub fn is_revealed(&self, time: Duration) -> bool {
+    match self.animation.revealed(time) {
+      Some(revealed) => revealed >= self.texts().iter().map(|text| text.chars().count()).sum(),
+      None => true,
+    }
+  }
+
+  // The text of each span, or of the whole text without spans.
+  pub(crate) fn texts(&self) -> Vec<&str> {
+    if self.spans.is_empty() {
+      vec![&self.text]
+    } else {
+      self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+  }
+
+  #[allow(deprecated)]
+  pub(crate) fn layout(&self) -> TextLayout {
+    TextLayout {
+      h_align: if self.centered {
+        HorizontalAlign::Center
+      } else {
+        self.h_align
+      },
+      v_align: self.v_align,
+      wrap: self.wrap,
+      line_spacing: self.line_spacing,
+      fallbacks: self.fallback_fonts.clone(),
+      faces: Faces::default(),
     }
   }
 }
 
 pub struct TextRenderer {
   pub render_texts: Vec<RenderText>,
+  // Indexed by font id, starting with the built in font.
+  fonts: Vec<FontArc>,
+  // The handles `fonts` were read from, with the version each was read at,
+  // or None when it hadn't loaded yet.
+  font_handles: Vec<(Handle<FontArc>, Option<u64>)>,
+  // The fonts' shaping tables, kept in step with `fonts`.
+  faces: Faces,
+  // Goes up whenever a reload replaces a font, so the renderer knows to
+  // rebuild what it made from the old one.
+  fonts_version: u64,
+  renderings: Vec<FontRendering>,
+  // Bitmap fonts hold the built in font in `fonts`, so ids stay in step
+  // with the glyph brush.
+  bitmap_fonts: HashMap<FontId, Arc<BitmapFont>>,
+  font_names: HashMap<String, FontId>,
+  localization: Localization,
+  // The localization version the texts were last looked up at.
+  localized_version: u64,
+  screen_size: (f32, f32),
+  camera: Camera,
+  time: Duration,
 }
 
 impl TextRenderer {
   pub fn new() -> Self {
+    let default_font = FontArc::try_from_slice(FONT_BYTES).unwrap();
+    let mut faces = Faces::default();
+    faces.set(DEFAULT_FONT, &default_font);
     Self {
       render_texts: Vec::new(),
+      fonts: vec![default_font.clone()],
+      font_handles: vec![(Handle::from_value(default_font), Some(0))],
+      faces,
+      fonts_version: 0,
+      renderings: vec![FontRendering::Bitmap],
+      bitmap_fonts: HashMap::new(),
+      font_names: HashMap::new(),
+      localization: Localization::new(),
+      localized_version: 0,
+      screen_size: (1.0, 1.0),
+      camera: Camera::default(),
+      time: Duration::from_secs(0),
+    }
+  }
+
+  // The size anchors are placed against when measuring text. Kept up to date
+  // with the window by the game loop.
+  pub fn screen_size(&self) -> (f32, f32) {
+    self.screen_size
+  }
+
+  pub(crate) fn set_screen_size(&mut self, screen_size: (f32, f32)) {
+    self.screen_size = screen_size;
+  }
+
+  // The camera world space text is placed with, the geometry's camera as of
+  // the last frame. Kept up to date by the game loop.
+  pub fn camera(&self) -> &Camera {
+    &self.camera
+  }
+
+  pub(crate) fn set_camera(&mut self, camera: Camera) {
+    self.camera = camera;
+  }
+
+  // The time since the game started, which text animations run on.
+  pub fn time(&self) -> Duration {
+    self.time
+  }
+
+  pub(crate) fn set_time(&mut self, time: Duration) {
+    self.time = time;
+  }
+
+  // Draws `DEFAULT_FONT` text in `font` once it loads, rather than the built
+  // in copy of PressStart2P.
+  pub fn set_default_font(&mut self, font: Handle<FontArc>) {
+    self.font_handles[DEFAULT_FONT.0] = (font, None);
+  }
+
+  // Loads the default font from `DEFAULT_FONT_PATH` under the asset root,
+  // keeping the built in copy when it can't be loaded.
+  pub fn load_default_font(&mut self, assets: &mut AssetManager) -> Result<()> {
+    let font = assets.load::<FontArc, _>(DEFAULT_FONT_PATH)?;
+    self.set_default_font(font);
+    self.update_fonts();
+    Ok(())
+  }
+
+  // Registers a font for `RenderText::font`. Adding another font under the
+  // same name points the name at the new font, text already using the old
+  // id keeps drawing with the old font.
+  pub fn add_font(&mut self, name: &str, font: FontArc) -> FontId {
+    self.add_font_asset(name, Handle::from_value(font))
+  }
+
+  // Registers a font loaded through an `AssetManager`, see `add_font`. Text
+  // draws in the built in font until the font loads, and picks up the new
+  // font when it's hot reloaded.
+  pub fn add_font_asset(&mut self, name: &str, font: Handle<FontArc>) -> FontId {
+    let id = FontId(self.fonts.len());
+    let loaded = font.try_get();
+    let version = loaded.as_ref().map(|_| font.version());
+    self.fonts.push(match loaded {
+      Some(loaded) => (*loaded).clone(),
+      None => self.fonts[DEFAULT_FONT.0].clone(),
+    });
+    self.faces.set(id, &self.fonts[id.0]);
+    self.font_handles.push((font, version));
+    self.renderings.push(FontRendering::Bitmap);
+    self.font_names.insert(name.to_string(), id);
+    id
+  }
+
+  // Loads a TTF or OTF file, see `add_font`.
+  pub fn load_font<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<FontId> {
+    let font = FontArc::try_from_vec(std::fs::read(path)?)?;
+    Ok(self.add_font(name, font))
+  }
+
+  // Registers a BMFont for `RenderText::font`, the same as `add_font`. Text
+  // drawn in bitmap fonts is drawn from the font's pages, and only when all
+  // of a text's spans use bitmap fonts; spans mixing them with other fonts
+  // draw the bitmap spans in the built in font.
+  pub fn add_bitmap_font(&mut self, name: &str, font: BitmapFont) -> FontId {
+    let id = self.add_font_asset(name, self.font_handles[DEFAULT_FONT.0].0.clone());
+    self.bitmap_fonts.insert(id, Arc::new(font));
+    id
+  }
+
+  // Loads a text format `.fnt` file and its pages, see `add_bitmap_font`.
+  pub fn load_bitmap_font<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<FontId> {
+    let font = BitmapFont::load(path)?;
+    Ok(self.add_bitmap_font(name, font))
+  }
+
+  pub(crate) fn bitmap_font(&self, font: FontId) -> Option<&BitmapFont> {
+    self.bitmap_fonts.get(&font).map(|font| font.as_ref())
+  }
+
+  // Whether every span of `section` is in a bitmap font, so it's laid out
+  // and drawn from bitmap font pages.
+  pub(crate) fn is_bitmap_text(&self, section: &Section) -> bool {
+    !section.text.is_empty()
+      && section
+        .text
+        .iter()
+        .all(|text| self.bitmap_fonts.contains_key(&text.font_id))
+  }
+
+  // Lays out text in bitmap fonts, see `is_bitmap_text`.
+  pub(crate) fn bitmap_glyphs(&self, section: &Section, layout: &TextLayout) -> Vec<SectionGlyph> {
+    bitmap_font::layout(section, layout, |font| self.bitmap_font(font))
+  }
+
+  // The advance, ascent and descent of a laid out glyph, from its bitmap
+  // font when `bitmap` is set.
+  pub(crate) fn glyph_metrics(&self, glyph: &SectionGlyph, bitmap: bool) -> (f32, f32, f32) {
+    match self.bitmap_font(glyph.font_id) {
+      Some(font) if bitmap => font.metrics(glyph.glyph.id, glyph.glyph.scale.y),
+      _ => {
+        let font = self.fonts[glyph.font_id.0].as_scaled(glyph.glyph.scale);
+        (font.h_advance(glyph.glyph.id), font.ascent(), font.descent())
+      }
+    }
+  }
+
+  pub fn font(&self, name: &str) -> Option<FontId> {
+    self.font_names.get(name).copied()
+  }
+
+  // Fonts start out as `FontRendering::Bitmap`.
+  pub fn set_font_rendering(&mut self, font: FontId, rendering: FontRendering) {
+    if let Some(current) = self.renderings.get_mut(font.0) {
+      *current = rendering;
+    }
+  }
+
+  pub fn font_rendering(&self, font: FontId) -> FontRendering {
+    self
+      .renderings
+      .get(font.0)
+      .copied()
+      .unwrap_or(FontRendering::Bitmap)
+  }
+
+  pub fn localization(&self) -> &Localization {
+    &self.localization
+  }
+
+  pub fn localization_mut(&mut self) -> &mut Localization {
+    &mut self.localization
+  }
+
+  // Switches language, see `Localization::set_language`, and looks up the
+  // localized texts already pushed again.
+  pub fn set_language(&mut self, assets: &mut AssetManager, language: &str) -> Result<()> {
+    self.localization.set_language(assets, language)?;
+    self.update_localized();
+    Ok(())
+  }
+
+  // Looks up localized texts again if the language or its strings changed
+  // since they were last looked up. The game loop calls this every frame.
+  pub(crate) fn update_localized(&mut self) {
+    let version = self.localization.version();
+    if version == self.localized_version {
+      return;
+    }
+    self.localized_version = version;
+    for text in self.render_texts.iter_mut() {
+      if let Some(localized) = &text.localized {
+        text.text = self.localization.text(localized);
+      }
+    }
+  }
+
+  // Turns markup like `[color=red]GAME[/color] OVER` into spans, with `base`
+  // styling any text outside of tags. See `markup::parse` for the tags.
+  pub fn parse_markup(&self, markup: &str, base: &TextSpan) -> Vec<TextSpan> {
+    markup::parse(markup, base, |name| self.font(name))
+  }
+
+  // Lays out `text` the way the renderer would draw it, for sizing UI around
+  // text or placing a cursor in it.
+  pub fn measure(&self, text: &RenderText) -> TextMetrics {
+    if let Some(localized) = &text.localized {
+      let text = RenderText {
+        text: self.localization.text(localized),
+        localized: None,
+        ..text.clone()
+      };
+      return self.measure(&text);
+    }
+
+    let section = text.section(self.screen_size, &self.camera, self.time);
+    let layout = self.text_layout(text);
+    let bitmap = self.is_bitmap_text(&section);
+    let glyphs = if bitmap {
+      self.bitmap_glyphs(&section, &layout)
+    } else {
+      let geometry = SectionGeometry {
+        screen_position: section.screen_position,
+        bounds: section.bounds,
+      };
+      layout.calculate_glyphs(&self.fonts, &geometry, &section.text)
+    };
+    measure::measure(&section, glyphs, |glyph| self.glyph_metrics(glyph, bitmap))
+  }
+
+  // Measures a single run of text, wrapped at `wrap_width` when given.
+  pub fn measure_str(
+    &self,
+    text: &str,
+    font: FontId,
+    size: f32,
+    wrap_width: Option<f32>,
+  ) -> TextMetrics {
+    self.measure(&RenderText {
+      text: text.to_string(),
+      font,
+      size,
+      bounds: (wrap_width.unwrap_or(UNBOUNDED_F32), UNBOUNDED_F32).into(),
+      ..RenderText::default()
+    })
+  }
+
+  pub(crate) fn fonts(&self) -> &[FontArc] {
+    &self.fonts
+  }
+
+  // The layout `text` is drawn and measured with, shaping with the fonts'
+  // tables parsed up front.
+  pub(crate) fn text_layout(&self, text: &RenderText) -> TextLayout {
+    TextLayout {
+      faces: self.faces.clone(),
+      ..text.layout()
+    }
+  }
+
+  pub(crate) fn fonts_version(&self) -> u64 {
+    self.fonts_version
+  }
+
+  // Picks up fonts that finished loading or were hot reloaded since the
+  // last frame. Called by the game loop.
+  pub(crate) fn update_fonts(&mut self) {
+    let fonts = self.fonts.iter_mut().zip(self.font_handles.iter_mut());
+    for (index, (font, (handle, version))) in fonts.enumerate() {
+      let current = handle.version();
+      if *version == Some(current) {
+        continue;
+      }
+      if let Some(loaded) = handle.try_get() {
+        *font = (*loaded).clone();
+        self.faces.set(FontId(index), font);
+        *version = Some(current);
+        self.fonts_version += 1;
+      }
     }
   }
 
@@ -40,7 +663,36 @@ impl TextRenderer {
     self.render_texts.clear();
   }
 
-  pub fn push_render_text(&mut self, text: RenderText) {
+  pub fn push_render_text(&mut self, mut text: RenderText) {
+    if let Some(localized) = &text.localized {
+      text.text = self.localization.text(localized);
+    }
     self.render_texts.push(text);
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn loads_the_default_font_from_the_asset_root() {
+    let mut text_renderer = TextRenderer::new();
+    let mut assets = AssetManager::new(env!("CARGO_MANIFEST_DIR").to_string() + "/res");
+    text_renderer.load_default_font(&mut assets).unwrap();
+
+    let handle = &text_renderer.font_handles[DEFAULT_FONT.0].0;
+    assert_eq!(handle.path(), Path::new(DEFAULT_FONT_PATH));
+    assert_eq!(text_renderer.fonts_version(), 1);
+  }
+
+  #[test]
+  fn keeps_the_built_in_font_without_one() {
+    let mut text_renderer = TextRenderer::new();
+    let mut assets = AssetManager::new("does-not-exist");
+    assert!(text_renderer.load_default_font(&mut assets).is_err());
+
+    assert_eq!(text_renderer.fonts_version(), 0);
+    assert_eq!(text_renderer.fonts[DEFAULT_FONT.0].font_data(), FONT_BYTES);
+  }
+}
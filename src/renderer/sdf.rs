@@ -0,0 +1,542 @@
+use super::create_render_pipeline;
+use super::render_text::{FontId, Outline};
+use crate::assets::Shader;
+use crate::util::GrowableBuffer;
+
+use std::collections::HashMap;
+
+use wgpu_glyph::ab_glyph::{point, Font, FontArc, GlyphId};
+use wgpu_glyph::{Extra, SectionGlyph};
+
+// Glyphs are rasterized once at this size and scaled from there.
+const SDF_SCALE: f32 = 64.0;
+// How far from a glyph's edge distances are recorded, in pixels at
+// `SDF_SCALE`. Outlines can't be thicker than this.
+const SPREAD: f32 = 8.0;
+// The atlas starts out this wide and high, and doubles as it fills up to
+// `MAX_ATLAS_SIZE`.
+const ATLAS_SIZE: u32 = 1024;
+const MAX_ATLAS_SIZE: u32 = 4096;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SdfVertex {
+    position: [f32; 2],
+    tex_coord: [f32; 2],
+    color: [f32; 4],
+    outline_color: [f32; 4],
+    outline_width: f32,
+}
+
+unsafe impl bytemuck::Pod for SdfVertex {}
+unsafe impl bytemuck::Zeroable for SdfVertex {}
+
+impl SdfVertex {
+    const SIZE: wgpu::BufferAddress = std::mem::size_of::<Self>() as wgpu::BufferAddress;
+    const DESC: wgpu::VertexBufferDescriptor<'static> = wgpu::VertexBufferDescriptor {
+        stride: Self::SIZE,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float2,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float2,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Float4,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                shader_location: 3,
+                format: wgpu::VertexFormat::Float4,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                shader_location: 4,
+                format: wgpu::VertexFormat::Float,
+            },
+        ],
+    };
+}
+
+// Where a glyph's distance field is in the atlas, in pixels, and the area it
+// covers around the glyph's origin at `SDF_SCALE`.
+#[derive(Copy, Clone, Debug)]
+struct SdfGlyph {
+    tex_min: [f32; 2],
+    tex_max: [f32; 2],
+    min: [f32; 2],
+    max: [f32; 2],
+}
+
+// Distance fields for every glyph drawn so far, packed into rows.
+struct SdfAtlas {
+    size: u32,
+    pixels: Vec<u8>,
+    // `None` for glyphs without an outline, like spaces.
+    glyphs: HashMap<(FontId, GlyphId), Option<SdfGlyph>>,
+    cursor: (u32, u32),
+    row_height: u32,
+    dirty: bool,
+    full: bool,
+}
+
+impl SdfAtlas {
+    fn new() -> Self {
+        Self {
+            size: ATLAS_SIZE,
+            pixels: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+            glyphs: HashMap::new(),
+            cursor: (0, 0),
+            row_height: 0,
+            dirty: true,
+            full: false,
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    fn glyph(&mut self, font: &FontArc, font_id: FontId, id: GlyphId) -> Option<SdfGlyph> {
+        if let Some(glyph) = self.glyphs.get(&(font_id, id)) {
+            return *glyph;
+        }
+
+        let glyph = match generate(font, id) {
+            Some((width, height, distances, min)) => {
+                // Glyphs that don't fit even once the atlas is as big as it
+                // gets wait for it to be cleared on the next frame.
+                let (x, y) = match self.allocate(width, height) {
+                    Some(position) => position,
+                    None => {
+                        self.full = true;
+                        return None;
+                    }
+                };
+                for row in 0..height {
+                    let start = ((y + row) * self.size + x) as usize;
+                    let source = (row * width) as usize;
+                    self.pixels[start..start + width as usize]
+                        .copy_from_slice(&distances[source..source + width as usize]);
+                }
+                self.dirty = true;
+
+                Some(SdfGlyph {
+                    tex_min: [x as f32, y as f32],
+                    tex_max: [(x + width) as f32, (y + height) as f32],
+                    min,
+                    max: [min[0] + width as f32, min[1] + height as f32],
+                })
+            }
+            None => None,
+        };
+        self.glyphs.insert((font_id, id), glyph);
+        glyph
+    }
+
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if self.cursor.0 + width > self.size {
+            self.cursor = (0, self.cursor.1 + self.row_height);
+            self.row_height = 0;
+        }
+        while self.cursor.0 + width > self.size || self.cursor.1 + height > self.size {
+            if self.size >= MAX_ATLAS_SIZE {
+                return None;
+            }
+            self.grow();
+        }
+
+        let position = self.cursor;
+        // A pixel of space between glyphs keeps filtering from bleeding.
+        self.cursor.0 += width + 1;
+        self.row_height = self.row_height.max(height + 1);
+        Some(position)
+    }
+
+    // Doubles the atlas, keeping every glyph where it is.
+    fn grow(&mut self) {
+        let size = self.size * 2;
+        let mut pixels = vec![0; (size * size) as usize];
+        for (row, source) in self.pixels.chunks(self.size as usize).enumerate() {
+            let start = row * size as usize;
+            pixels[start..start + source.len()].copy_from_slice(source);
+        }
+        self.size = size;
+        self.pixels = pixels;
+        self.dirty = true;
+    }
+}
+
+// Rasterizes a glyph at `SDF_SCALE` and turns its coverage into distances
+// from the edge, 0.5 on the edge and higher inside. Returns the field's size,
+// the field and its top left corner relative to the glyph's origin.
+fn generate(font: &FontArc, id: GlyphId) -> Option<(u32, u32, Vec<u8>, [f32; 2])> {
+    let outlined = font.outline_glyph(id.with_scale_and_position(SDF_SCALE, point(0.0, 0.0)))?;
+    let bounds = outlined.px_bounds();
+    let padding = SPREAD.ceil() as i32;
+    let glyph_width = bounds.width() as i32;
+    let glyph_height = bounds.height() as i32;
+
+    let mut coverage = vec![0.0; (glyph_width * glyph_height) as usize];
+    outlined.draw(|x, y, c| coverage[(y as i32 * glyph_width + x as i32) as usize] = c);
+    let inside = |x: i32, y: i32| {
+        let (x, y) = (x - padding, y - padding);
+        x >= 0
+            && y >= 0
+            && x < glyph_width
+            && y < glyph_height
+            && coverage[(y * glyph_width + x) as usize] >= 0.5
+    };
+
+    let width = glyph_width + padding * 2;
+    let height = glyph_height + padding * 2;
+    let mut distances = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let is_inside = inside(x, y);
+            // The nearest pixel on the other side of the edge.
+            let mut nearest = (SPREAD * SPREAD) as i32;
+            for dy in -padding..=padding {
+                for dx in -padding..=padding {
+                    let squared = dx * dx + dy * dy;
+                    if squared < nearest && inside(x + dx, y + dy) != is_inside {
+                        nearest = squared;
+                    }
+                }
+            }
+
+            let distance = (nearest as f32).sqrt() - 0.5;
+            let signed = if is_inside { distance } else { -distance };
+            let value = (0.5 + signed / (SPREAD * 2.0)).clamp(0.0, 1.0);
+            distances.push((value * 255.0).round() as u8);
+        }
+    }
+
+    let min = [bounds.min.x - padding as f32, bounds.min.y - padding as f32];
+    Some((width as u32, height as u32, distances, min))
+}
+
+// Draws glyphs from signed distance fields, which stay sharp however far
+// they're scaled and can be outlined in the shader.
+pub(crate) struct SdfPipeline {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    // Recreated when the atlas grows.
+    texture: wgpu::Texture,
+    texture_size: u32,
+    bind_group: wgpu::BindGroup,
+    atlas: SdfAtlas,
+    vertices: Vec<SdfVertex>,
+    indices: Vec<u32>,
+    vertex_buffer: GrowableBuffer,
+    index_buffer: GrowableBuffer,
+    screen_size: (f32, f32),
+}
+
+impl SdfPipeline {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("SDF Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                    count: None,
+                },
+            ],
+        });
+        let (texture, bind_group) =
+            create_atlas_texture(device, &bind_group_layout, &sampler, ATLAS_SIZE);
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+            label: Some("SDF Pipeline Layout"),
+        });
+        let pipeline = create_pipeline(
+            device,
+            &layout,
+            color_format,
+            wgpu::include_spirv!("../../res/shaders/sdf.vert.spv"),
+            wgpu::include_spirv!("../../res/shaders/sdf.frag.spv"),
+        );
+
+        Self {
+            pipeline,
+            layout,
+            color_format,
+            bind_group_layout,
+            sampler,
+            texture,
+            texture_size: ATLAS_SIZE,
+            bind_group,
+            atlas: SdfAtlas::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            vertex_buffer: GrowableBuffer::new(
+                device,
+                "SDF Vertex Buffer",
+                wgpu::BufferUsage::VERTEX,
+            ),
+            index_buffer: GrowableBuffer::new(device, "SDF Index Buffer", wgpu::BufferUsage::INDEX),
+            screen_size: (1.0, 1.0),
+        }
+    }
+
+    pub fn set_shaders(&mut self, device: &wgpu::Device, vertex: &Shader, fragment: &Shader) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.layout,
+            self.color_format,
+            vertex.module_source(),
+            fragment.module_source(),
+        );
+    }
+
+    // Forgets every glyph in the atlas, for when the fonts they came from
+    // change.
+    pub fn clear_glyphs(&mut self) {
+        self.atlas.clear();
+    }
+
+    pub fn reset(&mut self, screen_size: (f32, f32)) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.screen_size = screen_size;
+        if self.atlas.full {
+            self.atlas.clear();
+        }
+    }
+
+    // Queues glyphs colored by `extras`, indexed by each glyph's section.
+    pub fn queue(
+        &mut self,
+        fonts: &[FontArc],
+        glyphs: &[SectionGlyph],
+        extras: &[Extra],
+        outline: Option<Outline>,
+    ) {
+        let (width, height) = self.screen_size;
+        let to_clip = |x: f32, y: f32| [x / width * 2.0 - 1.0, 1.0 - y / height * 2.0];
+
+        for glyph in glyphs {
+            let font_id = glyph.font_id;
+            let sdf = match self.atlas.glyph(&fonts[font_id.0], font_id, glyph.glyph.id) {
+                Some(sdf) => sdf,
+                None => continue,
+            };
+            let scale = (
+                glyph.glyph.scale.x / SDF_SCALE,
+                glyph.glyph.scale.y / SDF_SCALE,
+            );
+            let origin = glyph.glyph.position;
+            let min = (
+                origin.x + sdf.min[0] * scale.0,
+                origin.y + sdf.min[1] * scale.1,
+            );
+            let max = (
+                origin.x + sdf.max[0] * scale.0,
+                origin.y + sdf.max[1] * scale.1,
+            );
+
+            let color = extras[glyph.section_index].color;
+            // Outline thickness in distance field units, as far as the field
+            // reaches.
+            let (outline_color, outline_width) = match outline {
+                Some(outline) => (
+                    outline.color.into(),
+                    (outline.thickness / scale.1 / (SPREAD * 2.0)).min(0.5),
+                ),
+                None => (color, 0.0),
+            };
+
+            let first = self.vertices.len() as u32;
+            let corners = [
+                (to_clip(min.0, min.1), [sdf.tex_min[0], sdf.tex_min[1]]),
+                (to_clip(max.0, min.1), [sdf.tex_max[0], sdf.tex_min[1]]),
+                (to_clip(max.0, max.1), [sdf.tex_max[0], sdf.tex_max[1]]),
+                (to_clip(min.0, max.1), [sdf.tex_min[0], sdf.tex_max[1]]),
+            ];
+            self.vertices
+                .extend(corners.iter().map(|&(position, tex_coord)| SdfVertex {
+                    position,
+                    tex_coord,
+                    color,
+                    outline_color,
+                    outline_width,
+                }));
+            self.indices
+                .extend([0, 1, 2, 0, 2, 3].iter().map(|index| first + index));
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        target: &wgpu::TextureView,
+    ) {
+        if self.indices.is_empty() {
+            return;
+        }
+
+        // Texture coordinates are queued in pixels, since the atlas can grow
+        // after the first glyphs of a frame are queued.
+        let size = self.atlas.size as f32;
+        for vertex in self.vertices.iter_mut() {
+            vertex.tex_coord = [vertex.tex_coord[0] / size, vertex.tex_coord[1] / size];
+        }
+
+        if self.texture_size != self.atlas.size {
+            let (texture, bind_group) = create_atlas_texture(
+                device,
+                &self.bind_group_layout,
+                &self.sampler,
+                self.atlas.size,
+            );
+            self.texture = texture;
+            self.bind_group = bind_group;
+            self.texture_size = self.atlas.size;
+        }
+        if self.atlas.dirty {
+            queue.write_texture(
+                wgpu::TextureCopyView {
+                    texture: &self.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                },
+                &self.atlas.pixels,
+                wgpu::TextureDataLayout {
+                    offset: 0,
+                    bytes_per_row: self.atlas.size,
+                    rows_per_image: self.atlas.size,
+                },
+                wgpu::Extent3d {
+                    width: self.atlas.size,
+                    height: self.atlas.size,
+                    depth: 1,
+                },
+            );
+            self.atlas.dirty = false;
+        }
+
+        self.vertex_buffer.write(
+            device,
+            encoder,
+            staging_belt,
+            bytemuck::cast_slice(&self.vertices),
+        );
+        self.index_buffer.write(
+            device,
+            encoder,
+            staging_belt,
+            bytemuck::cast_slice(&self.indices),
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.buffer().slice(..));
+        render_pass.set_index_buffer(self.index_buffer.buffer().slice(..));
+        render_pass.draw_indexed(0..self.indices.len() as u32, 0, 0..1);
+    }
+}
+
+fn create_atlas_texture(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    sampler: &wgpu::Sampler,
+    size: u32,
+) -> (wgpu::Texture, wgpu::BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("SDF Atlas"),
+        size: wgpu::Extent3d {
+            width: size,
+            height: size,
+            depth: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("SDF Bind Group"),
+        layout: bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    });
+    (texture, bind_group)
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    vs_src: wgpu::ShaderModuleSource,
+    fs_src: wgpu::ShaderModuleSource,
+) -> wgpu::RenderPipeline {
+    create_render_pipeline(
+        device,
+        "SDF Pipeline",
+        layout,
+        color_format,
+        &[SdfVertex::DESC],
+        vs_src,
+        fs_src,
+    )
+}
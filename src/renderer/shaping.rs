@@ -0,0 +1,580 @@
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+use glyph_brush::{BuiltInLineBreaker, LineBreak, LineBreaker, ToSectionText};
+use rustybuzz::{Direction, Face, UnicodeBuffer};
+use unicode_bidi::BidiInfo;
+use wgpu_glyph::ab_glyph::{point, Font, FontArc, Glyph, GlyphId, PxScale, ScaleFont};
+use wgpu_glyph::{FontId, SectionGlyph};
+
+// A font's shaping tables, parsed from the font's data. The face's lifetime
+// is a stand-in for `_font`'s, so it's never handed out as `'static`; see
+// `Faces::get`.
+struct ShapingFace {
+    // Borrows `_font`'s data, and is declared first so it's dropped first.
+    face: Face<'static>,
+    _font: FontArc,
+}
+
+impl ShapingFace {
+    fn new(font: &FontArc) -> Option<Self> {
+        let data = font.font_data();
+        // Font data sits behind the font's `Arc` and never moves or changes,
+        // and `_font` keeps it alive for as long as `face` is.
+        let data: &'static [u8] = unsafe { &*(data as *const [u8]) };
+        Some(Self {
+            face: Face::from_slice(data, 0)?,
+            _font: font.clone(),
+        })
+    }
+}
+
+// Shaping tables for each font, indexed by font id. Parsing them takes a
+// while, so the text renderer does it once per font rather than for every
+// run of text. Clones share the tables.
+#[derive(Clone, Default)]
+pub(crate) struct Faces(Arc<Vec<Option<Arc<ShapingFace>>>>);
+
+impl Faces {
+    // Parses the tables of the font with `id`, replacing any it had.
+    pub fn set(&mut self, id: FontId, font: &FontArc) {
+        let faces = Arc::make_mut(&mut self.0);
+        if faces.len() <= id.0 {
+            faces.resize(id.0 + 1, None);
+        }
+        faces[id.0] = ShapingFace::new(font).map(Arc::new);
+    }
+
+    // The face only borrows from `self`, so it can't outlive the font data
+    // it was parsed from, even when cloned.
+    fn get(&self, id: FontId) -> Option<&Face<'_>> {
+        self.0.get(id.0)?.as_deref().map(|face| &face.face)
+    }
+}
+
+impl fmt::Debug for Faces {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Faces({})", self.0.len())
+    }
+}
+
+impl PartialEq for Faces {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// A shaped glyph, in the order of the text it came from rather than the
+// order it's drawn in.
+struct Shaped {
+    section_index: usize,
+    // Byte index of the glyph's first character within the paragraph.
+    cluster: usize,
+    font_id: FontId,
+    id: GlyphId,
+    scale: PxScale,
+    advance: f32,
+    offset: (f32, f32),
+}
+
+// A line of glyphs in the order they're drawn. Marks can sit above or below
+// the baseline, so it's kept apart from the glyphs.
+pub(crate) struct Line {
+    pub baseline: f32,
+    pub glyphs: Vec<SectionGlyph>,
+}
+
+// Runs of text drawn in one font and direction are shaped together.
+struct Run {
+    range: Range<usize>,
+    section_index: usize,
+    font_id: FontId,
+    rtl: bool,
+}
+
+// Shapes text into lines no wider than `wrap_width`, left aligned with the
+// first line's top at the origin like glyph brush's built in layout. Each
+// character is drawn in its section's font, or else the first of `fallbacks`
+// with a glyph for it, and right to left text is reordered line by line.
+// Fonts missing from `faces` have their tables parsed for each run.
+pub(crate) fn shape<F, S>(
+    fonts: &[F],
+    faces: &Faces,
+    sections: &[S],
+    fallbacks: &[FontId],
+    line_breaker: BuiltInLineBreaker,
+    wrap_width: f32,
+) -> Vec<Line>
+where
+    F: Font,
+    S: ToSectionText,
+{
+    let sections: Vec<_> = sections.iter().map(|s| s.to_section_text()).collect();
+    let mut text = String::new();
+    let mut starts = Vec::with_capacity(sections.len());
+    for section in &sections {
+        starts.push(text.len());
+        text.push_str(section.text);
+    }
+    let section_at = |index: usize| starts.partition_point(|&start| start <= index).max(1) - 1;
+
+    let mut lines = Vec::new();
+    let mut top = 0.0;
+    let mut paragraph_start = 0;
+    for paragraph in text.split('\n') {
+        let section_at = |index: usize| section_at(paragraph_start + index);
+        let bidi = BidiInfo::new(paragraph, None);
+        let glyphs = shape_paragraph(fonts, faces, &sections, fallbacks, &bidi, section_at);
+
+        for line in break_lines(paragraph, &glyphs, line_breaker, wrap_width) {
+            let end = glyphs
+                .get(line.end)
+                .map_or(paragraph.len(), |next| next.cluster);
+            let glyphs = &glyphs[line];
+            let fonts_used = glyphs.iter().map(|glyph| (glyph.font_id, glyph.scale));
+            let (ascent, descent, line_gap) = if glyphs.is_empty() {
+                let section = &sections[section_at(0)];
+                line_metrics(fonts, std::iter::once((section.font_id, section.scale)))
+            } else {
+                line_metrics(fonts, fonts_used)
+            };
+            let baseline = top + ascent;
+
+            let text_range = glyphs.first().map_or(0, |glyph| glyph.cluster)..end;
+            let levels = bidi
+                .paragraphs
+                .iter()
+                .find(|para| para.range.contains(&text_range.start))
+                .map(|para| bidi.reordered_levels(para, text_range.clone()));
+
+            let mut caret = 0.0;
+            let mut placed = Vec::with_capacity(glyphs.len());
+            for index in visual_order(glyphs, levels.as_deref()) {
+                let glyph = &glyphs[index];
+                let section_index = glyph.section_index;
+                placed.push(SectionGlyph {
+                    section_index,
+                    byte_index: paragraph_start + glyph.cluster - starts[section_index],
+                    font_id: glyph.font_id,
+                    glyph: Glyph {
+                        id: glyph.id,
+                        scale: glyph.scale,
+                        position: point(caret + glyph.offset.0, baseline - glyph.offset.1),
+                    },
+                });
+                caret += glyph.advance;
+            }
+            if !placed.is_empty() {
+                lines.push(Line {
+                    baseline,
+                    glyphs: placed,
+                });
+            }
+
+            top = baseline - descent + line_gap;
+        }
+        paragraph_start += paragraph.len() + 1;
+    }
+    lines
+}
+
+fn shape_paragraph<F, I>(
+    fonts: &[F],
+    faces: &Faces,
+    sections: &[glyph_brush::SectionText],
+    fallbacks: &[FontId],
+    bidi: &BidiInfo,
+    section_at: I,
+) -> Vec<Shaped>
+where
+    F: Font,
+    I: Fn(usize) -> usize,
+{
+    let paragraph = bidi.text;
+    let covers = |font: FontId, c: char| fonts[font.0].glyph_id(c).0 != 0;
+
+    // Split the paragraph wherever the section, font or direction changes.
+    let mut runs: Vec<Run> = Vec::new();
+    let mut previous_font = None;
+    for (index, c) in paragraph.char_indices() {
+        let section_index = section_at(index);
+        let primary = sections[section_index].font_id;
+        // Spaces, punctuation and marks stay in the font before them when
+        // they can, so they don't split a run of fallback text.
+        let font_id = match previous_font {
+            Some(font) if !c.is_alphanumeric() && covers(font, c) => font,
+            _ => std::iter::once(primary)
+                .chain(fallbacks.iter().copied())
+                .find(|&font| font.0 < fonts.len() && covers(font, c))
+                .unwrap_or(primary),
+        };
+        previous_font = Some(font_id);
+        let rtl = bidi.levels[index].is_rtl();
+
+        match runs.last_mut() {
+            Some(run)
+                if run.section_index == section_index
+                    && run.font_id == font_id
+                    && run.rtl == rtl =>
+            {
+                run.range.end = index + c.len_utf8();
+            }
+            _ => runs.push(Run {
+                range: index..index + c.len_utf8(),
+                section_index,
+                font_id,
+                rtl,
+            }),
+        }
+    }
+
+    let mut glyphs = Vec::new();
+    for run in runs {
+        let font = &fonts[run.font_id.0];
+        // ab_glyph has already parsed the font, so this only fails for fonts
+        // it reads that the shaper doesn't.
+        let parsed;
+        let face = match faces.get(run.font_id) {
+            Some(face) => face,
+            None => match Face::from_slice(font.font_data(), 0) {
+                Some(face) => {
+                    parsed = face;
+                    &parsed
+                }
+                None => continue,
+            },
+        };
+        let scale = sections[run.section_index].scale;
+        let scaled = font.as_scaled(scale);
+        let (h_scale, v_scale) = (scaled.h_scale_factor(), scaled.v_scale_factor());
+
+        let mut buffer = UnicodeBuffer::new();
+        buffer.push_str(&paragraph[run.range.clone()]);
+        buffer.set_direction(if run.rtl {
+            Direction::RightToLeft
+        } else {
+            Direction::LeftToRight
+        });
+        buffer.guess_segment_properties();
+        let shaped = rustybuzz::shape(face, &[], buffer);
+
+        let infos = shaped.glyph_infos().iter().zip(shaped.glyph_positions());
+        let start = glyphs.len();
+        glyphs.extend(infos.map(|(info, position)| Shaped {
+            section_index: run.section_index,
+            cluster: run.range.start + info.cluster as usize,
+            font_id: run.font_id,
+            id: GlyphId(info.glyph_id as u16),
+            scale,
+            advance: position.x_advance as f32 * h_scale,
+            offset: (
+                position.x_offset as f32 * h_scale,
+                position.y_offset as f32 * v_scale,
+            ),
+        }));
+        // Right to left runs come out in drawing order.
+        if run.rtl {
+            glyphs[start..].reverse();
+        }
+    }
+    glyphs
+}
+
+// Splits a paragraph's glyphs into lines, breaking where `line_breaker`
+// allows. Spaces can hang past the end of a line, and words too long for a
+// line of their own are broken wherever they overflow.
+fn break_lines(
+    paragraph: &str,
+    glyphs: &[Shaped],
+    line_breaker: BuiltInLineBreaker,
+    wrap_width: f32,
+) -> Vec<Range<usize>> {
+    let breaks: Vec<usize> = line_breaker
+        .line_breaks(paragraph)
+        .map(|line_break| match line_break {
+            LineBreak::Soft(index) | LineBreak::Hard(index) => index,
+        })
+        .collect();
+    let is_space = |glyph: &Shaped| {
+        paragraph[glyph.cluster..]
+            .chars()
+            .next()
+            .map(char::is_whitespace)
+            == Some(true)
+    };
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut width = 0.0;
+    let mut last_break = None;
+    for (index, glyph) in glyphs.iter().enumerate() {
+        let starts_cluster = index == 0 || glyphs[index - 1].cluster != glyph.cluster;
+        if index > start && starts_cluster && breaks.binary_search(&glyph.cluster).is_ok() {
+            last_break = Some(index);
+        }
+
+        if index > start && !is_space(glyph) && width + glyph.advance > wrap_width {
+            let at = last_break.unwrap_or(index);
+            lines.push(start..at);
+            width = glyphs[at..index].iter().map(|glyph| glyph.advance).sum();
+            start = at;
+            last_break = None;
+        }
+        width += glyph.advance;
+    }
+    lines.push(start..glyphs.len());
+    lines
+}
+
+// The order glyphs are drawn in from left to right, reversing runs of right
+// to left text.
+fn visual_order(glyphs: &[Shaped], levels: Option<&[unicode_bidi::Level]>) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..glyphs.len()).collect();
+    let levels = match levels {
+        Some(levels) => levels,
+        None => return order,
+    };
+    let level = |index: usize| levels[glyphs[index].cluster].number();
+
+    let highest = order.iter().map(|&index| level(index)).max().unwrap_or(0);
+    let lowest_odd = order
+        .iter()
+        .map(|&index| level(index))
+        .filter(|level| level % 2 == 1)
+        .min()
+        .unwrap_or(highest + 1);
+    for reverse_at in (lowest_odd..=highest).rev() {
+        let mut index = 0;
+        while index < order.len() {
+            if level(order[index]) < reverse_at {
+                index += 1;
+                continue;
+            }
+            let start = index;
+            while index < order.len() && level(order[index]) >= reverse_at {
+                index += 1;
+            }
+            order[start..index].reverse();
+        }
+    }
+    order
+}
+
+// The tallest ascent, lowest descent and widest line gap of the fonts on a
+// line.
+fn line_metrics<F, I>(fonts: &[F], used: I) -> (f32, f32, f32)
+where
+    F: Font,
+    I: Iterator<Item = (FontId, PxScale)>,
+{
+    used.fold(
+        (0.0, 0.0, 0.0),
+        |(ascent, descent, line_gap), (font, scale)| {
+            let font = fonts[font.0].as_scaled(scale);
+            (
+                font.ascent().max(ascent),
+                font.descent().min(descent),
+                font.line_gap().max(line_gap),
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::render_text::FONT_BYTES;
+    use glyph_brush::SectionText;
+    use wgpu_glyph::ab_glyph::{v2, CodepointIdIter, Outline};
+
+    const SCALE: f32 = 16.0;
+
+    fn font() -> FontArc {
+        FontArc::try_from_slice(FONT_BYTES).unwrap()
+    }
+
+    // The bundled font without a glyph for `missing`, to force a fallback.
+    struct Without {
+        font: FontArc,
+        missing: char,
+    }
+
+    impl Font for Without {
+        fn units_per_em(&self) -> Option<f32> {
+            self.font.units_per_em()
+        }
+
+        fn ascent_unscaled(&self) -> f32 {
+            self.font.ascent_unscaled()
+        }
+
+        fn descent_unscaled(&self) -> f32 {
+            self.font.descent_unscaled()
+        }
+
+        fn line_gap_unscaled(&self) -> f32 {
+            self.font.line_gap_unscaled()
+        }
+
+        fn glyph_id(&self, c: char) -> GlyphId {
+            if c == self.missing {
+                GlyphId(0)
+            } else {
+                self.font.glyph_id(c)
+            }
+        }
+
+        fn h_advance_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.h_advance_unscaled(id)
+        }
+
+        fn h_side_bearing_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.h_side_bearing_unscaled(id)
+        }
+
+        fn v_advance_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.v_advance_unscaled(id)
+        }
+
+        fn v_side_bearing_unscaled(&self, id: GlyphId) -> f32 {
+            self.font.v_side_bearing_unscaled(id)
+        }
+
+        fn kern_unscaled(&self, first: GlyphId, second: GlyphId) -> f32 {
+            self.font.kern_unscaled(first, second)
+        }
+
+        fn outline(&self, id: GlyphId) -> Option<Outline> {
+            self.font.outline(id)
+        }
+
+        fn glyph_count(&self) -> usize {
+            self.font.glyph_count()
+        }
+
+        fn codepoint_ids(&self) -> CodepointIdIter<'_> {
+            self.font.codepoint_ids()
+        }
+
+        fn glyph_raster_image2(&self, id: GlyphId, size: u16) -> Option<v2::GlyphImage<'_>> {
+            self.font.glyph_raster_image2(id, size)
+        }
+
+        fn font_data(&self) -> &[u8] {
+            self.font.font_data()
+        }
+    }
+
+    fn section(text: &str) -> SectionText<'_> {
+        SectionText {
+            text,
+            scale: PxScale::from(SCALE),
+            font_id: FontId(0),
+        }
+    }
+
+    fn shape_text<F: Font>(fonts: &[F], text: &str, fallbacks: &[FontId], wrap: f32) -> Vec<Line> {
+        shape(
+            fonts,
+            &Faces::default(),
+            &[section(text)],
+            fallbacks,
+            BuiltInLineBreaker::default(),
+            wrap,
+        )
+    }
+
+    fn byte_indices(line: &Line) -> Vec<usize> {
+        line.glyphs.iter().map(|glyph| glyph.byte_index).collect()
+    }
+
+    #[test]
+    fn reverses_right_to_left_runs() {
+        let fonts = [font()];
+        // Two Hebrew letters after Latin text are drawn last letter first.
+        let lines = shape_text(&fonts, "abc \u{5d0}\u{5d1}", &[], f32::INFINITY);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(byte_indices(&lines[0]), vec![0, 1, 2, 3, 6, 4]);
+
+        let xs: Vec<f32> = lines[0]
+            .glyphs
+            .iter()
+            .map(|glyph| glyph.glyph.position.x)
+            .collect();
+        assert!(xs.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", xs);
+    }
+
+    #[test]
+    fn reorders_levels_within_a_line() {
+        let fonts = [font()];
+        let mut faces = Faces::default();
+        faces.set(FontId(0), &fonts[0]);
+        // A right to left paragraph with a Latin word in it keeps the word
+        // left to right.
+        let lines = shape(
+            &fonts,
+            &faces,
+            &[section("\u{5d0} ab \u{5d1}")],
+            &[],
+            BuiltInLineBreaker::default(),
+            f32::INFINITY,
+        );
+        assert_eq!(byte_indices(&lines[0]), vec![6, 5, 3, 4, 2, 0]);
+    }
+
+    #[test]
+    fn falls_back_mid_word() {
+        let fonts = [
+            Without {
+                font: font(),
+                missing: 'b',
+            },
+            Without {
+                font: font(),
+                missing: '\0',
+            },
+        ];
+        let lines = shape_text(&fonts, "abc", &[FontId(1)], f32::INFINITY);
+        let used: Vec<FontId> = lines[0].glyphs.iter().map(|glyph| glyph.font_id).collect();
+        assert_eq!(used, vec![FontId(0), FontId(1), FontId(0)]);
+        assert_eq!(byte_indices(&lines[0]), vec![0, 1, 2]);
+
+        // Without a fallback the missing glyph stays in the section's font.
+        let lines = shape_text(&fonts, "abc", &[], f32::INFINITY);
+        assert!(lines[0]
+            .glyphs
+            .iter()
+            .all(|glyph| glyph.font_id == FontId(0)));
+    }
+
+    #[test]
+    fn wraps_inside_long_words() {
+        let fonts = [font()];
+        let advance = fonts[0].as_scaled(SCALE).h_advance(fonts[0].glyph_id('a'));
+        let lines = shape_text(&fonts, "aaaaaaaaaa", &[], advance * 3.5);
+        let lengths: Vec<usize> = lines.iter().map(|line| line.glyphs.len()).collect();
+        assert_eq!(lengths, vec![3, 3, 3, 1]);
+        assert_eq!(byte_indices(&lines[1]), vec![3, 4, 5]);
+        assert!(lines[1].baseline > lines[0].baseline);
+
+        // Words that fit are moved to the next line whole.
+        let lines = shape_text(&fonts, "aa aa", &[], advance * 3.5);
+        assert_eq!(byte_indices(&lines[0]), vec![0, 1, 2]);
+        assert_eq!(byte_indices(&lines[1]), vec![3, 4]);
+    }
+
+    #[test]
+    fn visual_order_without_levels_is_logical() {
+        let fonts = [font()];
+        let glyphs = shape_paragraph(
+            &fonts,
+            &Faces::default(),
+            &[section("abc")],
+            &[],
+            &BidiInfo::new("abc", None),
+            |_| 0,
+        );
+        assert_eq!(visual_order(&glyphs, None), vec![0, 1, 2]);
+    }
+}
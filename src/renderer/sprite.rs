@@ -0,0 +1,361 @@
+use super::create_render_pipeline;
+use crate::assets::{Handle, Shader, Texture};
+use crate::util::GrowableBuffer;
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct SpriteVertex {
+    position: [f32; 2],
+    tex_coord: [f32; 2],
+    color: [f32; 4],
+}
+
+unsafe impl bytemuck::Pod for SpriteVertex {}
+unsafe impl bytemuck::Zeroable for SpriteVertex {}
+
+impl SpriteVertex {
+    const SIZE: wgpu::BufferAddress = std::mem::size_of::<Self>() as wgpu::BufferAddress;
+    const DESC: wgpu::VertexBufferDescriptor<'static> = wgpu::VertexBufferDescriptor {
+        stride: Self::SIZE,
+        step_mode: wgpu::InputStepMode::Vertex,
+        attributes: &[
+            wgpu::VertexAttributeDescriptor {
+                offset: 0,
+                shader_location: 0,
+                format: wgpu::VertexFormat::Float2,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                shader_location: 1,
+                format: wgpu::VertexFormat::Float2,
+            },
+            wgpu::VertexAttributeDescriptor {
+                offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                shader_location: 2,
+                format: wgpu::VertexFormat::Float4,
+            },
+        ],
+    };
+}
+
+// The quads queued for one texture this frame, as a range of the frame's
+// indices.
+struct Batch {
+    texture: Handle<Texture>,
+    indices: Range<u32>,
+}
+
+// A texture on the GPU, holding on to the handle it was uploaded from.
+struct Uploaded {
+    _handle: Handle<Texture>,
+    // The handle's version when it was uploaded.
+    version: u64,
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+// Textured, tinted quads, batched by texture. Textures are uploaded the
+// first time they're drawn, uploaded again when their handle is reloaded,
+// and dropped after a frame that doesn't draw them.
+pub(crate) struct SpritePipeline {
+    pipeline: wgpu::RenderPipeline,
+    layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    // Keyed by the handle's id, which holding on to the handle keeps from
+    // being reused.
+    uploaded: HashMap<usize, Uploaded>,
+    // Every batch's quads go in the same buffers.
+    vertices: Vec<SpriteVertex>,
+    indices: Vec<u32>,
+    vertex_buffer: GrowableBuffer,
+    index_buffer: GrowableBuffer,
+    batches: Vec<Batch>,
+    screen_size: (f32, f32),
+}
+
+impl SpritePipeline {
+    pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Sprite Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        dimension: wgpu::TextureViewDimension::D2,
+                        component_type: wgpu::TextureComponentType::Float,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                    count: None,
+                },
+            ],
+        });
+        // Nearest filtering keeps pixel art and pixel fonts sharp.
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+            label: Some("Sprite Pipeline Layout"),
+        });
+        let pipeline = create_pipeline(
+            device,
+            &layout,
+            color_format,
+            wgpu::include_spirv!("../../res/shaders/sprite.vert.spv"),
+            wgpu::include_spirv!("../../res/shaders/sprite.frag.spv"),
+        );
+
+        Self {
+            pipeline,
+            layout,
+            color_format,
+            bind_group_layout,
+            sampler,
+            uploaded: HashMap::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            vertex_buffer: GrowableBuffer::new(
+                device,
+                "Sprite Vertex Buffer",
+                wgpu::BufferUsage::VERTEX,
+            ),
+            index_buffer: GrowableBuffer::new(
+                device,
+                "Sprite Index Buffer",
+                wgpu::BufferUsage::INDEX,
+            ),
+            batches: Vec::new(),
+            screen_size: (1.0, 1.0),
+        }
+    }
+
+    pub fn set_shaders(&mut self, device: &wgpu::Device, vertex: &Shader, fragment: &Shader) {
+        self.pipeline = create_pipeline(
+            device,
+            &self.layout,
+            self.color_format,
+            vertex.module_source(),
+            fragment.module_source(),
+        );
+    }
+
+    pub fn reset(&mut self, screen_size: (f32, f32)) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.batches.clear();
+        self.screen_size = screen_size;
+    }
+
+    // Queues the part of `handle`'s texture between `tex_min` and `tex_max`,
+    // given in pixels, to be drawn between `min` and `max` on screen.
+    // Textures still loading aren't drawn.
+    pub fn push_quad(
+        &mut self,
+        handle: &Handle<Texture>,
+        min: (f32, f32),
+        max: (f32, f32),
+        tex_min: (f32, f32),
+        tex_max: (f32, f32),
+        color: [f32; 4],
+    ) {
+        let texture = match handle.try_get() {
+            Some(texture) => texture,
+            None => return,
+        };
+        let (width, height) = self.screen_size;
+        let to_clip = |x: f32, y: f32| [x / width * 2.0 - 1.0, 1.0 - y / height * 2.0];
+        let (texture_width, texture_height) = (texture.width() as f32, texture.height() as f32);
+        let to_uv = |x: f32, y: f32| [x / texture_width, y / texture_height];
+
+        // Consecutive quads usually share a texture, so only the last batch
+        // is checked.
+        let start = self.indices.len() as u32;
+        let batch = match self.batches.last_mut() {
+            Some(batch) if batch.texture.id() == handle.id() => batch,
+            _ => {
+                self.batches.push(Batch {
+                    texture: handle.clone(),
+                    indices: start..start,
+                });
+                self.batches.last_mut().unwrap()
+            }
+        };
+        batch.indices.end = start + 6;
+
+        let first = self.vertices.len() as u32;
+        let corners = [
+            (to_clip(min.0, min.1), to_uv(tex_min.0, tex_min.1)),
+            (to_clip(max.0, min.1), to_uv(tex_max.0, tex_min.1)),
+            (to_clip(max.0, max.1), to_uv(tex_max.0, tex_max.1)),
+            (to_clip(min.0, max.1), to_uv(tex_min.0, tex_max.1)),
+        ];
+        self.vertices
+            .extend(corners.iter().map(|&(position, tex_coord)| SpriteVertex {
+                position,
+                tex_coord,
+                color,
+            }));
+        self.indices
+            .extend([0, 1, 2, 0, 2, 3].iter().map(|index| first + index));
+    }
+
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        target: &wgpu::TextureView,
+    ) {
+        let batches = &self.batches;
+        self.uploaded
+            .retain(|&key, _| batches.iter().any(|batch| batch.texture.id() == key));
+        if self.batches.is_empty() {
+            return;
+        }
+
+        for batch in self.batches.iter() {
+            let key = batch.texture.id();
+            let current = self
+                .uploaded
+                .get(&key)
+                .is_some_and(|uploaded| uploaded.version == batch.texture.version());
+            if !current {
+                let uploaded = self.upload(device, queue, &batch.texture);
+                self.uploaded.insert(key, uploaded);
+            }
+        }
+
+        self.vertex_buffer.write(
+            device,
+            encoder,
+            staging_belt,
+            bytemuck::cast_slice(&self.vertices),
+        );
+        self.index_buffer.write(
+            device,
+            encoder,
+            staging_belt,
+            bytemuck::cast_slice(&self.indices),
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.buffer().slice(..));
+        render_pass.set_index_buffer(self.index_buffer.buffer().slice(..));
+        for batch in self.batches.iter() {
+            let uploaded = &self.uploaded[&batch.texture.id()];
+            render_pass.set_bind_group(0, &uploaded.bind_group, &[]);
+            render_pass.draw_indexed(batch.indices.clone(), 0, 0..1);
+        }
+    }
+
+    fn upload(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        handle: &Handle<Texture>,
+    ) -> Uploaded {
+        let version = handle.version();
+        let texture = handle.get();
+        let size = wgpu::Extent3d {
+            width: texture.width(),
+            height: texture.height(),
+            depth: 1,
+        };
+        let gpu_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Sprite Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &gpu_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            texture.pixels(),
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: texture.width() * 4,
+                rows_per_image: texture.height(),
+            },
+            size,
+        );
+
+        let view = gpu_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Sprite Bind Group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+        });
+
+        Uploaded {
+            _handle: handle.clone(),
+            version,
+            _texture: gpu_texture,
+            bind_group,
+        }
+    }
+}
+
+fn create_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    vs_src: wgpu::ShaderModuleSource,
+    fs_src: wgpu::ShaderModuleSource,
+) -> wgpu::RenderPipeline {
+    create_render_pipeline(
+        device,
+        "Sprite Pipeline",
+        layout,
+        color_format,
+        &[SpriteVertex::DESC],
+        vs_src,
+        fs_src,
+    )
+}
@@ -0,0 +1,123 @@
+use crate::error::Result;
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+struct ClipData {
+    samples: Vec<f32>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl ClipData {
+    fn duration(&self) -> Duration {
+        let frames = self.samples.len() as f64 / self.channels.max(1) as f64;
+        Duration::from_secs_f64(frames / self.sample_rate as f64)
+    }
+}
+
+// A sound decoded once into memory. Clones share the same samples, so a clip
+// can be kept around and played any number of times, even concurrently.
+#[derive(Clone)]
+pub struct SoundClip {
+    data: Arc<ClipData>,
+}
+
+impl SoundClip {
+    // Decodes a WAV, OGG, FLAC or MP3 file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    pub fn from_reader<R>(reader: R) -> Result<Self>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        let decoder = rodio::Decoder::new(reader)?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples = decoder.convert_samples().collect();
+        Ok(Self::from_samples(channels, sample_rate, samples))
+    }
+
+    // Interleaved samples, one per channel for each frame.
+    pub fn from_samples(channels: u16, sample_rate: u32, samples: Vec<f32>) -> Self {
+        Self {
+            data: Arc::new(ClipData {
+                samples,
+                channels,
+                sample_rate,
+            }),
+        }
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.data.channels
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.data.sample_rate
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.data.samples
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.data.duration()
+    }
+
+    pub fn source(&self) -> ClipSource {
+        ClipSource {
+            data: self.data.clone(),
+            position: 0,
+        }
+    }
+}
+
+pub struct ClipSource {
+    data: Arc<ClipData>,
+    position: usize,
+}
+
+impl Iterator for ClipSource {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.data.samples.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.data.samples.len().saturating_sub(self.position);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for ClipSource {}
+
+impl Source for ClipSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.data.samples.len().saturating_sub(self.position))
+    }
+
+    fn channels(&self) -> u16 {
+        self.data.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.data.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.data.duration())
+    }
+}
@@ -0,0 +1,562 @@
+use std::any::Any;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::Source;
+
+// Frames pulled from the input before the chain is locked and run over them.
+const BLOCK_FRAMES: usize = 256;
+
+// Processes interleaved samples in place. Parameters can be changed between
+// blocks through `EffectChain::modify`.
+pub trait Effect: Send + 'static {
+    fn process(&mut self, samples: &mut [f32], channels: u16, sample_rate: u32);
+
+    // Clears delay lines and filter history.
+    fn reset(&mut self) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EffectId(u32);
+
+struct Slot {
+    id: EffectId,
+    bypassed: bool,
+    effect: Box<dyn Effect>,
+}
+
+struct Chain {
+    slots: Vec<Slot>,
+    next_id: u32,
+}
+
+// An ordered list of effects shared between the game and the audio thread.
+#[derive(Clone)]
+pub struct EffectChain {
+    chain: Arc<Mutex<Chain>>,
+}
+
+impl Default for EffectChain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EffectChain {
+    pub fn new() -> Self {
+        Self {
+            chain: Arc::new(Mutex::new(Chain {
+                slots: Vec::new(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    pub fn push<E: Effect>(&self, effect: E) -> EffectId {
+        let mut chain = self.chain.lock().unwrap();
+        let id = EffectId(chain.next_id);
+        chain.next_id += 1;
+        chain.slots.push(Slot {
+            id,
+            bypassed: false,
+            effect: Box::new(effect),
+        });
+        id
+    }
+
+    pub fn remove(&self, id: EffectId) -> bool {
+        let mut chain = self.chain.lock().unwrap();
+        let len = chain.slots.len();
+        chain.slots.retain(|slot| slot.id != id);
+        chain.slots.len() != len
+    }
+
+    pub fn clear(&self) {
+        self.chain.lock().unwrap().slots.clear();
+    }
+
+    // A bypassed effect keeps its place and settings but lets audio through untouched.
+    pub fn set_bypassed(&self, id: EffectId, bypassed: bool) {
+        let mut chain = self.chain.lock().unwrap();
+        if let Some(slot) = chain.slots.iter_mut().find(|slot| slot.id == id) {
+            if slot.bypassed && !bypassed {
+                slot.effect.reset();
+            }
+            slot.bypassed = bypassed;
+        }
+    }
+
+    // Changes an effect's parameters, returning `None` if the id is unknown
+    // or belongs to a different type of effect.
+    pub fn modify<E, F, R>(&self, id: EffectId, f: F) -> Option<R>
+    where
+        E: Effect,
+        F: FnOnce(&mut E) -> R,
+    {
+        let mut chain = self.chain.lock().unwrap();
+        chain
+            .slots
+            .iter_mut()
+            .find(|slot| slot.id == id)
+            .and_then(|slot| slot.effect.as_any_mut().downcast_mut::<E>())
+            .map(f)
+    }
+
+    // Runs the chain over a single sound rather than a whole bus.
+    pub fn apply<S>(&self, input: S) -> Effected<S>
+    where
+        S: Source<Item = f32>,
+    {
+        Effected {
+            input,
+            chain: self.clone(),
+            buffer: Vec::with_capacity(BLOCK_FRAMES * 2),
+            position: 0,
+            finished: false,
+        }
+    }
+
+    fn process(&self, samples: &mut [f32], channels: u16, sample_rate: u32) {
+        let mut chain = self.chain.lock().unwrap();
+        for slot in chain.slots.iter_mut().filter(|slot| !slot.bypassed) {
+            slot.effect.process(samples, channels, sample_rate);
+        }
+    }
+}
+
+pub struct Effected<S> {
+    input: S,
+    chain: EffectChain,
+    buffer: Vec<f32>,
+    position: usize,
+    finished: bool,
+}
+
+impl<S> Iterator for Effected<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.position >= self.buffer.len() {
+            if self.finished {
+                return None;
+            }
+
+            let channels = self.input.channels();
+            let sample_rate = self.input.sample_rate();
+            self.buffer.clear();
+            self.buffer.extend(
+                self.input
+                    .by_ref()
+                    .take(BLOCK_FRAMES * channels.max(1) as usize),
+            );
+            self.finished = self.buffer.len() < BLOCK_FRAMES * channels.max(1) as usize;
+            self.chain.process(&mut self.buffer, channels, sample_rate);
+            self.position = 0;
+        }
+
+        let sample = self.buffer.get(self.position).copied();
+        self.position += 1;
+        sample
+    }
+}
+
+impl<S> Source for Effected<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FilterKind {
+    LowPass,
+    HighPass,
+}
+
+// Second order filter from the Audio EQ Cookbook.
+#[derive(Debug, Clone)]
+struct Biquad {
+    kind: FilterKind,
+    coefficients: [f32; 5],
+    // x1, x2, y1, y2 for each channel.
+    history: Vec<[f32; 4]>,
+    designed_for: Option<(f32, f32, u32)>,
+}
+
+impl Biquad {
+    fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            coefficients: [1.0, 0.0, 0.0, 0.0, 0.0],
+            history: Vec::new(),
+            designed_for: None,
+        }
+    }
+
+    fn design(&mut self, cutoff: f32, q: f32, sample_rate: u32) {
+        if self.designed_for == Some((cutoff, q, sample_rate)) {
+            return;
+        }
+        self.designed_for = Some((cutoff, q, sample_rate));
+
+        let cutoff = cutoff.clamp(10.0, sample_rate as f32 * 0.49);
+        let omega = 2.0 * PI * cutoff / sample_rate as f32;
+        let alpha = omega.sin() / (2.0 * q.max(0.01));
+        let cos = omega.cos();
+        let (b0, b1, b2) = match self.kind {
+            FilterKind::LowPass => ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0),
+            FilterKind::HighPass => ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0),
+        };
+        let a0 = 1.0 + alpha;
+        self.coefficients = [
+            b0 / a0,
+            b1 / a0,
+            b2 / a0,
+            -2.0 * cos / a0,
+            (1.0 - alpha) / a0,
+        ];
+    }
+
+    fn process(&mut self, samples: &mut [f32], channels: u16) {
+        let channels = channels.max(1) as usize;
+        self.history.resize(channels, [0.0; 4]);
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+
+        for frame in samples.chunks_mut(channels) {
+            for (sample, history) in frame.iter_mut().zip(self.history.iter_mut()) {
+                let [x1, x2, y1, y2] = *history;
+                let x = *sample;
+                let y = b0 * x + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2;
+                *history = [x, x1, y, y1];
+                *sample = y;
+            }
+        }
+    }
+}
+
+pub struct LowPass {
+    pub cutoff: f32,
+    pub q: f32,
+    filter: Biquad,
+}
+
+impl LowPass {
+    pub fn new(cutoff: f32) -> Self {
+        Self {
+            cutoff,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+            filter: Biquad::new(FilterKind::LowPass),
+        }
+    }
+}
+
+impl Effect for LowPass {
+    fn process(&mut self, samples: &mut [f32], channels: u16, sample_rate: u32) {
+        self.filter.design(self.cutoff, self.q, sample_rate);
+        self.filter.process(samples, channels);
+    }
+
+    fn reset(&mut self) {
+        self.filter.history.clear();
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+pub struct HighPass {
+    pub cutoff: f32,
+    pub q: f32,
+    filter: Biquad,
+}
+
+impl HighPass {
+    pub fn new(cutoff: f32) -> Self {
+        Self {
+            cutoff,
+            q: std::f32::consts::FRAC_1_SQRT_2,
+            filter: Biquad::new(FilterKind::HighPass),
+        }
+    }
+}
+
+impl Effect for HighPass {
+    fn process(&mut self, samples: &mut [f32], channels: u16, sample_rate: u32) {
+        self.filter.design(self.cutoff, self.q, sample_rate);
+        self.filter.process(samples, channels);
+    }
+
+    fn reset(&mut self) {
+        self.filter.history.clear();
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Repeats the input after `delay`, each repeat `feedback` times quieter.
+pub struct Echo {
+    pub delay: Duration,
+    pub feedback: f32,
+    // Balance between the dry input (0.0) and the echoes (1.0).
+    pub mix: f32,
+    lines: Vec<Vec<f32>>,
+    cursor: usize,
+}
+
+impl Echo {
+    pub fn new(delay: Duration, feedback: f32, mix: f32) -> Self {
+        Self {
+            delay,
+            feedback,
+            mix,
+            lines: Vec::new(),
+            cursor: 0,
+        }
+    }
+}
+
+impl Effect for Echo {
+    fn process(&mut self, samples: &mut [f32], channels: u16, sample_rate: u32) {
+        let channels = channels.max(1) as usize;
+        let length = ((self.delay.as_secs_f32() * sample_rate as f32) as usize).max(1);
+        if self.lines.len() != channels || self.lines[0].len() != length {
+            self.lines = vec![vec![0.0; length]; channels];
+            self.cursor = 0;
+        }
+
+        for frame in samples.chunks_mut(channels) {
+            for (sample, line) in frame.iter_mut().zip(self.lines.iter_mut()) {
+                let delayed = line[self.cursor];
+                line[self.cursor] = *sample + delayed * self.feedback;
+                *sample = *sample * (1.0 - self.mix) + delayed * self.mix;
+            }
+            self.cursor = (self.cursor + 1) % length;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.lines.clear();
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Delay lengths, in samples at 44.1kHz, from Freeverb.
+const COMB_TUNING: [usize; 4] = [1116, 1188, 1277, 1356];
+const ALLPASS_TUNING: [usize; 2] = [556, 441];
+// Offsets the right channel so the tail spreads across the stereo field.
+const STEREO_SPREAD: usize = 23;
+
+struct Comb {
+    buffer: Vec<f32>,
+    cursor: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.cursor];
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        self.buffer[self.cursor] = input + self.filter_store * feedback;
+        self.cursor = (self.cursor + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    cursor: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.cursor];
+        self.buffer[self.cursor] = input + buffered * 0.5;
+        self.cursor = (self.cursor + 1) % self.buffer.len();
+        buffered - input
+    }
+}
+
+// A small Schroeder reverb: parallel combs into series allpasses per channel.
+pub struct Reverb {
+    // 0.0 to 1.0, larger rooms ring for longer.
+    pub room_size: f32,
+    // 0.0 to 1.0, how quickly high frequencies die away.
+    pub damping: f32,
+    pub mix: f32,
+    channels: Vec<(Vec<Comb>, Vec<Allpass>)>,
+    built_for: Option<(usize, u32)>,
+}
+
+impl Reverb {
+    pub fn new(room_size: f32, damping: f32, mix: f32) -> Self {
+        Self {
+            room_size,
+            damping,
+            mix,
+            channels: Vec::new(),
+            built_for: None,
+        }
+    }
+
+    fn build(&mut self, channels: usize, sample_rate: u32) {
+        if self.built_for == Some((channels, sample_rate)) {
+            return;
+        }
+        self.built_for = Some((channels, sample_rate));
+
+        let scale = |length: usize, channel: usize| {
+            ((length + channel * STEREO_SPREAD) as f32 * sample_rate as f32 / 44_100.0) as usize
+        };
+        self.channels = (0..channels)
+            .map(|channel| {
+                let combs = COMB_TUNING
+                    .iter()
+                    .map(|&length| Comb {
+                        buffer: vec![0.0; scale(length, channel).max(1)],
+                        cursor: 0,
+                        filter_store: 0.0,
+                    })
+                    .collect();
+                let allpasses = ALLPASS_TUNING
+                    .iter()
+                    .map(|&length| Allpass {
+                        buffer: vec![0.0; scale(length, channel).max(1)],
+                        cursor: 0,
+                    })
+                    .collect();
+                (combs, allpasses)
+            })
+            .collect();
+    }
+}
+
+impl Effect for Reverb {
+    fn process(&mut self, samples: &mut [f32], channels: u16, sample_rate: u32) {
+        let channels = channels.max(1) as usize;
+        self.build(channels, sample_rate);
+        let feedback = 0.7 + self.room_size.clamp(0.0, 1.0) * 0.28;
+        let damping = self.damping.clamp(0.0, 1.0) * 0.4;
+
+        for frame in samples.chunks_mut(channels) {
+            for (sample, (combs, allpasses)) in frame.iter_mut().zip(self.channels.iter_mut()) {
+                let input = *sample * 0.015;
+                let mut wet: f32 = combs
+                    .iter_mut()
+                    .map(|comb| comb.process(input, feedback, damping))
+                    .sum();
+                for allpass in allpasses.iter_mut() {
+                    wet = allpass.process(wet);
+                }
+                *sample = *sample * (1.0 - self.mix) + wet * self.mix * 3.0;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.built_for = None;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Soft clipping overdrive.
+pub struct Distortion {
+    // 1.0 is barely audible, 10.0 and above is heavily clipped.
+    pub drive: f32,
+    pub mix: f32,
+}
+
+impl Distortion {
+    pub fn new(drive: f32) -> Self {
+        Self { drive, mix: 1.0 }
+    }
+}
+
+impl Effect for Distortion {
+    fn process(&mut self, samples: &mut [f32], _channels: u16, _sample_rate: u32) {
+        let drive = self.drive.max(0.01);
+        let normalize = drive.tanh();
+        for sample in samples.iter_mut() {
+            let wet = (*sample * drive).tanh() / normalize;
+            *sample = *sample * (1.0 - self.mix) + wet * self.mix;
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// Reduces bit depth and sample rate for a lo-fi, retro console sound.
+pub struct Bitcrusher {
+    pub bits: u32,
+    // Holds each sample for this many frames.
+    pub downsample: u32,
+    held: Vec<f32>,
+    counter: u32,
+}
+
+impl Bitcrusher {
+    pub fn new(bits: u32, downsample: u32) -> Self {
+        Self {
+            bits,
+            downsample,
+            held: Vec::new(),
+            counter: 0,
+        }
+    }
+}
+
+impl Effect for Bitcrusher {
+    fn process(&mut self, samples: &mut [f32], channels: u16, _sample_rate: u32) {
+        let channels = channels.max(1) as usize;
+        self.held.resize(channels, 0.0);
+        let levels = 2f32.powi(self.bits.clamp(1, 24) as i32 - 1);
+
+        for frame in samples.chunks_mut(channels) {
+            if self.counter == 0 {
+                for (held, sample) in self.held.iter_mut().zip(frame.iter()) {
+                    *held = (sample * levels).round() / levels;
+                }
+            }
+            self.counter = (self.counter + 1) % self.downsample.max(1);
+            frame.copy_from_slice(&self.held[..frame.len()]);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.counter = 0;
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
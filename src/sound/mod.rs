@@ -0,0 +1,287 @@
+pub mod clip;
+pub mod effects;
+pub mod music;
+pub mod offline;
+pub mod spatial;
+pub mod synth;
+pub mod timeline;
+pub mod tracker;
+
+use crate::error::{Error, Result};
+use crate::geometry::camera::Camera;
+use clip::SoundClip;
+use effects::{EffectChain, Effected};
+use music::MusicPlayer;
+use offline::AudioBuffer;
+use spatial::{Emitter, Listener, SpatialOptions};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use cgmath::Vector2;
+use rodio::dynamic_mixer::{self, DynamicMixer, DynamicMixerController};
+use rodio::Source;
+
+const MIXER_CHANNELS: u16 = 2;
+const MIXER_SAMPLE_RATE: u32 = 44_100;
+
+// Sounds are mixed on the music or effects bus, which both feed the master
+// bus. Each bus has its own effect chain.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Bus {
+    Master,
+    Music,
+    Effects,
+}
+
+struct BusMixer {
+    mixer: Arc<DynamicMixerController<f32>>,
+    effects: EffectChain,
+}
+
+impl BusMixer {
+    fn new() -> (Self, Effected<KeepAlive>) {
+        let (mixer, output) = dynamic_mixer::mixer(MIXER_CHANNELS, MIXER_SAMPLE_RATE);
+        let effects = EffectChain::new();
+        let output = effects.apply(KeepAlive(output));
+        (Self { mixer, effects }, output)
+    }
+}
+
+pub struct SoundSystem {
+    #[allow(dead_code)]
+    device: Option<rodio::Device>,
+    // Without a device the mixed output is kept here to be rendered on demand.
+    offline_output: Option<Effected<KeepAlive>>,
+    sink: rodio::Sink,
+    master: BusMixer,
+    music_bus: BusMixer,
+    effects_bus: BusMixer,
+    listener: Listener,
+    listener_follows_camera: bool,
+    music: MusicPlayer,
+    clips: HashMap<String, SoundClip>,
+}
+
+impl SoundSystem {
+    pub fn new() -> Self {
+        let device = rodio::default_output_device().unwrap();
+        let (master, master_output) = BusMixer::new();
+        rodio::play_raw(&device, master_output);
+
+        Self::with_master(Some(device), None, master)
+    }
+
+    // Mixes exactly as `new` does, but nothing is played until `render` pulls
+    // samples out of the mixer. Useful for tests and for exporting audio.
+    pub fn new_offline() -> Self {
+        let (master, master_output) = BusMixer::new();
+
+        Self::with_master(None, Some(master_output), master)
+    }
+
+    fn with_master(
+        device: Option<rodio::Device>,
+        offline_output: Option<Effected<KeepAlive>>,
+        master: BusMixer,
+    ) -> Self {
+        let (music_bus, music_output) = BusMixer::new();
+        master.mixer.add(music_output);
+        let (effects_bus, effects_output) = BusMixer::new();
+        master.mixer.add(effects_output);
+
+        let (sink, sink_output) = rodio::Sink::new_idle();
+        sink.set_volume(0.5);
+        effects_bus.mixer.add(sink_output);
+        let music = MusicPlayer::new(music_bus.mixer.clone());
+
+        Self {
+            device,
+            offline_output,
+            sink,
+            master,
+            music_bus,
+            effects_bus,
+            listener: Listener::new(),
+            listener_follows_camera: true,
+            music,
+            clips: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    pub fn queue<S>(&self, sound: S)
+    where
+        S: rodio::Source + Send + 'static,
+        S::Item: rodio::Sample,
+        S::Item: Send,
+    {
+        self.sink.append(sound);
+    }
+
+    // Decodes the file and keeps it under `name`. Loading a name that is
+    // already known returns the cached clip without touching the disk.
+    pub fn load_sound<P: AsRef<Path>>(&mut self, name: &str, path: P) -> Result<SoundClip> {
+        if let Some(clip) = self.clips.get(name) {
+            return Ok(clip.clone());
+        }
+
+        let clip = SoundClip::load(path)?;
+        self.clips.insert(name.to_string(), clip.clone());
+        Ok(clip)
+    }
+
+    pub fn insert_sound(&mut self, name: &str, clip: SoundClip) {
+        self.clips.insert(name.to_string(), clip);
+    }
+
+    pub fn sound(&self, name: &str) -> Option<&SoundClip> {
+        self.clips.get(name)
+    }
+
+    pub fn unload_sound(&mut self, name: &str) -> Option<SoundClip> {
+        self.clips.remove(name)
+    }
+
+    // Plays the clip on top of anything else that is playing.
+    #[inline]
+    pub fn play(&self, clip: &SoundClip) {
+        self.play_with_volume(clip, 1.0);
+    }
+
+    pub fn play_with_volume(&self, clip: &SoundClip, volume: f32) {
+        self.play_source(clip.source().amplify(volume));
+    }
+
+    // Mixes any source into the effects bus, for example one wrapped in its
+    // own `EffectChain`.
+    pub fn play_source<S>(&self, source: S)
+    where
+        S: rodio::Source + Send + 'static,
+        S::Item: rodio::Sample + Send,
+    {
+        self.effects_bus.mixer.add(source.convert_samples());
+    }
+
+    pub fn play_named(&self, name: &str) -> Result<()> {
+        let clip = self
+            .sound(name)
+            .ok_or_else(|| Error::UnknownSound(name.to_string()))?;
+        self.play(clip);
+        Ok(())
+    }
+
+    // Plays the sound at a position in world coordinates. Sounds play
+    // concurrently, each with its own emitter.
+    #[inline]
+    pub fn queue_spatial<S>(&self, sound: S, position: Vector2<f32>) -> Emitter
+    where
+        S: rodio::Source + Send + 'static,
+        S::Item: rodio::Sample + Send,
+    {
+        self.queue_spatial_with_options(sound, position, SpatialOptions::default())
+    }
+
+    pub fn queue_spatial_with_options<S>(
+        &self,
+        sound: S,
+        position: Vector2<f32>,
+        options: SpatialOptions,
+    ) -> Emitter
+    where
+        S: rodio::Source + Send + 'static,
+        S::Item: rodio::Sample + Send,
+    {
+        let (source, emitter) =
+            spatial::spatialize(sound.convert_samples(), position, options, &self.listener);
+        self.effects_bus.mixer.add(source);
+        emitter
+    }
+
+    pub fn listener_position(&self) -> Vector2<f32> {
+        self.listener.position()
+    }
+
+    // Spatial sounds are heard from here. The listener follows the camera
+    // unless it's placed here, which stops it following until
+    // `set_listener_follows_camera` turns that back on.
+    pub fn set_listener_position(&mut self, position: Vector2<f32>) {
+        self.listener_follows_camera = false;
+        self.listener.set_position(position);
+    }
+
+    pub fn listener_follows_camera(&self) -> bool {
+        self.listener_follows_camera
+    }
+
+    // On by default, so what is on the left of the screen sounds on the left.
+    pub fn set_listener_follows_camera(&mut self, follow: bool) {
+        self.listener_follows_camera = follow;
+    }
+
+    // Moves the listener to the camera each frame while it follows it.
+    pub(crate) fn update_listener(&mut self, camera: &Camera) {
+        if self.listener_follows_camera {
+            self.listener.set_position(camera.position);
+        }
+    }
+
+    pub fn music(&mut self) -> &mut MusicPlayer {
+        &mut self.music
+    }
+
+    // Effects added here apply to everything mixed on the bus and can be
+    // tweaked while it plays.
+    pub fn bus_effects(&self, bus: Bus) -> &EffectChain {
+        match bus {
+            Bus::Master => &self.master.effects,
+            Bus::Music => &self.music_bus.effects,
+            Bus::Effects => &self.effects_bus.effects,
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline_output.is_some()
+    }
+
+    // Advances an offline sound system by `duration` and returns what was
+    // mixed. Returns `None` when the output is going to a device.
+    pub fn render(&mut self, duration: Duration) -> Option<AudioBuffer> {
+        self.offline_output
+            .as_mut()
+            .map(|output| offline::render(output, duration))
+    }
+}
+
+// The dynamic mixer ends as soon as it runs out of sounds, which would tear
+// down the output stream. Play silence instead until more sounds are added.
+struct KeepAlive(DynamicMixer<f32>);
+
+impl Iterator for KeepAlive {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        Some(self.0.next().unwrap_or(0.0))
+    }
+}
+
+impl Source for KeepAlive {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.0.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.0.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,501 @@
+use super::timeline::{BeatEvent, MusicPosition, Timeline};
+use super::tracker::{Module, TrackerControl};
+use crate::error::{Error, Result};
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use rodio::dynamic_mixer::DynamicMixerController;
+use rodio::Source;
+
+// How many samples the fader processes between checks for new commands.
+const CONTROL_PERIOD: u32 = 256;
+
+#[derive(Debug, Copy, Clone)]
+pub struct MusicOptions {
+    pub looping: bool,
+    // Where playback jumps back to when the track loops.
+    pub loop_start: Duration,
+    // Where the track loops, `None` loops at the end of the file. Has to come
+    // after `loop_start`.
+    pub loop_end: Option<Duration>,
+    pub volume: f32,
+}
+
+impl Default for MusicOptions {
+    fn default() -> Self {
+        Self {
+            looping: true,
+            loop_start: Duration::from_secs(0),
+            loop_end: None,
+            volume: 1.0,
+        }
+    }
+}
+
+pub struct MusicPlayer {
+    mixer: Arc<DynamicMixerController<f32>>,
+    current: Option<Arc<TrackControl>>,
+    volume: Arc<AtomicU32>,
+    // Set while the current track is a tracker module.
+    tracker: Option<TrackerControl>,
+    timeline: Option<Timeline>,
+    last_beat: i64,
+}
+
+impl MusicPlayer {
+    pub(crate) fn new(mixer: Arc<DynamicMixerController<f32>>) -> Self {
+        Self {
+            mixer,
+            current: None,
+            volume: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+            tracker: None,
+            timeline: None,
+            last_beat: -1,
+        }
+    }
+
+    // Cuts whatever is playing and starts the track immediately.
+    pub fn play<P: AsRef<Path>>(&mut self, path: P, options: MusicOptions) -> Result<()> {
+        self.crossfade_to(path, options, Duration::from_secs(0))
+    }
+
+    // Fades the current track out while the new one fades in over `duration`.
+    pub fn crossfade_to<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: MusicOptions,
+        duration: Duration,
+    ) -> Result<()> {
+        let stream = MusicStream::open(path.as_ref(), &options)?;
+        self.stop(duration);
+        self.push_track(stream, options, duration);
+        Ok(())
+    }
+
+    // Plays a tracker module in place of the current track, crossfading over
+    // `duration`. Modules loop at their own restart position so the loop
+    // points in `options` are ignored.
+    pub fn play_module(
+        &mut self,
+        module: &Module,
+        options: MusicOptions,
+        duration: Duration,
+    ) -> TrackerControl {
+        let player = module.player(options.looping);
+        let control = player.control();
+        self.stop(duration);
+        self.push_track(player, options, duration);
+        self.tracker = Some(control.clone());
+        control
+    }
+
+    pub fn stop(&mut self, fade_out: Duration) {
+        if let Some(track) = self.current.take() {
+            track.fade_to(0.0, fade_out, true);
+        }
+        self.tracker = None;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        match &self.current {
+            Some(track) => !track.finished.load(Ordering::Relaxed),
+            None => false,
+        }
+    }
+
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    // Scales every music track, including ones that are still fading out.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    // How beats are counted for the current and following tracks. Beats
+    // restart from zero whenever a new track starts.
+    pub fn set_timeline(&mut self, timeline: Option<Timeline>) {
+        self.timeline = timeline;
+        self.last_beat = self.position().map_or(-1, |position| position.beat_index());
+    }
+
+    pub fn timeline(&self) -> Option<Timeline> {
+        self.timeline
+    }
+
+    // How long the current track has been playing.
+    pub fn elapsed(&self) -> Option<Duration> {
+        let track = self.current.as_ref()?;
+        Some(Duration::from_micros(track.elapsed.load(Ordering::Relaxed)))
+    }
+
+    // `None` when nothing is playing, no timeline is set, or the timeline
+    // counts rows and the track isn't a module.
+    pub fn position(&self) -> Option<MusicPosition> {
+        let timeline = self.timeline?;
+        let time = self.elapsed()?;
+        let rows_played = self.tracker.as_ref().map(|tracker| tracker.rows_played());
+        Some(MusicPosition {
+            time,
+            beat: timeline.beat_at(time, rows_played)?,
+            beats_per_bar: timeline.beats_per_bar.max(1),
+        })
+    }
+
+    // Beats passed since the last call, oldest first.
+    pub(crate) fn take_beats(&mut self) -> Vec<BeatEvent> {
+        let position = match self.position() {
+            Some(position) => position,
+            None => return Vec::new(),
+        };
+
+        let first = (self.last_beat + 1).max(0);
+        let last = position.beat_index();
+        self.last_beat = self.last_beat.max(last);
+        (first..=last)
+            .map(|beat| BeatEvent::new(beat as u64, position.beats_per_bar))
+            .collect()
+    }
+
+    fn push_track<S>(&mut self, stream: S, options: MusicOptions, fade_in: Duration)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        let control = Arc::new(TrackControl {
+            fade: Mutex::new(None),
+            finished: AtomicBool::new(false),
+            elapsed: AtomicU64::new(0),
+        });
+        control.fade_to(options.volume, fade_in, false);
+
+        self.mixer.add(Fader {
+            input: stream,
+            control: control.clone(),
+            master: self.volume.clone(),
+            master_gain: self.volume(),
+            gain: 0.0,
+            target: 0.0,
+            step: 0.0,
+            stop_when_silent: false,
+            until_control: 0,
+            played: 0,
+        });
+        self.current = Some(control);
+        self.tracker = None;
+        self.last_beat = -1;
+    }
+}
+
+struct FadeRequest {
+    target: f32,
+    duration: Duration,
+    stop_when_silent: bool,
+}
+
+struct TrackControl {
+    fade: Mutex<Option<FadeRequest>>,
+    finished: AtomicBool,
+    // Microseconds of the track played so far.
+    elapsed: AtomicU64,
+}
+
+impl TrackControl {
+    fn fade_to(&self, target: f32, duration: Duration, stop_when_silent: bool) {
+        *self.fade.lock().unwrap() = Some(FadeRequest {
+            target,
+            duration,
+            stop_when_silent,
+        });
+    }
+}
+
+type FileDecoder = rodio::Decoder<BufReader<File>>;
+
+// Streams a track from disk, decoding as it plays instead of up front.
+struct MusicStream {
+    decoder: FileDecoder,
+    channels: u16,
+    sample_rate: u32,
+    looping: bool,
+    loop_start: u64,
+    loop_end: Option<u64>,
+    position: u64,
+    // Makes decoders ready at the loop start for the next time the track
+    // loops.
+    next_loop: Option<LoopWorker>,
+    // Samples of silence left to play while the next loop isn't ready.
+    silence: u16,
+}
+
+impl MusicStream {
+    fn open(path: &Path, options: &MusicOptions) -> Result<Self> {
+        if let Some(end) = options.loop_end {
+            if end <= options.loop_start {
+                return Err(Error::InvalidMusicOptions(format!(
+                    "loop end {:?} isn't after loop start {:?}",
+                    end, options.loop_start
+                )));
+            }
+        }
+
+        let decoder = rodio::Decoder::new(BufReader::new(File::open(path)?))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let to_samples = |duration: Duration| {
+            (duration.as_secs_f64() * sample_rate as f64) as u64 * channels as u64
+        };
+        let loop_start = to_samples(options.loop_start);
+
+        Ok(Self {
+            decoder,
+            channels,
+            sample_rate,
+            looping: options.looping,
+            loop_start,
+            loop_end: options.loop_end.map(to_samples),
+            position: 0,
+            next_loop: if options.looping {
+                Some(LoopWorker::spawn(path.to_path_buf(), loop_start)?)
+            } else {
+                None
+            },
+            silence: 0,
+        })
+    }
+
+    // Swaps in the decoder prepared at the loop start, asks for the next one
+    // and plays on from the loop start. The audio thread never waits for it:
+    // until it's ready the track plays silence a frame at a time.
+    fn loop_back(&mut self) -> Option<f32> {
+        let worker = self.next_loop.as_ref()?;
+        match worker.ready.try_recv() {
+            Ok(decoder) => {
+                self.decoder = decoder?;
+                worker.request();
+                self.position = self.loop_start;
+            }
+            Err(TryRecvError::Empty) => {
+                self.silence = self.channels.saturating_sub(1);
+                return Some(0.0);
+            }
+            Err(TryRecvError::Disconnected) => return None,
+        }
+
+        let sample = self.decoder.next()?;
+        self.position += 1;
+        Some(sample_to_f32(sample))
+    }
+}
+
+// Reopens the file and decodes up to the loop start on a thread of its own,
+// one decoder per request. The decoders can't seek, and this keeps the file
+// I/O and decoding off the audio thread. The thread ends with the stream.
+struct LoopWorker {
+    requests: Sender<()>,
+    ready: Receiver<Option<FileDecoder>>,
+}
+
+impl LoopWorker {
+    // Starts with the first decoder already requested.
+    fn spawn(path: PathBuf, loop_start: u64) -> Result<Self> {
+        let (requests, pending) = mpsc::channel();
+        let (finished, ready) = mpsc::channel();
+        thread::Builder::new()
+            .name("music-loop".to_string())
+            .spawn(move || {
+                while pending.recv().is_ok() {
+                    if finished.send(open_at(&path, loop_start)).is_err() {
+                        break;
+                    }
+                }
+            })?;
+
+        let worker = Self { requests, ready };
+        worker.request();
+        Ok(worker)
+    }
+
+    fn request(&self) {
+        let _ = self.requests.send(());
+    }
+}
+
+fn open_at(path: &Path, loop_start: u64) -> Option<FileDecoder> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => {
+            log::error!("Unable to reopen {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let mut decoder = match rodio::Decoder::new(BufReader::new(file)) {
+        Ok(decoder) => decoder,
+        Err(e) => {
+            log::error!("Unable to decode {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    for _ in 0..loop_start {
+        decoder.next()?;
+    }
+    Some(decoder)
+}
+
+impl Iterator for MusicStream {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(0.0);
+        }
+        if self.looping && matches!(self.loop_end, Some(end) if self.position >= end) {
+            return self.loop_back();
+        }
+
+        match self.decoder.next() {
+            Some(sample) => {
+                self.position += 1;
+                Some(sample_to_f32(sample))
+            }
+            // Guard against looping forever on a loop start past the end of the file.
+            None if self.looping && self.position > self.loop_start => self.loop_back(),
+            None => None,
+        }
+    }
+}
+
+impl Source for MusicStream {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[inline]
+fn sample_to_f32(sample: i16) -> f32 {
+    f32::from(sample) / 32768.0
+}
+
+// Applies fades requested through the track's control on the audio thread so
+// they stay smooth regardless of the frame rate.
+struct Fader<S> {
+    input: S,
+    control: Arc<TrackControl>,
+    master: Arc<AtomicU32>,
+    master_gain: f32,
+    gain: f32,
+    target: f32,
+    step: f32,
+    stop_when_silent: bool,
+    until_control: u32,
+    played: u64,
+}
+
+impl<S> Fader<S>
+where
+    S: Source<Item = f32>,
+{
+    fn poll_control(&mut self) {
+        self.master_gain = f32::from_bits(self.master.load(Ordering::Relaxed));
+        let samples_per_second = self.input.sample_rate() as u64 * self.input.channels() as u64;
+        self.control.elapsed.store(
+            self.played * 1_000_000 / samples_per_second.max(1),
+            Ordering::Relaxed,
+        );
+
+        if let Some(request) = self.control.fade.lock().unwrap().take() {
+            let samples = request.duration.as_secs_f32()
+                * self.input.sample_rate() as f32
+                * self.input.channels() as f32;
+            self.target = request.target;
+            self.stop_when_silent = request.stop_when_silent;
+            if samples < 1.0 {
+                self.gain = self.target;
+                self.step = 0.0;
+            } else {
+                self.step = (self.target - self.gain) / samples;
+            }
+        }
+    }
+}
+
+impl<S> Iterator for Fader<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.until_control == 0 {
+            self.poll_control();
+            self.until_control = CONTROL_PERIOD;
+        }
+        self.until_control -= 1;
+
+        if self.step != 0.0 {
+            self.gain += self.step;
+            if (self.step > 0.0 && self.gain >= self.target)
+                || (self.step < 0.0 && self.gain <= self.target)
+            {
+                self.gain = self.target;
+                self.step = 0.0;
+            }
+        }
+
+        if self.stop_when_silent && self.step == 0.0 && self.gain <= 0.0 {
+            self.control.finished.store(true, Ordering::Relaxed);
+            return None;
+        }
+
+        match self.input.next() {
+            Some(sample) => {
+                self.played += 1;
+                Some(sample * self.gain * self.master_gain)
+            }
+            None => {
+                self.control.finished.store(true, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+}
+
+impl<S> Source for Fader<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
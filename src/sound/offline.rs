@@ -0,0 +1,216 @@
+use crate::error::Result;
+use crate::sound::clip::SoundClip;
+
+use std::ops::Range;
+use std::path::Path;
+use std::time::Duration;
+
+use rodio::{Sample, Source};
+
+// Interleaved samples rendered without an output device.
+#[derive(Debug, Clone)]
+pub struct AudioBuffer {
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+impl AudioBuffer {
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.frames() as f64 / self.sample_rate as f64)
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    // The samples of every channel between two points in time.
+    pub fn window(&self, range: Range<Duration>) -> &[f32] {
+        let start = self.sample_index(range.start);
+        let end = self.sample_index(range.end).max(start);
+        &self.samples[start..end]
+    }
+
+    pub fn peak(&self, range: Range<Duration>) -> f32 {
+        self.window(range)
+            .iter()
+            .fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    pub fn rms(&self, range: Range<Duration>) -> f32 {
+        let window = self.window(range);
+        if window.is_empty() {
+            return 0.0;
+        }
+        let sum: f32 = window.iter().map(|sample| sample * sample).sum();
+        (sum / window.len() as f32).sqrt()
+    }
+
+    // When the output first rises above `threshold`, handy for checking that
+    // a sound started when it was supposed to.
+    pub fn onset(&self, threshold: f32) -> Option<Duration> {
+        let channels = self.channels.max(1) as usize;
+        self.samples
+            .iter()
+            .position(|sample| sample.abs() > threshold)
+            .map(|index| {
+                Duration::from_secs_f64((index / channels) as f64 / self.sample_rate as f64)
+            })
+    }
+
+    pub fn to_clip(&self) -> SoundClip {
+        SoundClip::from_samples(self.channels, self.sample_rate, self.samples.clone())
+    }
+
+    // Writes 16 bit PCM, which every tool can open.
+    pub fn write_wav<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let spec = hound::WavSpec {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for sample in &self.samples {
+            let sample = sample.clamp(-1.0, 1.0);
+            writer.write_sample((sample * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()?;
+        Ok(())
+    }
+
+    fn sample_index(&self, time: Duration) -> usize {
+        let frame = (time.as_secs_f64() * self.sample_rate as f64) as usize;
+        (frame * self.channels.max(1) as usize).min(self.samples.len())
+    }
+}
+
+// Pulls `duration` worth of samples out of a source, padding with silence if
+// it ends early. Finite sources can be rendered whole with `render_source`.
+pub fn render<S>(source: &mut S, duration: Duration) -> AudioBuffer
+where
+    S: Source<Item = f32>,
+{
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+    let frames = (duration.as_secs_f64() * sample_rate as f64) as usize;
+    let samples = source
+        .by_ref()
+        .chain(std::iter::repeat(0.0))
+        .take(frames * channels as usize)
+        .collect();
+
+    AudioBuffer {
+        channels,
+        sample_rate,
+        samples,
+    }
+}
+
+// Renders a finite source, such as a synth effect, until it ends.
+pub fn render_source<S>(source: S) -> AudioBuffer
+where
+    S: Source,
+    S::Item: Sample,
+{
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+
+    AudioBuffer {
+        channels,
+        sample_rate,
+        samples: source.convert_samples().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sound::synth::SynthParams;
+    use crate::sound::SoundSystem;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn clip() -> SoundClip {
+        SoundClip::from_samples(1, 44_100, vec![0.8; 4_410])
+    }
+
+    #[test]
+    fn played_clip_starts_on_time_at_its_volume() {
+        let mut system = SoundSystem::new_offline();
+        system.play_source(clip().source().delay(ms(100)));
+
+        let buffer = system.render(ms(300)).unwrap();
+        assert_eq!(buffer.channels, 2);
+        assert_eq!(buffer.duration(), ms(300));
+        assert_eq!(buffer.onset(0.01), Some(ms(100)));
+        assert_eq!(buffer.peak(ms(0)..ms(99)), 0.0);
+
+        let peak = buffer.peak(ms(100)..ms(200));
+        assert!((peak - 0.8).abs() < 1e-3, "peak {}", peak);
+        let rms = buffer.rms(ms(105)..ms(195));
+        assert!((rms - 0.8).abs() < 1e-3, "rms {}", rms);
+        assert_eq!(buffer.peak(ms(201)..ms(300)), 0.0);
+    }
+
+    #[test]
+    fn queued_clip_starts_on_time_at_half_volume() {
+        let mut system = SoundSystem::new_offline();
+        system.queue(clip().source().delay(ms(100)));
+
+        // An idle queue plays silence in 10ms pieces, so queued sounds start
+        // on the next one.
+        let buffer = system.render(ms(300)).unwrap();
+        let onset = buffer.onset(0.01).unwrap();
+        assert!(onset >= ms(100) && onset <= ms(110), "onset {:?}", onset);
+
+        let peak = buffer.peak(onset..onset + ms(100));
+        assert!((peak - 0.4).abs() < 1e-3, "peak {}", peak);
+        let rms = buffer.rms(onset + ms(5)..onset + ms(95));
+        assert!((rms - 0.4).abs() < 1e-3, "rms {}", rms);
+    }
+
+    #[test]
+    fn synth_effect_renders_its_envelope() {
+        let params = SynthParams::default();
+        let buffer = render_source(params.source());
+        assert_eq!(buffer.channels, 1);
+        assert_eq!(buffer.duration().as_millis(), params.duration().as_millis());
+
+        // A square wave with no attack starts at full volume and settles at
+        // the sustain level.
+        assert_eq!(buffer.onset(0.01), Some(ms(0)));
+        let peak = buffer.peak(ms(0)..ms(5));
+        assert!((peak - 0.5).abs() < 0.01, "peak {}", peak);
+        let rms = buffer.rms(ms(60)..ms(140));
+        assert!((rms - 0.25).abs() < 0.01, "rms {}", rms);
+        assert!(buffer.peak(ms(245)..ms(250)) < 0.02);
+    }
+
+    #[test]
+    fn synth_effect_mixes_offline() {
+        let mut system = SoundSystem::new_offline();
+        system.play_source(SynthParams::default().source());
+
+        let buffer = system.render(ms(400)).unwrap();
+        assert_eq!(buffer.onset(0.01), Some(ms(0)));
+        let rms = buffer.rms(ms(60)..ms(140));
+        assert!((rms - 0.25).abs() < 0.01, "rms {}", rms);
+        assert!(buffer.peak(ms(260)..ms(400)) < 1e-6);
+    }
+
+    #[test]
+    fn window_clamps_to_the_buffer() {
+        let buffer = AudioBuffer {
+            channels: 2,
+            sample_rate: 10,
+            samples: vec![0.5; 20],
+        };
+        assert_eq!(buffer.frames(), 10);
+        assert_eq!(buffer.window(ms(500)..ms(2_000)).len(), 10);
+        assert!(buffer.window(ms(800)..ms(200)).is_empty());
+        assert_eq!(buffer.rms(ms(0)..ms(0)), 0.0);
+    }
+}
@@ -0,0 +1,343 @@
+use std::f32::consts::FRAC_PI_4;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Vector2};
+use rodio::Source;
+
+// How many frames are rendered between reads of the emitter and listener positions.
+const CONTROL_PERIOD: u32 = 256;
+
+// The smallest `min_distance` used, so the curves never divide by zero.
+const MIN_DISTANCE: f32 = 1e-3;
+
+// How loudness falls off between `min_distance` and `max_distance`.
+#[derive(Debug, Copy, Clone)]
+pub enum Attenuation {
+    None,
+    Linear,
+    Inverse { rolloff: f32 },
+    Exponential { rolloff: f32 },
+}
+
+impl Attenuation {
+    fn gain(&self, distance: f32, min_distance: f32, max_distance: f32) -> f32 {
+        if distance >= max_distance {
+            return 0.0;
+        }
+        let min_distance = min_distance.max(MIN_DISTANCE);
+        let distance = distance.max(min_distance);
+
+        match *self {
+            Attenuation::None => 1.0,
+            Attenuation::Linear => {
+                1.0 - (distance - min_distance) / (max_distance - min_distance).max(f32::EPSILON)
+            }
+            Attenuation::Inverse { rolloff } => {
+                min_distance / (min_distance + rolloff * (distance - min_distance))
+            }
+            Attenuation::Exponential { rolloff } => (distance / min_distance).powf(-rolloff),
+        }
+    }
+}
+
+// Distances are in world units, like emitter and listener positions. The
+// defaults suit the default camera, which shows 2 units across: sounds pan
+// fully at the edge of the screen, play at full volume within a quarter unit
+// and fade out by 4 units, a screen beyond either edge. Scale them with the
+// camera's zoom for worlds shown at other sizes.
+#[derive(Debug, Copy, Clone)]
+pub struct SpatialOptions {
+    pub volume: f32,
+    pub attenuation: Attenuation,
+    // Closer than this the sound plays at full volume. Values at or near zero
+    // are treated as a small positive distance.
+    pub min_distance: f32,
+    // Further than this the sound is silent.
+    pub max_distance: f32,
+    // Horizontal offset from the listener at which the sound pans fully to one side.
+    pub pan_width: f32,
+}
+
+impl Default for SpatialOptions {
+    fn default() -> Self {
+        Self {
+            volume: 1.0,
+            attenuation: Attenuation::Linear,
+            min_distance: 0.25,
+            max_distance: 4.0,
+            pan_width: 1.0,
+        }
+    }
+}
+
+// Shared listener position that every spatial sound is heard from.
+#[derive(Clone)]
+pub(crate) struct Listener {
+    position: Arc<Mutex<Vector2<f32>>>,
+}
+
+impl Listener {
+    pub(crate) fn new() -> Self {
+        Self {
+            position: Arc::new(Mutex::new(Vector2::new(0.0, 0.0))),
+        }
+    }
+
+    pub(crate) fn position(&self) -> Vector2<f32> {
+        *self.position.lock().unwrap()
+    }
+
+    pub(crate) fn set_position(&self, position: Vector2<f32>) {
+        *self.position.lock().unwrap() = position;
+    }
+}
+
+struct EmitterState {
+    position: Mutex<Vector2<f32>>,
+    stopped: AtomicBool,
+    finished: AtomicBool,
+}
+
+// Handle to a playing spatial sound. Dropping it leaves the sound playing.
+#[derive(Clone)]
+pub struct Emitter {
+    state: Arc<EmitterState>,
+}
+
+impl Emitter {
+    pub fn position(&self) -> Vector2<f32> {
+        *self.state.position.lock().unwrap()
+    }
+
+    pub fn set_position(&self, position: Vector2<f32>) {
+        *self.state.position.lock().unwrap() = position;
+    }
+
+    pub fn stop(&self) {
+        self.state.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.finished.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) fn spatialize<S>(
+    input: S,
+    position: Vector2<f32>,
+    options: SpatialOptions,
+    listener: &Listener,
+) -> (SpatialSource<S>, Emitter)
+where
+    S: Source<Item = f32>,
+{
+    let state = Arc::new(EmitterState {
+        position: Mutex::new(position),
+        stopped: AtomicBool::new(false),
+        finished: AtomicBool::new(false),
+    });
+
+    let mut source = SpatialSource {
+        input,
+        state: state.clone(),
+        listener: listener.clone(),
+        options,
+        gains: [0.0, 0.0],
+        steps: [0.0, 0.0],
+        pending_right: None,
+        until_control: 0,
+    };
+    // Start at the right gains instead of ramping up from silence.
+    source.gains = source.target_gains();
+
+    (source, Emitter { state })
+}
+
+// Downmixes a sound to mono and pans it across a stereo pair based on where
+// its emitter is relative to the listener.
+pub(crate) struct SpatialSource<S> {
+    input: S,
+    state: Arc<EmitterState>,
+    listener: Listener,
+    options: SpatialOptions,
+    gains: [f32; 2],
+    steps: [f32; 2],
+    pending_right: Option<f32>,
+    until_control: u32,
+}
+
+impl<S> SpatialSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn target_gains(&self) -> [f32; 2] {
+        let offset = *self.state.position.lock().unwrap() - self.listener.position();
+        let options = &self.options;
+        let gain = options.volume
+            * options.attenuation.gain(
+                offset.magnitude(),
+                options.min_distance,
+                options.max_distance,
+            );
+
+        // Equal power panning keeps loudness constant as the sound crosses the listener.
+        let pan = (offset.x / options.pan_width.max(f32::EPSILON)).clamp(-1.0, 1.0);
+        let angle = (pan + 1.0) * FRAC_PI_4;
+        [gain * angle.cos(), gain * angle.sin()]
+    }
+
+    fn poll_control(&mut self) {
+        let targets = self.target_gains();
+        for ((step, target), gain) in self.steps.iter_mut().zip(&targets).zip(&self.gains) {
+            *step = (target - gain) / CONTROL_PERIOD as f32;
+        }
+    }
+
+    fn finish(&self) -> Option<f32> {
+        self.state.finished.store(true, Ordering::Relaxed);
+        None
+    }
+}
+
+impl<S> Iterator for SpatialSource<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+
+        if self.state.stopped.load(Ordering::Relaxed) {
+            return self.finish();
+        }
+
+        if self.until_control == 0 {
+            self.poll_control();
+            self.until_control = CONTROL_PERIOD;
+        }
+        self.until_control -= 1;
+
+        let channels = self.input.channels().max(1);
+        let mut sum = 0.0;
+        for _ in 0..channels {
+            match self.input.next() {
+                Some(sample) => sum += sample,
+                None => return self.finish(),
+            }
+        }
+        let mono = sum / channels as f32;
+
+        self.gains[0] += self.steps[0];
+        self.gains[1] += self.steps[1];
+        self.pending_right = Some(mono * self.gains[1]);
+        Some(mono * self.gains[0])
+    }
+}
+
+impl<S> Source for SpatialSource<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-5,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn attenuation_curves() {
+        let linear = Attenuation::Linear;
+        assert_near(linear.gain(0.0, 1.0, 3.0), 1.0);
+        assert_near(linear.gain(2.0, 1.0, 3.0), 0.5);
+        assert_near(linear.gain(3.0, 1.0, 3.0), 0.0);
+
+        let inverse = Attenuation::Inverse { rolloff: 1.0 };
+        assert_near(inverse.gain(2.0, 1.0, 10.0), 0.5);
+        assert_near(inverse.gain(4.0, 1.0, 10.0), 0.25);
+
+        let exponential = Attenuation::Exponential { rolloff: 2.0 };
+        assert_near(exponential.gain(2.0, 1.0, 10.0), 0.25);
+
+        // Past the max distance every curve is silent, and a zero min
+        // distance doesn't divide by zero.
+        assert_near(Attenuation::None.gain(10.0, 1.0, 10.0), 0.0);
+        assert!(inverse.gain(0.5, 0.0, 10.0).is_finite());
+    }
+
+    fn source_at(
+        position: Vector2<f32>,
+        listener: &Listener,
+    ) -> (SpatialSource<SamplesBuffer<f32>>, Emitter) {
+        let options = SpatialOptions {
+            attenuation: Attenuation::None,
+            ..SpatialOptions::default()
+        };
+        let sound = SamplesBuffer::new(2, 44_100, vec![1.0, 0.5, 1.0, 0.5]);
+        spatialize(sound, position, options, listener)
+    }
+
+    #[test]
+    fn pans_with_equal_power() {
+        let listener = Listener::new();
+        let centered = source_at(Vector2::new(0.0, 0.5), &listener).0;
+        let [left, right] = centered.target_gains();
+        assert_near(left, FRAC_PI_4.cos());
+        assert_near(right, FRAC_PI_4.cos());
+
+        // A pan width to the right, or further, plays in the right channel only.
+        let (right_side, emitter) = source_at(Vector2::new(1.0, 0.0), &listener);
+        assert_near(right_side.target_gains()[0], 0.0);
+        assert_near(right_side.target_gains()[1], 1.0);
+        emitter.set_position(Vector2::new(-3.0, 0.0));
+        assert_near(right_side.target_gains()[0], 1.0);
+
+        // Positions are relative to the listener.
+        listener.set_position(Vector2::new(-3.0, 0.0));
+        let [left, right] = right_side.target_gains();
+        assert_near(left, right);
+    }
+
+    #[test]
+    fn downmixes_to_a_stereo_pair() {
+        let listener = Listener::new();
+        let (source, emitter) = source_at(Vector2::new(0.0, 0.0), &listener);
+        assert_eq!(source.channels(), 2);
+
+        let samples: Vec<f32> = source.collect();
+        assert_eq!(samples.len(), 4);
+        for sample in samples {
+            assert_near(sample, 0.75 * FRAC_PI_4.cos());
+        }
+        assert!(emitter.is_finished());
+    }
+}
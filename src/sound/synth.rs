@@ -0,0 +1,377 @@
+use crate::sound::clip::SoundClip;
+
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Waveform {
+    // `duty` is the fraction of each period spent high, 0.5 for a plain square.
+    Square { duty: f32 },
+    Triangle,
+    Saw,
+    Sine,
+    Noise,
+}
+
+// Attack, decay and release are in seconds. `sustain` is the level held
+// between decay and release.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+impl Envelope {
+    fn level(&self, time: f32, hold: f32) -> f32 {
+        let release_start = self.attack + self.decay + hold;
+        if time < self.attack {
+            time / self.attack
+        } else if time < self.attack + self.decay {
+            1.0 - (1.0 - self.sustain) * (time - self.attack) / self.decay
+        } else if time < release_start {
+            self.sustain
+        } else if time < release_start + self.release {
+            self.sustain * (1.0 - (time - release_start) / self.release)
+        } else {
+            0.0
+        }
+    }
+}
+
+// Steps through pitch offsets, in semitones, every `step` seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Arpeggio {
+    pub semitones: Vec<f32>,
+    pub step: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SynthParams {
+    pub waveform: Waveform,
+    // Starting pitch in Hz.
+    pub frequency: f32,
+    // Pitch change in octaves per second, negative values sweep down.
+    pub sweep: f32,
+    pub arpeggio: Option<Arpeggio>,
+    pub envelope: Envelope,
+    // Seconds spent at the sustain level before releasing.
+    pub hold: f32,
+    pub volume: f32,
+}
+
+impl Default for SynthParams {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::Square { duty: 0.5 },
+            frequency: 440.0,
+            sweep: 0.0,
+            arpeggio: None,
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.05,
+                sustain: 0.5,
+                release: 0.1,
+            },
+            hold: 0.1,
+            volume: 0.5,
+        }
+    }
+}
+
+impl SynthParams {
+    pub fn blip() -> Self {
+        Self {
+            waveform: Waveform::Square { duty: 0.5 },
+            frequency: 880.0,
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.02,
+                sustain: 0.6,
+                release: 0.04,
+            },
+            hold: 0.02,
+            ..Self::default()
+        }
+    }
+
+    pub fn coin() -> Self {
+        Self {
+            waveform: Waveform::Square { duty: 0.25 },
+            frequency: 988.0,
+            arpeggio: Some(Arpeggio {
+                semitones: vec![0.0, 5.0],
+                step: 0.06,
+            }),
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.05,
+                sustain: 0.7,
+                release: 0.15,
+            },
+            hold: 0.1,
+            ..Self::default()
+        }
+    }
+
+    pub fn explosion() -> Self {
+        Self {
+            waveform: Waveform::Noise,
+            frequency: 1200.0,
+            sweep: -2.5,
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.1,
+                sustain: 0.6,
+                release: 0.5,
+            },
+            hold: 0.1,
+            volume: 0.7,
+            ..Self::default()
+        }
+    }
+
+    pub fn jump() -> Self {
+        Self {
+            waveform: Waveform::Square { duty: 0.5 },
+            frequency: 300.0,
+            sweep: 2.0,
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.05,
+                sustain: 0.5,
+                release: 0.1,
+            },
+            hold: 0.1,
+            ..Self::default()
+        }
+    }
+
+    pub fn laser() -> Self {
+        Self {
+            waveform: Waveform::Saw,
+            frequency: 1400.0,
+            sweep: -4.0,
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.02,
+                sustain: 0.5,
+                release: 0.12,
+            },
+            hold: 0.05,
+            ..Self::default()
+        }
+    }
+
+    pub fn hit() -> Self {
+        Self {
+            waveform: Waveform::Noise,
+            frequency: 3000.0,
+            sweep: -3.0,
+            envelope: Envelope {
+                attack: 0.0,
+                decay: 0.03,
+                sustain: 0.3,
+                release: 0.08,
+            },
+            hold: 0.0,
+            ..Self::default()
+        }
+    }
+
+    // Negative and NaN times count as zero, and a total too long to play
+    // comes out as zero too, since presets come from files.
+    pub fn duration(&self) -> Duration {
+        let params = self.clamped();
+        let envelope = &params.envelope;
+        let seconds = envelope.attack + envelope.decay + params.hold + envelope.release;
+        Duration::try_from_secs_f32(seconds).unwrap_or_default()
+    }
+
+    // These params with the envelope's times and `hold` no less than zero and
+    // the sustain level between zero and one, which is how they're played.
+    fn clamped(&self) -> Self {
+        let time = |time: f32| time.max(0.0);
+        Self {
+            envelope: Envelope {
+                attack: time(self.envelope.attack),
+                decay: time(self.envelope.decay),
+                // `max` turns NaN into zero, which `clamp` wouldn't.
+                sustain: self.envelope.sustain.max(0.0).clamp(0.0, 1.0),
+                release: time(self.envelope.release),
+            },
+            hold: time(self.hold),
+            ..self.clone()
+        }
+    }
+
+    pub fn source(&self) -> Synth {
+        let params = self.clamped();
+        Synth {
+            length: (params.duration().as_secs_f32() * SAMPLE_RATE as f32) as u64,
+            params,
+            sample: 0,
+            phase: 0.0,
+            noise: 0.0,
+            noise_state: 0x1234_5678,
+        }
+    }
+
+    // Renders the whole effect up front so it can be replayed without
+    // running the oscillators again.
+    pub fn to_clip(&self) -> SoundClip {
+        SoundClip::from_samples(1, SAMPLE_RATE, self.source().collect())
+    }
+}
+
+// Mono oscillator voice that plays a `SynthParams` once.
+pub struct Synth {
+    params: SynthParams,
+    length: u64,
+    sample: u64,
+    phase: f32,
+    noise: f32,
+    noise_state: u32,
+}
+
+impl Synth {
+    fn frequency(&self, time: f32) -> f32 {
+        let mut semitones = 0.0;
+        if let Some(arpeggio) = &self.params.arpeggio {
+            if !arpeggio.semitones.is_empty() && arpeggio.step > 0.0 {
+                let step = (time / arpeggio.step) as usize % arpeggio.semitones.len();
+                semitones = arpeggio.semitones[step];
+            }
+        }
+
+        let octaves = self.params.sweep * time + semitones / 12.0;
+        (self.params.frequency * 2f32.powf(octaves)).clamp(1.0, SAMPLE_RATE as f32 * 0.5)
+    }
+
+    fn next_noise(&mut self) -> f32 {
+        // xorshift32, seeded the same every time so effects render identically.
+        let mut x = self.noise_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.noise_state = x;
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+
+    fn oscillate(&mut self) -> f32 {
+        match self.params.waveform {
+            Waveform::Square { duty } => {
+                if self.phase < duty {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Saw => 2.0 * self.phase - 1.0,
+            Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+            Waveform::Noise => self.noise,
+        }
+    }
+}
+
+impl Iterator for Synth {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.sample >= self.length {
+            return None;
+        }
+
+        let time = self.sample as f32 / SAMPLE_RATE as f32;
+        let value = self.oscillate()
+            * self.params.envelope.level(time, self.params.hold)
+            * self.params.volume;
+
+        self.phase += self.frequency(time) / SAMPLE_RATE as f32;
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            // Noise holds a random level for each period, so its pitch follows the frequency.
+            self.noise = self.next_noise();
+        }
+        self.sample += 1;
+
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.length - self.sample) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl Source for Synth {
+    fn current_frame_len(&self) -> Option<usize> {
+        Some((self.length - self.sample) as usize)
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        Some(self.params.duration())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamps_negative_envelope_times() {
+        let params = SynthParams {
+            envelope: Envelope {
+                attack: -1.0,
+                decay: f32::NAN,
+                sustain: -0.5,
+                release: -0.2,
+            },
+            hold: 0.01,
+            ..SynthParams::default()
+        };
+        assert_eq!(params.duration(), Duration::from_secs_f32(0.01));
+
+        let samples: Vec<f32> = params.source().collect();
+        assert_eq!(samples.len(), (0.01 * SAMPLE_RATE as f32) as usize);
+        assert!(samples.iter().all(|sample| sample.is_finite()));
+    }
+
+    #[test]
+    fn envelope_levels_stay_in_range() {
+        let envelope = SynthParams {
+            envelope: Envelope {
+                attack: 0.1,
+                decay: -0.1,
+                sustain: 2.0,
+                release: 0.1,
+            },
+            ..SynthParams::default()
+        }
+        .clamped()
+        .envelope;
+
+        for step in 0..40 {
+            let level = envelope.level(step as f32 * 0.01, 0.1);
+            assert!((0.0..=1.0).contains(&level), "{}", level);
+        }
+        assert_eq!(envelope.level(0.05, 0.1), 0.5);
+        assert_eq!(envelope.level(0.15, 0.1), 1.0);
+        assert_eq!(envelope.level(0.5, 0.1), 0.0);
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+// Where beats come from for the current music track.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BeatClock {
+    // A fixed tempo, with the first beat `offset` into the track.
+    Bpm { bpm: f32, offset: Duration },
+    // Follows a tracker module's rows, so beats keep in step with tempo changes
+    // inside the song and with `TrackerControl::set_tempo_scale`.
+    Rows { rows_per_beat: u32 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Timeline {
+    pub clock: BeatClock,
+    pub beats_per_bar: u32,
+}
+
+impl Timeline {
+    pub fn bpm(bpm: f32) -> Self {
+        Self {
+            clock: BeatClock::Bpm {
+                bpm,
+                offset: Duration::from_secs(0),
+            },
+            beats_per_bar: 4,
+        }
+    }
+
+    // Most modules are written with four rows to the beat.
+    pub fn rows(rows_per_beat: u32) -> Self {
+        Self {
+            clock: BeatClock::Rows { rows_per_beat },
+            beats_per_bar: 4,
+        }
+    }
+
+    pub fn with_offset(mut self, offset: Duration) -> Self {
+        if let BeatClock::Bpm { bpm, .. } = self.clock {
+            self.clock = BeatClock::Bpm { bpm, offset };
+        }
+        self
+    }
+
+    pub fn with_beats_per_bar(mut self, beats_per_bar: u32) -> Self {
+        self.beats_per_bar = beats_per_bar.max(1);
+        self
+    }
+
+    // Beats since the first one, negative before the offset.
+    pub(crate) fn beat_at(&self, elapsed: Duration, rows_played: Option<usize>) -> Option<f64> {
+        match self.clock {
+            BeatClock::Bpm { bpm, offset } => {
+                let seconds = elapsed.as_secs_f64() - offset.as_secs_f64();
+                Some(seconds * bpm as f64 / 60.0)
+            }
+            BeatClock::Rows { rows_per_beat } => {
+                Some(rows_played? as f64 / rows_per_beat.max(1) as f64)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MusicPosition {
+    // How long the track has been playing, including any loops.
+    pub time: Duration,
+    // Beats since the first, the fractional part is the progress to the next.
+    pub beat: f64,
+    pub beats_per_bar: u32,
+}
+
+impl MusicPosition {
+    pub fn beat_index(&self) -> i64 {
+        self.beat.floor() as i64
+    }
+
+    pub fn bar(&self) -> i64 {
+        self.beat_index().div_euclid(self.beats_per_bar as i64)
+    }
+
+    pub fn beat_in_bar(&self) -> u32 {
+        self.beat_index().rem_euclid(self.beats_per_bar as i64) as u32
+    }
+
+    // 0 right on the beat rising to 1 just before the next, handy for pulsing.
+    pub fn beat_phase(&self) -> f32 {
+        self.beat.rem_euclid(1.0) as f32
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BeatEvent {
+    pub beat: u64,
+    pub bar: u64,
+    pub beat_in_bar: u32,
+}
+
+impl BeatEvent {
+    pub(crate) fn new(beat: u64, beats_per_bar: u32) -> Self {
+        let beats_per_bar = beats_per_bar.max(1) as u64;
+        Self {
+            beat,
+            bar: beat / beats_per_bar,
+            beat_in_bar: (beat % beats_per_bar) as u32,
+        }
+    }
+
+    // The first beat of a bar.
+    pub fn is_downbeat(&self) -> bool {
+        self.beat_in_bar == 0
+    }
+}
@@ -0,0 +1,266 @@
+mod player;
+mod protracker;
+mod xm;
+
+pub use player::TrackerPlayer;
+
+use crate::error::{Error, Result};
+
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+pub(crate) const NOTE_KEY_OFF: u8 = 97;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct Note {
+    // 1 is C-0, 0 means no note and `NOTE_KEY_OFF` releases the current one.
+    pub note: u8,
+    // 1 based, 0 keeps the channel's instrument.
+    pub instrument: u8,
+    // Raw XM volume column byte, 0 when empty.
+    pub volume: u8,
+    pub effect: u8,
+    pub param: u8,
+}
+
+pub(crate) struct Pattern {
+    pub rows: usize,
+    // Row major, one note per channel for each row. Trailing empty notes can
+    // be left out.
+    pub notes: Vec<Note>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum LoopKind {
+    None,
+    Forward,
+    PingPong,
+}
+
+pub(crate) struct SampleData {
+    pub data: Vec<f32>,
+    pub loop_kind: LoopKind,
+    pub loop_start: usize,
+    pub loop_length: usize,
+    // 0 to 64.
+    pub volume: u8,
+    // In 1/128ths of a semitone.
+    pub finetune: i8,
+    pub relative_note: i8,
+    // ProTracker samples don't carry panning and leave the channel's as it is.
+    pub panning: Option<u8>,
+}
+
+#[derive(Clone)]
+pub(crate) struct Envelope {
+    // Tick and value (0 to 64) pairs.
+    pub points: Vec<(u16, u16)>,
+    pub sustain: Option<usize>,
+    pub loop_range: Option<(usize, usize)>,
+}
+
+pub(crate) struct Instrument {
+    pub samples: Vec<SampleData>,
+    // Which sample each of the 96 notes plays.
+    pub sample_map: [u8; 96],
+    pub volume_envelope: Option<Envelope>,
+    pub fadeout: u16,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum FrequencyMode {
+    Amiga,
+    Linear,
+}
+
+pub(crate) struct ModuleData {
+    pub title: String,
+    pub channels: usize,
+    pub orders: Vec<usize>,
+    pub restart: usize,
+    pub patterns: Vec<Pattern>,
+    pub instruments: Vec<Instrument>,
+    pub speed: u32,
+    pub tempo: u32,
+    pub frequency_mode: FrequencyMode,
+    // Initial panning of each channel, 0 is hard left and 255 hard right.
+    pub panning: Vec<u8>,
+}
+
+// A ProTracker MOD or FastTracker XM song. Clones share the same data.
+#[derive(Clone)]
+pub struct Module {
+    pub(crate) data: Arc<ModuleData>,
+}
+
+impl Module {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let data = if bytes.starts_with(xm::SIGNATURE) {
+            xm::parse(bytes)?
+        } else {
+            protracker::parse(bytes)?
+        };
+
+        Ok(Self {
+            data: Arc::new(data),
+        })
+    }
+
+    pub fn title(&self) -> &str {
+        &self.data.title
+    }
+
+    pub fn channels(&self) -> usize {
+        self.data.channels
+    }
+
+    // Ticks per row and beats per minute the song starts with.
+    pub fn initial_speed(&self) -> u32 {
+        self.data.speed
+    }
+
+    pub fn initial_tempo(&self) -> u32 {
+        self.data.tempo
+    }
+
+    pub fn player(&self, looping: bool) -> TrackerPlayer {
+        TrackerPlayer::new(self.clone(), looping)
+    }
+}
+
+pub(crate) struct TrackerShared {
+    pub muted: Vec<AtomicBool>,
+    pub tempo_scale: AtomicU32,
+    pub order: AtomicUsize,
+    pub row: AtomicUsize,
+    pub rows_played: AtomicUsize,
+    pub finished: AtomicBool,
+}
+
+// Controls a playing module from the game while the audio thread renders it.
+#[derive(Clone)]
+pub struct TrackerControl {
+    pub(crate) shared: Arc<TrackerShared>,
+}
+
+impl TrackerControl {
+    pub(crate) fn new(channels: usize) -> Self {
+        Self {
+            shared: Arc::new(TrackerShared {
+                muted: (0..channels).map(|_| AtomicBool::new(false)).collect(),
+                tempo_scale: AtomicU32::new(1.0f32.to_bits()),
+                order: AtomicUsize::new(0),
+                row: AtomicUsize::new(0),
+                rows_played: AtomicUsize::new(0),
+                finished: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.shared.muted.len()
+    }
+
+    // Muting channels while the song plays is the usual way to layer
+    // adaptive music, e.g. only bringing in the drums during a boss fight.
+    pub fn set_channel_muted(&self, channel: usize, muted: bool) {
+        if let Some(flag) = self.shared.muted.get(channel) {
+            flag.store(muted, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_channel_muted(&self, channel: usize) -> bool {
+        matches!(self.shared.muted.get(channel), Some(flag) if flag.load(Ordering::Relaxed))
+    }
+
+    // Multiplies the song's tempo, 2.0 plays twice as fast without changing pitch.
+    pub fn set_tempo_scale(&self, scale: f32) {
+        self.shared
+            .tempo_scale
+            .store(scale.max(0.01).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn tempo_scale(&self) -> f32 {
+        f32::from_bits(self.shared.tempo_scale.load(Ordering::Relaxed))
+    }
+
+    // Position in the order list and row in the current pattern.
+    pub fn order(&self) -> usize {
+        self.shared.order.load(Ordering::Relaxed)
+    }
+
+    pub fn row(&self) -> usize {
+        self.shared.row.load(Ordering::Relaxed)
+    }
+
+    // Rows since the song started, counting repeats and loops.
+    pub fn rows_played(&self) -> usize {
+        self.shared.rows_played.load(Ordering::Relaxed)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> Error {
+    Error::InvalidModule(message.to_string())
+}
+
+// Little and big endian readers that report truncated files instead of panicking.
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub position: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], position: usize) -> Self {
+        Self { bytes, position }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.position)
+    }
+
+    pub fn bytes(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self.position + length;
+        let slice = self
+            .bytes
+            .get(self.position..end)
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        self.position = end;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16_le(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u16_be(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32_le(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn string(&mut self, length: usize) -> Result<String> {
+        let bytes = self.bytes(length)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8_lossy(&bytes[..end])
+            .trim_end()
+            .to_string())
+    }
+}
@@ -0,0 +1,731 @@
+use super::*;
+
+use std::time::Duration;
+
+use rodio::Source;
+
+const OUTPUT_RATE: u32 = 44_100;
+// Amiga periods in FastTracker's units, C-4 at period 1712 plays at 8363Hz.
+const AMIGA_CLOCK: f32 = 8363.0 * 1712.0;
+const MAX_VOLUME: i32 = 64;
+const FADE_MAX: i32 = 65536;
+
+const VIBRATO_TABLE: [i32; 32] = [
+    0, 24, 49, 74, 97, 120, 141, 161, 180, 197, 212, 224, 235, 244, 250, 253, 255, 253, 250, 244,
+    235, 224, 212, 197, 180, 161, 141, 120, 97, 74, 49, 24,
+];
+
+// Effect numbers. XM's lettered effects continue after F, so G is 16.
+const ARPEGGIO: u8 = 0x0;
+const PORTA_UP: u8 = 0x1;
+const PORTA_DOWN: u8 = 0x2;
+const TONE_PORTA: u8 = 0x3;
+const VIBRATO: u8 = 0x4;
+const TONE_PORTA_VOLUME_SLIDE: u8 = 0x5;
+const VIBRATO_VOLUME_SLIDE: u8 = 0x6;
+const SET_PANNING: u8 = 0x8;
+const SAMPLE_OFFSET: u8 = 0x9;
+const VOLUME_SLIDE: u8 = 0xa;
+const POSITION_JUMP: u8 = 0xb;
+const SET_VOLUME: u8 = 0xc;
+const PATTERN_BREAK: u8 = 0xd;
+const EXTENDED: u8 = 0xe;
+const SET_SPEED: u8 = 0xf;
+const SET_GLOBAL_VOLUME: u8 = 16;
+const GLOBAL_VOLUME_SLIDE: u8 = 17;
+const KEY_OFF: u8 = 20;
+
+#[derive(Default)]
+struct Channel {
+    instrument: usize,
+    sample: Option<(usize, usize)>,
+    playing: bool,
+    position: f64,
+    forward: bool,
+    step: f64,
+
+    note: i32,
+    finetune: i8,
+    period: f32,
+    target_period: f32,
+    volume: i32,
+    panning: i32,
+
+    effect: u8,
+    param: u8,
+    volume_column: u8,
+    delayed: Option<Note>,
+    arpeggio: i32,
+    vibrato_offset: f32,
+
+    porta_up: u8,
+    porta_down: u8,
+    tone_porta_speed: u8,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    vibrato_position: u8,
+    volume_slide: u8,
+    global_volume_slide: u8,
+    sample_offset: u8,
+
+    key_on: bool,
+    fade: i32,
+    envelope_tick: u16,
+
+    loop_row: usize,
+    loop_count: u8,
+}
+
+// Renders a `Module` as stereo samples at 44.1kHz.
+pub struct TrackerPlayer {
+    module: Module,
+    control: TrackerControl,
+    looping: bool,
+    channels: Vec<Channel>,
+    order: usize,
+    row: usize,
+    tick: u32,
+    speed: u32,
+    tempo: u32,
+    global_volume: i32,
+    pattern_delay: u32,
+    jump: Option<(usize, usize)>,
+    frames_until_tick: f64,
+    pending_right: Option<f32>,
+    amplitude: f32,
+    finished: bool,
+}
+
+impl TrackerPlayer {
+    pub fn new(module: Module, looping: bool) -> Self {
+        let data = module.data.clone();
+        let channels = data
+            .panning
+            .iter()
+            .map(|&panning| Channel {
+                panning: panning as i32,
+                forward: true,
+                fade: FADE_MAX,
+                ..Channel::default()
+            })
+            .collect();
+
+        Self {
+            control: TrackerControl::new(data.channels),
+            looping,
+            channels,
+            order: 0,
+            row: 0,
+            tick: 0,
+            speed: data.speed,
+            tempo: data.tempo,
+            global_volume: MAX_VOLUME,
+            pattern_delay: 0,
+            jump: None,
+            frames_until_tick: 0.0,
+            pending_right: None,
+            // Keeps a busy song from clipping without making sparse ones too quiet.
+            amplitude: 0.7 / (data.channels as f32).sqrt(),
+            finished: data.orders.is_empty(),
+            module,
+        }
+    }
+
+    pub fn control(&self) -> TrackerControl {
+        self.control.clone()
+    }
+
+    fn frames_per_tick(&self) -> f64 {
+        let scale = f32::from_bits(self.control.shared.tempo_scale.load(Ordering::Relaxed));
+        OUTPUT_RATE as f64 * 2.5 / (self.tempo as f64 * scale as f64)
+    }
+
+    fn period_for(&self, note: i32, finetune: i8) -> f32 {
+        match self.module.data.frequency_mode {
+            FrequencyMode::Linear => 7680.0 - note as f32 * 64.0 - finetune as f32 / 2.0,
+            FrequencyMode::Amiga => {
+                1712.0 * 2f32.powf(-(note as f32 - 48.0 + finetune as f32 / 128.0) / 12.0)
+            }
+        }
+    }
+
+    fn frequency_for(&self, period: f32, arpeggio: i32) -> f32 {
+        match self.module.data.frequency_mode {
+            FrequencyMode::Linear => {
+                let period = period - arpeggio as f32 * 64.0;
+                8363.0 * 2f32.powf((4608.0 - period) / 768.0)
+            }
+            FrequencyMode::Amiga => {
+                let period = period / 2f32.powf(arpeggio as f32 / 12.0);
+                AMIGA_CLOCK / period.max(1.0)
+            }
+        }
+    }
+
+    fn process_tick(&mut self) {
+        let row_tick = self.tick % self.speed.max(1);
+        if self.tick == 0 {
+            self.process_row();
+        } else {
+            for index in 0..self.channels.len() {
+                self.process_channel_tick(index, row_tick);
+            }
+        }
+
+        for index in 0..self.channels.len() {
+            self.update_channel(index);
+        }
+
+        self.tick += 1;
+        if self.tick >= self.speed.max(1) * (1 + self.pattern_delay) {
+            self.tick = 0;
+            self.pattern_delay = 0;
+            self.advance_row();
+        }
+    }
+
+    fn advance_row(&mut self) {
+        let data = self.module.data.clone();
+        match self.jump.take() {
+            Some((order, row)) => {
+                self.order = order;
+                self.row = row;
+            }
+            None => {
+                self.row += 1;
+                if self.row >= self.pattern_rows(self.order) {
+                    self.row = 0;
+                    self.order += 1;
+                }
+            }
+        }
+
+        if self.order >= data.orders.len() {
+            if self.looping {
+                self.order = data.restart;
+            } else {
+                self.finished = true;
+            }
+        }
+        if self.row >= self.pattern_rows(self.order) {
+            self.row = 0;
+        }
+
+        let shared = &self.control.shared;
+        shared.order.store(self.order, Ordering::Relaxed);
+        shared.row.store(self.row, Ordering::Relaxed);
+        shared.rows_played.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn pattern_rows(&self, order: usize) -> usize {
+        let data = &self.module.data;
+        data.orders
+            .get(order)
+            .and_then(|&pattern| data.patterns.get(pattern))
+            .map_or(64, |pattern| pattern.rows)
+    }
+
+    fn process_row(&mut self) {
+        let data = self.module.data.clone();
+        let pattern = data
+            .orders
+            .get(self.order)
+            .and_then(|&pattern| data.patterns.get(pattern));
+
+        for index in 0..self.channels.len() {
+            let note = pattern
+                .and_then(|pattern| pattern.notes.get(self.row * data.channels + index))
+                .copied()
+                .unwrap_or_default();
+
+            let channel = &mut self.channels[index];
+            channel.effect = note.effect;
+            channel.param = note.param;
+            channel.volume_column = note.volume;
+            channel.arpeggio = 0;
+            channel.vibrato_offset = 0.0;
+
+            let delay = note.effect == EXTENDED && note.param >> 4 == 0xd && note.param & 0xf > 0;
+            if delay {
+                channel.delayed = Some(note);
+            } else {
+                channel.delayed = None;
+                self.trigger(index, note);
+                self.process_volume_column(index, true);
+            }
+            self.process_row_effect(index);
+        }
+    }
+
+    fn trigger(&mut self, index: usize, note: Note) {
+        let data = self.module.data.clone();
+        let tone_porta = note.effect == TONE_PORTA
+            || note.effect == TONE_PORTA_VOLUME_SLIDE
+            || note.volume >> 4 == 0xf;
+
+        if note.instrument > 0 {
+            let channel = &mut self.channels[index];
+            channel.instrument = note.instrument as usize;
+            channel.key_on = true;
+            channel.fade = FADE_MAX;
+            channel.envelope_tick = 0;
+
+            let sample_note = if note.note > 0 && note.note < NOTE_KEY_OFF {
+                note.note as usize - 1
+            } else {
+                (channel.note.max(0) as usize).min(95)
+            };
+            let sample = data
+                .instruments
+                .get(channel.instrument - 1)
+                .and_then(|instrument| {
+                    instrument
+                        .samples
+                        .get(instrument.sample_map[sample_note] as usize)
+                });
+            if let Some(sample) = sample {
+                channel.volume = sample.volume as i32;
+                if let Some(panning) = sample.panning {
+                    channel.panning = panning as i32;
+                }
+            }
+        }
+
+        if note.note == NOTE_KEY_OFF {
+            self.key_off(index);
+            return;
+        }
+        if note.note == 0 || note.note > 96 {
+            return;
+        }
+
+        let channel = &self.channels[index];
+        let instrument_index = channel.instrument.wrapping_sub(1);
+        let instrument = match data.instruments.get(instrument_index) {
+            Some(instrument) => instrument,
+            None => return,
+        };
+        let sample_index = instrument.sample_map[note.note as usize - 1] as usize;
+        let sample = match instrument.samples.get(sample_index) {
+            Some(sample) => sample,
+            None => return,
+        };
+
+        let real_note = note.note as i32 - 1 + sample.relative_note as i32;
+        let period = self.period_for(real_note, sample.finetune);
+        let channel = &mut self.channels[index];
+        channel.note = real_note;
+
+        if tone_porta && channel.playing {
+            channel.target_period = period;
+            return;
+        }
+
+        if note.effect == SAMPLE_OFFSET && note.param > 0 {
+            channel.sample_offset = note.param;
+        }
+        channel.position = if note.effect == SAMPLE_OFFSET {
+            channel.sample_offset as f64 * 256.0
+        } else {
+            0.0
+        };
+        channel.sample = Some((instrument_index, sample_index));
+        channel.finetune = sample.finetune;
+        channel.period = period;
+        channel.target_period = period;
+        channel.forward = true;
+        channel.playing = channel.position < sample.data.len() as f64;
+        channel.key_on = true;
+        channel.fade = FADE_MAX;
+        channel.envelope_tick = 0;
+        channel.vibrato_position = 0;
+    }
+
+    fn key_off(&mut self, index: usize) {
+        let has_envelope = self.instrument_envelope(index).is_some();
+        let channel = &mut self.channels[index];
+        channel.key_on = false;
+        // Without an envelope there's nothing to release, so the note stops.
+        if !has_envelope {
+            channel.volume = 0;
+        }
+    }
+
+    fn instrument_envelope(&self, index: usize) -> Option<&Envelope> {
+        let instrument = self.channels[index].instrument.checked_sub(1)?;
+        self.module
+            .data
+            .instruments
+            .get(instrument)?
+            .volume_envelope
+            .as_ref()
+    }
+
+    fn process_volume_column(&mut self, index: usize, first_tick: bool) {
+        let channel = &mut self.channels[index];
+        let value = channel.volume_column;
+        let (kind, amount) = (value >> 4, value & 0xf);
+
+        match (kind, first_tick) {
+            (0x1..=0x4, true) => channel.volume = (value - 0x10) as i32,
+            (0x5, true) if value == 0x50 => channel.volume = MAX_VOLUME,
+            (0x6, false) => channel.volume = (channel.volume - amount as i32).max(0),
+            (0x7, false) => channel.volume = (channel.volume + amount as i32).min(MAX_VOLUME),
+            (0x8, true) => channel.volume = (channel.volume - amount as i32).max(0),
+            (0x9, true) => channel.volume = (channel.volume + amount as i32).min(MAX_VOLUME),
+            (0xa, true) if amount > 0 => channel.vibrato_speed = amount * 4,
+            (0xb, true) if amount > 0 => channel.vibrato_depth = amount,
+            (0xb, false) => self.vibrato(index),
+            (0xc, true) => channel.panning = amount as i32 * 17,
+            (0xf, true) if amount > 0 => channel.tone_porta_speed = amount << 4,
+            (0xf, false) => self.tone_porta(index),
+            _ => {}
+        }
+    }
+
+    fn process_row_effect(&mut self, index: usize) {
+        let channel = &mut self.channels[index];
+        let (effect, param) = (channel.effect, channel.param);
+        let (x, y) = (param >> 4, param & 0xf);
+
+        match effect {
+            PORTA_UP if param > 0 => channel.porta_up = param,
+            PORTA_DOWN if param > 0 => channel.porta_down = param,
+            TONE_PORTA if param > 0 => channel.tone_porta_speed = param,
+            VIBRATO => {
+                if x > 0 {
+                    channel.vibrato_speed = x;
+                }
+                if y > 0 {
+                    channel.vibrato_depth = y;
+                }
+            }
+            TONE_PORTA_VOLUME_SLIDE | VIBRATO_VOLUME_SLIDE | VOLUME_SLIDE if param > 0 => {
+                channel.volume_slide = param
+            }
+            SET_PANNING => channel.panning = param as i32,
+            SET_VOLUME => channel.volume = (param as i32).min(MAX_VOLUME),
+            POSITION_JUMP => {
+                let row = self.jump.map_or(0, |(_, row)| row);
+                self.jump = Some((param as usize, row));
+            }
+            PATTERN_BREAK => {
+                // The row is stored as two decimal digits.
+                let row = x as usize * 10 + y as usize;
+                let order = self.jump.map_or(self.order + 1, |(order, _)| order);
+                self.jump = Some((order, row));
+            }
+            SET_SPEED if param > 0 => {
+                if param < 32 {
+                    self.speed = param as u32;
+                } else {
+                    self.tempo = param as u32;
+                }
+            }
+            SET_GLOBAL_VOLUME => self.global_volume = (param as i32).min(MAX_VOLUME),
+            GLOBAL_VOLUME_SLIDE if param > 0 => channel.global_volume_slide = param,
+            KEY_OFF if param == 0 => self.key_off(index),
+            EXTENDED => self.process_extended_row(index, x, y),
+            _ => {}
+        }
+    }
+
+    fn process_extended_row(&mut self, index: usize, command: u8, value: u8) {
+        let row = self.row;
+        let channel = &mut self.channels[index];
+        match command {
+            0x1 => channel.period = (channel.period - value as f32 * 4.0).max(1.0),
+            0x2 => channel.period += value as f32 * 4.0,
+            0x6 => {
+                if value == 0 {
+                    channel.loop_row = row;
+                } else {
+                    if channel.loop_count == 0 {
+                        channel.loop_count = value;
+                    } else {
+                        channel.loop_count -= 1;
+                    }
+                    if channel.loop_count > 0 {
+                        self.jump = Some((self.order, channel.loop_row));
+                    }
+                }
+            }
+            0xa => channel.volume = (channel.volume + value as i32).min(MAX_VOLUME),
+            0xb => channel.volume = (channel.volume - value as i32).max(0),
+            0xc if value == 0 => channel.volume = 0,
+            0xe if self.pattern_delay == 0 => self.pattern_delay = value as u32,
+            _ => {}
+        }
+    }
+
+    fn process_channel_tick(&mut self, index: usize, tick: u32) {
+        self.process_volume_column(index, false);
+
+        let channel = &mut self.channels[index];
+        let (effect, param) = (channel.effect, channel.param);
+        let (x, y) = (param >> 4, param & 0xf);
+
+        match effect {
+            ARPEGGIO if param > 0 => {
+                channel.arpeggio = match tick % 3 {
+                    0 => 0,
+                    1 => x as i32,
+                    _ => y as i32,
+                };
+            }
+            PORTA_UP => channel.period = (channel.period - channel.porta_up as f32 * 4.0).max(1.0),
+            PORTA_DOWN => channel.period += channel.porta_down as f32 * 4.0,
+            TONE_PORTA => self.tone_porta(index),
+            VIBRATO => self.vibrato(index),
+            TONE_PORTA_VOLUME_SLIDE => {
+                self.tone_porta(index);
+                self.volume_slide(index);
+            }
+            VIBRATO_VOLUME_SLIDE => {
+                self.vibrato(index);
+                self.volume_slide(index);
+            }
+            VOLUME_SLIDE => self.volume_slide(index),
+            GLOBAL_VOLUME_SLIDE => {
+                let slide = channel.global_volume_slide;
+                let (up, down) = ((slide >> 4) as i32, (slide & 0xf) as i32);
+                self.global_volume = if up > 0 {
+                    (self.global_volume + up).min(MAX_VOLUME)
+                } else {
+                    (self.global_volume - down).max(0)
+                };
+            }
+            KEY_OFF if param as u32 == tick => self.key_off(index),
+            EXTENDED => match x {
+                0x9 if tick.checked_rem(y as u32) == Some(0) => {
+                    channel.position = 0.0;
+                    channel.forward = true;
+                }
+                0xc if y as u32 == tick => channel.volume = 0,
+                0xd if y as u32 == tick => {
+                    if let Some(note) = channel.delayed.take() {
+                        self.trigger(index, note);
+                        self.process_volume_column(index, true);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn tone_porta(&mut self, index: usize) {
+        let channel = &mut self.channels[index];
+        let speed = channel.tone_porta_speed as f32 * 4.0;
+        if channel.period < channel.target_period {
+            channel.period = (channel.period + speed).min(channel.target_period);
+        } else if channel.period > channel.target_period {
+            channel.period = (channel.period - speed).max(channel.target_period);
+        }
+    }
+
+    fn vibrato(&mut self, index: usize) {
+        let channel = &mut self.channels[index];
+        let position = channel.vibrato_position & 63;
+        let mut delta = VIBRATO_TABLE[(position & 31) as usize];
+        if position >= 32 {
+            delta = -delta;
+        }
+        channel.vibrato_offset = (delta * channel.vibrato_depth as i32) as f32 / 32.0;
+        channel.vibrato_position = channel.vibrato_position.wrapping_add(channel.vibrato_speed);
+    }
+
+    fn volume_slide(&mut self, index: usize) {
+        let channel = &mut self.channels[index];
+        let (up, down) = (
+            (channel.volume_slide >> 4) as i32,
+            (channel.volume_slide & 0xf) as i32,
+        );
+        channel.volume = if up > 0 {
+            (channel.volume + up).min(MAX_VOLUME)
+        } else {
+            (channel.volume - down).max(0)
+        };
+    }
+
+    // Advances envelopes and works out the playback rate after this tick's effects.
+    fn update_channel(&mut self, index: usize) {
+        let fadeout = self.channels[index]
+            .instrument
+            .checked_sub(1)
+            .and_then(|instrument| self.module.data.instruments.get(instrument))
+            .map_or(0, |instrument| instrument.fadeout as i32);
+        let envelope = self.instrument_envelope(index).cloned();
+
+        let channel = &self.channels[index];
+        let frequency =
+            self.frequency_for(channel.period + channel.vibrato_offset, channel.arpeggio);
+        let channel = &mut self.channels[index];
+        channel.step = frequency as f64 / OUTPUT_RATE as f64;
+
+        if !channel.key_on {
+            channel.fade = (channel.fade - fadeout).max(0);
+        }
+        if let Some(envelope) = envelope {
+            let holding = channel.key_on
+                && envelope.sustain.map(|point| envelope.points[point].0)
+                    == Some(channel.envelope_tick);
+            if !holding {
+                channel.envelope_tick = channel.envelope_tick.saturating_add(1);
+                if let Some((start, end)) = envelope.loop_range {
+                    if channel.envelope_tick >= envelope.points[end].0 {
+                        channel.envelope_tick = envelope.points[start].0;
+                    }
+                }
+            }
+        }
+    }
+
+    fn envelope_level(&self, index: usize) -> f32 {
+        let envelope = match self.instrument_envelope(index) {
+            Some(envelope) => envelope,
+            None => return 1.0,
+        };
+        let tick = self.channels[index].envelope_tick;
+        let points = &envelope.points;
+
+        let next = points.iter().position(|&(x, _)| x > tick);
+        let value = match next {
+            Some(0) => points[0].1 as f32,
+            Some(next) => {
+                let (x0, y0) = points[next - 1];
+                let (x1, y1) = points[next];
+                let t = tick.saturating_sub(x0) as f32 / x1.saturating_sub(x0).max(1) as f32;
+                y0 as f32 + (y1 as f32 - y0 as f32) * t
+            }
+            None => points.last().map_or(64.0, |&(_, y)| y as f32),
+        };
+        value / 64.0
+    }
+
+    fn mix_frame(&mut self) -> (f32, f32) {
+        let mut left = 0.0;
+        let mut right = 0.0;
+        let global = self.global_volume as f32 / MAX_VOLUME as f32;
+
+        for index in 0..self.channels.len() {
+            if !self.channels[index].playing {
+                continue;
+            }
+            let muted = self.control.shared.muted[index].load(Ordering::Relaxed);
+            let envelope = self.envelope_level(index);
+            let value = match self.advance_sample(index) {
+                Some(value) => value,
+                None => continue,
+            };
+            if muted {
+                continue;
+            }
+
+            let channel = &self.channels[index];
+            let gain = channel.volume as f32 / MAX_VOLUME as f32 * envelope * channel.fade as f32
+                / FADE_MAX as f32
+                * global
+                * self.amplitude;
+            let pan = channel.panning.clamp(0, 255) as f32 / 255.0;
+            left += value * gain * (1.0 - pan).sqrt();
+            right += value * gain * pan.sqrt();
+        }
+
+        (left, right)
+    }
+
+    // Reads the channel's sample with linear interpolation and moves it along,
+    // following the sample's loop.
+    fn advance_sample(&mut self, index: usize) -> Option<f32> {
+        let data = self.module.data.clone();
+        let channel = &mut self.channels[index];
+        let (instrument, sample) = channel.sample?;
+        let sample = data.instruments.get(instrument)?.samples.get(sample)?;
+        let length = sample.data.len();
+        if length == 0 {
+            channel.playing = false;
+            return None;
+        }
+
+        let position = channel.position.max(0.0);
+        let index = (position as usize).min(length - 1);
+        let next = (index + 1).min(length - 1);
+        let fraction = (position - index as f64) as f32;
+        let value = sample.data[index] + (sample.data[next] - sample.data[index]) * fraction;
+
+        if channel.forward {
+            channel.position += channel.step;
+        } else {
+            channel.position -= channel.step;
+        }
+
+        let loop_start = sample.loop_start as f64;
+        let loop_end = (sample.loop_start + sample.loop_length) as f64;
+        match sample.loop_kind {
+            LoopKind::None => {
+                if channel.position >= length as f64 {
+                    channel.playing = false;
+                }
+            }
+            LoopKind::Forward => {
+                while channel.position >= loop_end {
+                    channel.position -= sample.loop_length as f64;
+                }
+            }
+            LoopKind::PingPong => {
+                if channel.forward && channel.position >= loop_end {
+                    channel.position = loop_end - (channel.position - loop_end);
+                    channel.forward = false;
+                } else if !channel.forward && channel.position < loop_start {
+                    channel.position = loop_start + (loop_start - channel.position);
+                    channel.forward = true;
+                }
+            }
+        }
+
+        Some(value)
+    }
+}
+
+impl Iterator for TrackerPlayer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(right) = self.pending_right.take() {
+            return Some(right);
+        }
+
+        if self.frames_until_tick <= 0.0 {
+            if self.finished {
+                self.control.shared.finished.store(true, Ordering::Relaxed);
+                return None;
+            }
+            self.process_tick();
+            self.frames_until_tick += self.frames_per_tick();
+        }
+        self.frames_until_tick -= 1.0;
+
+        let (left, right) = self.mix_frame();
+        self.pending_right = Some(right);
+        Some(left)
+    }
+}
+
+impl Source for TrackerPlayer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        OUTPUT_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,242 @@
+use super::*;
+
+const SAMPLE_COUNT: usize = 31;
+const ROWS: usize = 64;
+const SIGNATURE_OFFSET: usize = 1080;
+
+fn channels_for(signature: &[u8]) -> Option<usize> {
+    match signature {
+        b"M.K." | b"M!K!" | b"FLT4" | b"4CHN" => Some(4),
+        b"FLT8" | b"OKTA" | b"CD81" => Some(8),
+        [digit, b'C', b'H', b'N'] if digit.is_ascii_digit() => Some((digit - b'0') as usize),
+        [tens, ones, b'C', b'H'] if tens.is_ascii_digit() && ones.is_ascii_digit() => {
+            Some(((tens - b'0') * 10 + (ones - b'0')) as usize)
+        }
+        _ => None,
+    }
+}
+
+// ProTracker periods are converted to notes so both formats share the player.
+// Period 428 is C-2 in ProTracker, which lines up with XM's C-4.
+fn note_for_period(period: u16) -> u8 {
+    if period == 0 {
+        return 0;
+    }
+    let semitones = 12.0 * (428.0 / period as f32).log2();
+    (49.0 + semitones.round()).clamp(1.0, 96.0) as u8
+}
+
+pub(super) fn parse(bytes: &[u8]) -> Result<ModuleData> {
+    let signature = bytes
+        .get(SIGNATURE_OFFSET..SIGNATURE_OFFSET + 4)
+        .ok_or_else(|| invalid("file is too short to be a module"))?;
+    let channels = channels_for(signature)
+        .filter(|&channels| channels > 0)
+        .ok_or_else(|| invalid("unrecognized module format"))?;
+
+    let mut reader = Reader::new(bytes, 0);
+    let title = reader.string(20)?;
+
+    let mut headers = Vec::with_capacity(SAMPLE_COUNT);
+    for _ in 0..SAMPLE_COUNT {
+        reader.bytes(22)?;
+        let length = reader.u16_be()? as usize * 2;
+        // Finetune is a signed nibble in eighths of a semitone.
+        let finetune = ((reader.u8()? & 0x0f) << 4) as i8;
+        let volume = reader.u8()?.min(64);
+        let loop_start = reader.u16_be()? as usize * 2;
+        let loop_length = reader.u16_be()? as usize * 2;
+        headers.push((length, finetune, volume, loop_start, loop_length));
+    }
+
+    let song_length = (reader.u8()? as usize).clamp(1, 128);
+    let restart = reader.u8()? as usize;
+    let order_table = reader.bytes(128)?;
+    let orders: Vec<usize> = order_table[..song_length]
+        .iter()
+        .map(|&order| order as usize)
+        .collect();
+    let pattern_count = order_table
+        .iter()
+        .map(|&order| order as usize)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    reader.bytes(4)?;
+
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let mut notes = Vec::with_capacity(ROWS * channels);
+        for _ in 0..ROWS * channels {
+            let cell = reader.bytes(4)?;
+            let period = (((cell[0] & 0x0f) as u16) << 8) | cell[1] as u16;
+            notes.push(Note {
+                note: note_for_period(period),
+                instrument: (cell[0] & 0xf0) | (cell[2] >> 4),
+                volume: 0,
+                effect: cell[2] & 0x0f,
+                param: cell[3],
+            });
+        }
+        patterns.push(Pattern { rows: ROWS, notes });
+    }
+
+    let mut instruments = Vec::with_capacity(SAMPLE_COUNT);
+    for (length, finetune, volume, loop_start, loop_length) in headers {
+        // Some files are cut short, keep whatever sample data is there.
+        let available = bytes.len().saturating_sub(reader.position).min(length);
+        let data = reader
+            .bytes(available)?
+            .iter()
+            .map(|&byte| byte as i8 as f32 / 128.0)
+            .collect::<Vec<_>>();
+
+        let loops = loop_length > 2 && loop_start + loop_length <= data.len();
+        instruments.push(Instrument {
+            samples: vec![SampleData {
+                data,
+                loop_kind: if loops {
+                    LoopKind::Forward
+                } else {
+                    LoopKind::None
+                },
+                loop_start,
+                loop_length,
+                volume,
+                finetune,
+                relative_note: 0,
+                panning: None,
+            }],
+            sample_map: [0; 96],
+            volume_envelope: None,
+            fadeout: 0,
+        });
+    }
+
+    // Amiga channels alternate left, right, right, left. Hard panning is
+    // tiring on headphones so keep some of each side in the other.
+    let panning = (0..channels)
+        .map(|channel| {
+            if channel % 4 == 0 || channel % 4 == 3 {
+                64
+            } else {
+                192
+            }
+        })
+        .collect();
+
+    Ok(ModuleData {
+        title,
+        channels,
+        orders,
+        restart: if restart < song_length { restart } else { 0 },
+        patterns,
+        instruments,
+        speed: 6,
+        tempo: 125,
+        frequency_mode: FrequencyMode::Amiga,
+        panning,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A module with one pattern and `samples` as the first sample's data.
+    fn module(signature: &[u8; 4], channels: usize, samples: &[i8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"test song\0\0\0\0\0\0\0\0\0\0\0");
+        for sample in 0..SAMPLE_COUNT {
+            bytes.extend_from_slice(&[0; 22]);
+            let words = if sample == 0 { samples.len() / 2 } else { 0 };
+            bytes.extend_from_slice(&(words as u16).to_be_bytes());
+            bytes.extend_from_slice(&[0x0f, 80]);
+            // Loops over the second half of the first sample.
+            bytes.extend_from_slice(&((words / 2) as u16).to_be_bytes());
+            bytes.extend_from_slice(&((words / 2) as u16).to_be_bytes());
+        }
+        bytes.extend_from_slice(&[1, 5]);
+        bytes.extend_from_slice(&[0; 128]);
+        bytes.extend_from_slice(signature);
+
+        let mut pattern = vec![0; ROWS * channels * 4];
+        // Instrument 1 playing period 428 with effect C20 on the first cell.
+        pattern[..4].copy_from_slice(&[0x01, 0xac, 0x1c, 0x20]);
+        bytes.extend_from_slice(&pattern);
+        bytes.extend(samples.iter().map(|&sample| sample as u8));
+        bytes
+    }
+
+    #[test]
+    fn parses_header_pattern_and_samples() {
+        let data = parse(&module(b"M.K.", 4, &[0, 64, -64, 127])).unwrap();
+        assert_eq!(data.title, "test song");
+        assert_eq!(data.channels, 4);
+        assert_eq!(data.orders, vec![0]);
+        // The restart position is past the end of the song.
+        assert_eq!(data.restart, 0);
+        assert_eq!(data.panning, vec![64, 192, 192, 64]);
+
+        let note = data.patterns[0].notes[0];
+        assert_eq!(
+            (note.note, note.instrument, note.effect, note.param),
+            (49, 1, 0x0c, 0x20)
+        );
+
+        let sample = &data.instruments[0].samples[0];
+        assert_eq!(sample.data, vec![0.0, 0.5, -0.5, 127.0 / 128.0]);
+        assert_eq!(sample.volume, 64);
+        assert_eq!(sample.finetune, -16);
+        // Loops of a single word don't count as loops.
+        assert_eq!(sample.loop_kind, LoopKind::None);
+        assert_eq!(data.instruments.len(), SAMPLE_COUNT);
+    }
+
+    #[test]
+    fn loops_that_fit_the_sample() {
+        let data = parse(&module(b"M.K.", 4, &[0; 16])).unwrap();
+        let sample = &data.instruments[0].samples[0];
+        assert_eq!(sample.loop_kind, LoopKind::Forward);
+        assert_eq!((sample.loop_start, sample.loop_length), (8, 8));
+    }
+
+    #[test]
+    fn reads_channel_counts_from_the_signature() {
+        assert_eq!(channels_for(b"FLT8"), Some(8));
+        assert_eq!(channels_for(b"6CHN"), Some(6));
+        assert_eq!(channels_for(b"12CH"), Some(12));
+        assert_eq!(channels_for(b"XXXX"), None);
+
+        assert_eq!(parse(&module(b"6CHN", 6, &[])).unwrap().channels, 6);
+        assert!(parse(&module(b"0CHN", 4, &[])).is_err());
+        assert!(parse(&module(b"ABCD", 4, &[])).is_err());
+    }
+
+    #[test]
+    fn converts_periods_to_notes() {
+        assert_eq!(note_for_period(0), 0);
+        assert_eq!(note_for_period(428), 49);
+        assert_eq!(note_for_period(214), 61);
+        assert_eq!(note_for_period(856), 37);
+        // Out of range periods clamp to the notes there are.
+        assert_eq!(note_for_period(1), 96);
+        assert_eq!(note_for_period(4095), 10);
+    }
+
+    #[test]
+    fn keeps_what_there_is_of_truncated_samples() {
+        let mut bytes = module(b"M.K.", 4, &[1; 8]);
+        bytes.truncate(bytes.len() - 3);
+        let data = parse(&bytes).unwrap();
+        assert_eq!(data.instruments[0].samples[0].data.len(), 5);
+    }
+
+    #[test]
+    fn rejects_truncated_headers_and_patterns() {
+        let bytes = module(b"M.K.", 4, &[]);
+        for length in 0..bytes.len() {
+            assert!(parse(&bytes[..length]).is_err(), "length {}", length);
+        }
+    }
+}
@@ -0,0 +1,464 @@
+use super::*;
+
+pub(super) const SIGNATURE: &[u8] = b"Extended Module: ";
+const HEADER_OFFSET: usize = 60;
+// The fields of a pattern header that are read.
+const PATTERN_HEADER_SIZE: usize = 9;
+const MAX_ROWS: usize = 256;
+
+fn parse_envelope(
+    points: &[u8],
+    count: u8,
+    sustain: u8,
+    loop_start: u8,
+    loop_end: u8,
+    flags: u8,
+) -> Option<Envelope> {
+    if flags & 1 == 0 || count == 0 {
+        return None;
+    }
+
+    let count = (count as usize).min(12);
+    let points = points
+        .chunks(4)
+        .take(count)
+        .map(|point| {
+            (
+                u16::from_le_bytes([point[0], point[1]]),
+                u16::from_le_bytes([point[2], point[3]]).min(64),
+            )
+        })
+        .collect();
+    let in_range = |point: u8| (point as usize) < count;
+
+    Some(Envelope {
+        points,
+        sustain: if flags & 2 != 0 && in_range(sustain) {
+            Some(sustain as usize)
+        } else {
+            None
+        },
+        loop_range: if flags & 4 != 0 && in_range(loop_start) && in_range(loop_end) {
+            Some((loop_start as usize, loop_end as usize))
+        } else {
+            None
+        },
+    })
+}
+
+fn parse_pattern(reader: &mut Reader, channels: usize) -> Result<Pattern> {
+    let start = reader.position;
+    let header_length = reader.u32_le()? as usize;
+    reader.u8()?;
+    let rows = (reader.u16_le()? as usize).max(1);
+    let packed_length = reader.u16_le()? as usize;
+    if header_length < PATTERN_HEADER_SIZE {
+        return Err(invalid("pattern header too short"));
+    }
+    if rows > MAX_ROWS {
+        return Err(invalid("too many rows in pattern"));
+    }
+    reader.position = start + header_length;
+
+    // Empty patterns keep no notes, since missing notes play as empty ones.
+    if packed_length == 0 {
+        return Ok(Pattern {
+            rows,
+            notes: Vec::new(),
+        });
+    }
+
+    let packed = reader.bytes(packed_length)?;
+    let mut notes = vec![Note::default(); rows * channels];
+    let mut cursor = Reader::new(packed, 0);
+    for note in notes.iter_mut() {
+        if cursor.position >= packed.len() {
+            break;
+        }
+
+        let first = cursor.u8()?;
+        // The high bit marks a compressed note where the low bits say which
+        // fields follow. Otherwise the byte is the note and every field follows.
+        let flags = if first & 0x80 != 0 { first } else { 0x1f };
+        if first & 0x80 == 0 {
+            note.note = first;
+        } else if flags & 0x01 != 0 {
+            note.note = cursor.u8()?;
+        }
+        if flags & 0x02 != 0 {
+            note.instrument = cursor.u8()?;
+        }
+        if flags & 0x04 != 0 {
+            note.volume = cursor.u8()?;
+        }
+        if flags & 0x08 != 0 {
+            note.effect = cursor.u8()?;
+        }
+        if flags & 0x10 != 0 {
+            note.param = cursor.u8()?;
+        }
+    }
+
+    Ok(Pattern { rows, notes })
+}
+
+fn decode_samples(bytes: &[u8], sixteen_bit: bool) -> Vec<f32> {
+    // Samples are stored as deltas from the previous sample.
+    if sixteen_bit {
+        let mut value = 0i16;
+        bytes
+            .chunks_exact(2)
+            .map(|pair| {
+                value = value.wrapping_add(i16::from_le_bytes([pair[0], pair[1]]));
+                value as f32 / 32768.0
+            })
+            .collect()
+    } else {
+        let mut value = 0i8;
+        bytes
+            .iter()
+            .map(|&byte| {
+                value = value.wrapping_add(byte as i8);
+                value as f32 / 128.0
+            })
+            .collect()
+    }
+}
+
+fn parse_instrument(reader: &mut Reader) -> Result<Instrument> {
+    let start = reader.position;
+    let header_size = reader.u32_le()? as usize;
+    reader.bytes(22)?;
+    reader.u8()?;
+    let sample_count = reader.u16_le()? as usize;
+
+    let mut instrument = Instrument {
+        samples: Vec::new(),
+        sample_map: [0; 96],
+        volume_envelope: None,
+        fadeout: 0,
+    };
+    if sample_count == 0 {
+        reader.position = start + header_size;
+        return Ok(instrument);
+    }
+
+    let sample_header_size = reader.u32_le()? as usize;
+    instrument.sample_map.copy_from_slice(reader.bytes(96)?);
+    let volume_points = reader.bytes(48)?;
+    reader.bytes(48)?;
+    let volume_count = reader.u8()?;
+    reader.u8()?;
+    let volume_sustain = reader.u8()?;
+    let volume_loop_start = reader.u8()?;
+    let volume_loop_end = reader.u8()?;
+    reader.bytes(3)?;
+    let volume_flags = reader.u8()?;
+    reader.u8()?;
+    reader.bytes(4)?;
+    instrument.fadeout = reader.u16_le()?;
+    instrument.volume_envelope = parse_envelope(
+        volume_points,
+        volume_count,
+        volume_sustain,
+        volume_loop_start,
+        volume_loop_end,
+        volume_flags,
+    );
+    reader.position = start + header_size;
+
+    let mut headers = Vec::with_capacity(sample_count);
+    for _ in 0..sample_count {
+        let header_start = reader.position;
+        let length = reader.u32_le()? as usize;
+        let loop_start = reader.u32_le()? as usize;
+        let loop_length = reader.u32_le()? as usize;
+        let volume = reader.u8()?.min(64);
+        let finetune = reader.u8()? as i8;
+        let kind = reader.u8()?;
+        let panning = reader.u8()?;
+        let relative_note = reader.u8()? as i8;
+        reader.position = header_start + sample_header_size;
+        headers.push((
+            length,
+            loop_start,
+            loop_length,
+            volume,
+            finetune,
+            kind,
+            panning,
+            relative_note,
+        ));
+    }
+
+    for (length, loop_start, loop_length, volume, finetune, kind, panning, relative_note) in headers
+    {
+        let sixteen_bit = kind & 0x10 != 0;
+        let data = decode_samples(reader.bytes(length)?, sixteen_bit);
+        let width = if sixteen_bit { 2 } else { 1 };
+        let (loop_start, loop_length) = (loop_start / width, loop_length / width);
+
+        let loop_kind = match kind & 0x03 {
+            _ if loop_length == 0 || loop_start + loop_length > data.len() => LoopKind::None,
+            1 => LoopKind::Forward,
+            2 => LoopKind::PingPong,
+            _ => LoopKind::None,
+        };
+        instrument.samples.push(SampleData {
+            data,
+            loop_kind,
+            loop_start,
+            loop_length,
+            volume,
+            finetune,
+            relative_note,
+            panning: Some(panning),
+        });
+    }
+
+    Ok(instrument)
+}
+
+pub(super) fn parse(bytes: &[u8]) -> Result<ModuleData> {
+    let mut reader = Reader::new(bytes, SIGNATURE.len());
+    let title = reader.string(20)?;
+
+    reader.position = HEADER_OFFSET;
+    let header_size = reader.u32_le()? as usize;
+    let song_length = (reader.u16_le()? as usize).clamp(1, 256);
+    let restart = reader.u16_le()? as usize;
+    let channels = reader.u16_le()? as usize;
+    let pattern_count = reader.u16_le()? as usize;
+    let instrument_count = reader.u16_le()? as usize;
+    let flags = reader.u16_le()?;
+    let speed = reader.u16_le()? as u32;
+    let tempo = reader.u16_le()? as u32;
+    let orders = reader.bytes(256)?[..song_length]
+        .iter()
+        .map(|&order| order as usize)
+        .collect();
+    if channels == 0 || channels > 64 {
+        return Err(invalid("unsupported channel count"));
+    }
+
+    reader.position = HEADER_OFFSET + header_size;
+    if pattern_count * PATTERN_HEADER_SIZE > reader.remaining() {
+        return Err(invalid("more patterns than the file holds"));
+    }
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        patterns.push(parse_pattern(&mut reader, channels)?);
+    }
+
+    let mut instruments = Vec::with_capacity(instrument_count);
+    for _ in 0..instrument_count {
+        instruments.push(parse_instrument(&mut reader)?);
+    }
+
+    Ok(ModuleData {
+        title,
+        channels,
+        orders,
+        restart: if restart < song_length { restart } else { 0 },
+        patterns,
+        instruments,
+        speed: speed.max(1),
+        tempo: tempo.max(32),
+        frequency_mode: if flags & 1 != 0 {
+            FrequencyMode::Linear
+        } else {
+            FrequencyMode::Amiga
+        },
+        panning: vec![128; channels],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSTRUMENT_HEADER_SIZE: usize = 263;
+    const SAMPLE_HEADER_SIZE: usize = 40;
+
+    fn header(channels: u16, patterns: u16, instruments: u16) -> Vec<u8> {
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend_from_slice(b"xm song\0\0\0\0\0\0\0\0\0\0\0\0\0");
+        bytes.push(0x1a);
+        bytes.extend_from_slice(&[b' '; 20]);
+        bytes.extend_from_slice(&[0x04, 0x01]);
+        assert_eq!(bytes.len(), HEADER_OFFSET);
+
+        bytes.extend_from_slice(&276u32.to_le_bytes());
+        for value in &[2, 1, channels, patterns, instruments, 1, 3, 140] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let mut orders = [0; 256];
+        orders[1] = 1;
+        bytes.extend_from_slice(&orders);
+        bytes
+    }
+
+    fn pattern(rows: u16, packed: &[u8]) -> Vec<u8> {
+        let mut bytes = 9u32.to_le_bytes().to_vec();
+        bytes.push(0);
+        bytes.extend_from_slice(&rows.to_le_bytes());
+        bytes.extend_from_slice(&(packed.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(packed);
+        bytes
+    }
+
+    // An instrument with one sample, `kind` giving its loop and bit depth.
+    fn instrument(kind: u8, data: &[u8], loop_start: u32, loop_length: u32) -> Vec<u8> {
+        let mut bytes = (INSTRUMENT_HEADER_SIZE as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 23]);
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&(SAMPLE_HEADER_SIZE as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 96]);
+
+        // Two volume envelope points with the sustain point in range and
+        // the loop end out of range.
+        let mut points = [0; 48];
+        points[..8].copy_from_slice(&[0, 0, 64, 0, 10, 0, 80, 0]);
+        bytes.extend_from_slice(&points);
+        bytes.extend_from_slice(&[0; 48]);
+        bytes.extend_from_slice(&[2, 0, 1, 0, 5, 0, 0, 0, 0b111, 0]);
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&256u16.to_le_bytes());
+        bytes.resize(INSTRUMENT_HEADER_SIZE, 0);
+
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&loop_start.to_le_bytes());
+        bytes.extend_from_slice(&loop_length.to_le_bytes());
+        bytes.extend_from_slice(&[100, 0xf0, kind, 200, 12, 0]);
+        bytes.extend_from_slice(&[0; 22]);
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    fn module() -> Vec<u8> {
+        let mut bytes = header(2, 2, 1);
+        // A full note on channel 0, then a packed note with only an
+        // instrument and effect on channel 1, then an empty packed note.
+        bytes.extend(pattern(
+            2,
+            &[49, 1, 0x40, 0x0f, 0x06, 0x8a, 0x01, 0x0c, 0x80],
+        ));
+        bytes.extend(pattern(64, &[]));
+        // 16 bit deltas 100, 100, -300 with a ping pong loop over the last two.
+        bytes.extend(instrument(0x12, &[100, 0, 100, 0, 0xd4, 0xfe], 2, 4));
+        bytes
+    }
+
+    #[test]
+    fn parses_header_and_orders() {
+        let data = parse(&module()).unwrap();
+        assert_eq!(data.title, "xm song");
+        assert_eq!(data.channels, 2);
+        assert_eq!(data.orders, vec![0, 1]);
+        assert_eq!(data.restart, 1);
+        assert_eq!((data.speed, data.tempo), (3, 140));
+        assert_eq!(data.frequency_mode, FrequencyMode::Linear);
+        assert_eq!(data.panning, vec![128, 128]);
+    }
+
+    #[test]
+    fn unpacks_notes() {
+        let data = parse(&module()).unwrap();
+        let notes = &data.patterns[0].notes;
+        assert_eq!(notes.len(), 4);
+        assert_eq!(
+            (notes[0].note, notes[0].instrument, notes[0].volume),
+            (49, 1, 0x40)
+        );
+        assert_eq!((notes[0].effect, notes[0].param), (0x0f, 0x06));
+        assert_eq!(
+            (notes[1].note, notes[1].instrument, notes[1].effect),
+            (0, 1, 0x0c)
+        );
+        assert_eq!(notes[1].volume, 0);
+        assert_eq!(notes[2].note, 0);
+
+        // Empty patterns don't store their empty notes.
+        assert_eq!(data.patterns[1].rows, 64);
+        assert!(data.patterns[1].notes.is_empty());
+    }
+
+    #[test]
+    fn decodes_delta_samples() {
+        let data = parse(&module()).unwrap();
+        let sample = &data.instruments[0].samples[0];
+        assert_eq!(
+            sample.data,
+            vec![100.0 / 32768.0, 200.0 / 32768.0, -100.0 / 32768.0]
+        );
+        // Loop points are in bytes and come out in samples.
+        assert_eq!(sample.loop_kind, LoopKind::PingPong);
+        assert_eq!((sample.loop_start, sample.loop_length), (1, 2));
+        assert_eq!((sample.volume, sample.finetune), (64, -16));
+        assert_eq!((sample.relative_note, sample.panning), (12, Some(200)));
+
+        assert_eq!(
+            decode_samples(&[10, 246, 128], false),
+            vec![10.0 / 128.0, 0.0, -1.0]
+        );
+    }
+
+    #[test]
+    fn drops_loops_past_the_sample() {
+        let mut bytes = header(2, 0, 1);
+        bytes.extend(instrument(0x01, &[0; 4], 2, 4));
+        let data = parse(&bytes).unwrap();
+        assert_eq!(data.instruments[0].samples[0].loop_kind, LoopKind::None);
+    }
+
+    #[test]
+    fn keeps_envelope_points_in_range() {
+        let data = parse(&module()).unwrap();
+        let envelope = data.instruments[0].volume_envelope.as_ref().unwrap();
+        assert_eq!(envelope.points, vec![(0, 64), (10, 64)]);
+        assert_eq!(envelope.sustain, Some(1));
+        assert_eq!(envelope.loop_range, None);
+        assert_eq!(data.instruments[0].fadeout, 256);
+    }
+
+    #[test]
+    fn rejects_bad_channel_counts() {
+        assert!(parse(&header(0, 0, 0)).is_err());
+        assert!(parse(&header(65, 0, 0)).is_err());
+        assert!(parse(&header(64, 0, 0)).is_ok());
+    }
+
+    #[test]
+    fn rejects_hostile_pattern_headers() {
+        // A zero header length would make every pattern read the same bytes.
+        let mut bytes = header(64, 1, 0);
+        let mut empty = pattern(256, &[]);
+        empty[..4].copy_from_slice(&0u32.to_le_bytes());
+        bytes.extend(&empty);
+        assert!(parse(&bytes).is_err());
+
+        // Far more patterns than there are bytes for.
+        let mut bytes = header(64, u16::MAX, 0);
+        bytes.extend(pattern(256, &[]));
+        assert!(parse(&bytes).is_err());
+
+        let mut bytes = header(64, 1, 0);
+        bytes.extend(pattern(u16::MAX, &[0x80]));
+        assert!(parse(&bytes).is_err());
+
+        // A packed length past the end of the file.
+        let mut bytes = header(64, 1, 0);
+        let mut truncated = pattern(256, &[0x80; 4]);
+        truncated[7..9].copy_from_slice(&u16::MAX.to_le_bytes());
+        bytes.extend(&truncated);
+        assert!(parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_modules() {
+        let bytes = module();
+        for length in SIGNATURE.len()..bytes.len() {
+            assert!(parse(&bytes[..length]).is_err(), "length {}", length);
+        }
+    }
+}
@@ -1,3 +1,72 @@
-pub fn size_of_slice<T: Sized>(slice: &[T]) -> usize {
-    std::mem::size_of::<T>() * slice.len()
+// Copies `data` into `target` at `offset` through the staging belt. The copy
+// is recorded in `encoder`, ahead of whatever's recorded after it.
+pub fn write_buffer(
+    staging_belt: &mut wgpu::util::StagingBelt,
+    encoder: &mut wgpu::CommandEncoder,
+    device: &wgpu::Device,
+    target: &wgpu::Buffer,
+    offset: wgpu::BufferAddress,
+    data: &[u8],
+) {
+    if let Some(size) = wgpu::BufferSize::new(data.len() as wgpu::BufferAddress) {
+        staging_belt
+            .write_buffer(encoder, target, offset, size, device)
+            .copy_from_slice(data);
+    }
+}
+
+// A vertex or index buffer that's rewritten through the staging belt every
+// frame. It's only replaced when a frame's data doesn't fit, and then grows to
+// the next power of two, so it stops allocating once the data levels off.
+pub(crate) struct GrowableBuffer {
+    buffer: wgpu::Buffer,
+    label: &'static str,
+    usage: wgpu::BufferUsage,
+    // In bytes. Never shrinks.
+    capacity: wgpu::BufferAddress,
+}
+
+impl GrowableBuffer {
+    pub fn new(device: &wgpu::Device, label: &'static str, usage: wgpu::BufferUsage) -> Self {
+        let usage = usage | wgpu::BufferUsage::COPY_DST;
+        Self {
+            buffer: Self::create_buffer(device, label, usage, 0),
+            label,
+            usage,
+            capacity: 0,
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &'static str,
+        usage: wgpu::BufferUsage,
+        size: wgpu::BufferAddress,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn write(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging_belt: &mut wgpu::util::StagingBelt,
+        data: &[u8],
+    ) {
+        let size = data.len() as wgpu::BufferAddress;
+        if size > self.capacity {
+            self.capacity = size.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.label, self.usage, self.capacity);
+        }
+        write_buffer(staging_belt, encoder, device, &self.buffer, 0, data);
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
 }